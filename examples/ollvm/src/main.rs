@@ -1,4 +1,4 @@
-use radius2::{Radius, State, vc};
+use radius2::{Radius, vc};
 use std::io::{self, Write};
 
 // output: 
@@ -14,17 +14,17 @@ const HASHES:[u64; 8] = [
 
 fn main() {
     let mut radius = Radius::new("tests/ollvm");
-    fn flag_hook(state: &mut State) -> bool {
-        state.registers.set("rax", state.context["flag"][0].clone()); 
-        true
-    }
-    radius.hook(0x00400899, flag_hook);
-
     let main = radius.get_address("main").unwrap();
     let mut state = radius.call_state(main);
     radius.set_argv_env(&mut state, &[vc(0x58), vc(0x31)], &[]);
     let flag = state.symbolic_value("flag", 64);
-    state.context.insert("flag".to_owned(), vec!(flag.clone()));
+
+    // the hook captures the symbolic flag directly
+    let hook_flag = flag.clone();
+    radius.hook(0x00400899, move |state| {
+        state.registers.set("rax", hook_flag.clone());
+        true
+    });
 
     let mut end_state = radius.run(state, 1).unwrap();
     let rcx = end_state.registers.get("rcx");
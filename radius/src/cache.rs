@@ -0,0 +1,141 @@
+use crate::operations::Operations;
+use crate::processor::Word;
+use crate::value::Value;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// An ESIL token that can be saved, literals must be concrete
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Token {
+    Literal(u64),
+    Register(usize),
+    Operator(Operations),
+    Unknown(String),
+}
+
+/// Results of r2 queries and tokenized ESIL saved between runs
+///
+/// Each binary gets its own file in the cache directory, named after a hash
+/// of the binary, its loaded libraries, the r2 version, r2 arguments and any
+/// options set later with `R2Api::set_option`, so changing any of them starts
+/// a new cache instead of reusing a stale one. Options changed by running an
+/// `e` command directly are not part of the key and need `--no-cache`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskCache {
+    /// Output of r2 commands such as disassembly, calling conventions,
+    /// basic blocks, imports and segments
+    pub commands: HashMap<String, String>,
+    /// Tokens of each ESIL expression
+    pub tokens: HashMap<String, Vec<Token>>,
+    #[serde(skip)]
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub key: u64,
+    #[serde(skip)]
+    pub dirty: bool, // something was added since it was loaded
+}
+
+impl DiskCache {
+    /// Load the cache with `key` from `dir`, or start an empty one
+    pub fn load(dir: &Path, key: u64) -> Self {
+        let path = dir.join(format!("{:016x}.json", key));
+        let mut cache: DiskCache = fs::File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default();
+
+        cache.path = path;
+        cache.key = key;
+        cache
+    }
+
+    /// Write the cache if anything was added. It is written to a temporary
+    /// file first so concurrent runs never read a partial cache
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp = self.path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        serde_json::to_writer(&mut file, self)?;
+        file.flush()?;
+        drop(file);
+
+        fs::rename(&tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn get_command(&self, cmd: &str) -> Option<String> {
+        self.commands.get(cmd).cloned()
+    }
+
+    pub fn add_command(&mut self, cmd: &str, result: &str) {
+        self.commands.insert(cmd.to_owned(), result.to_owned());
+        self.dirty = true;
+    }
+
+    /// The cached tokens of `esil` as words
+    pub fn get_tokens(&self, esil: &str) -> Option<Vec<Word>> {
+        let tokens = self.tokens.get(esil)?;
+        Some(
+            tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Literal(val) => Word::Literal(Value::Concrete(*val, 0)),
+                    Token::Register(index) => Word::Register(*index),
+                    Token::Operator(op) => Word::Operator(op.to_owned()),
+                    Token::Unknown(s) => Word::Unknown(s.to_owned()),
+                })
+                .collect(),
+        )
+    }
+
+    /// Save the tokens of `esil`, unless a literal is symbolic or tainted
+    pub fn add_tokens(&mut self, esil: &str, words: &[Word]) {
+        let tokens: Option<Vec<Token>> = words
+            .iter()
+            .map(|word| match word {
+                Word::Literal(Value::Concrete(val, 0)) => Some(Token::Literal(*val)),
+                Word::Literal(_) => None,
+                Word::Register(index) => Some(Token::Register(*index)),
+                Word::Operator(op) => Some(Token::Operator(op.to_owned())),
+                Word::Unknown(s) => Some(Token::Unknown(s.to_owned())),
+            })
+            .collect();
+
+        if let Some(tokens) = tokens {
+            self.tokens.insert(esil.to_owned(), tokens);
+            self.dirty = true;
+        }
+    }
+}
+
+/// Continue an FNV-1a hash of `data`, start with `HASH_START`. Unlike the
+/// std hashers the result never changes between builds
+pub fn hash(mut hash: u64, data: &[u8]) -> u64 {
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub const HASH_START: u64 = 0xcbf29ce484222325;
+
+/// `$XDG_CACHE_HOME/radius2`, or `~/.cache/radius2`
+pub fn default_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("radius2"))
+}
@@ -0,0 +1,104 @@
+use crate::state::State;
+use crate::value::{vc, Value};
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A branch taken during a concolic run
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub pc: u64,
+    pub target: u64,
+    /// The condition under which `target` is taken
+    pub condition: Value,
+}
+
+/// Generational search state for `Processor::concolic`
+///
+/// Each seed assigns concrete bytes to every symbolic input. A seed is run
+/// along its concrete path and every branch after its bound is negated to
+/// generate a child seed, which is only allowed to negate later branches
+#[derive(Debug, Clone)]
+pub struct Concolic {
+    pub inputs: Vec<Value>,
+    pub queue: VecDeque<(Vec<Vec<u8>>, usize)>, // seeds and their bounds
+    pub seen: HashSet<Vec<Vec<u8>>>,
+    pub generated: Vec<Vec<Vec<u8>>>,
+    pub edges: HashSet<(u64, u64)>, // (pc, target) edges taken by any run
+    pub runs: usize,
+    pub max_runs: usize,  // maximum number of concrete runs
+    pub max_steps: usize, // maximum instructions executed in each run
+}
+
+impl Concolic {
+    pub fn new(inputs: Vec<Value>) -> Self {
+        Concolic {
+            inputs,
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+            generated: vec![],
+            edges: HashSet::new(),
+            runs: 0,
+            max_runs: 256,
+            max_steps: 1_000_000,
+        }
+    }
+
+    /// Add a seed with the concrete bytes of each input, missing
+    /// bytes are zero and extra bytes are ignored
+    pub fn add_seed(&mut self, seed: &[Vec<u8>]) {
+        let seed: Vec<Vec<u8>> = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let mut bytes = seed.get(i).cloned().unwrap_or_default();
+                bytes.resize(input.size() as usize / 8, 0);
+                bytes
+            })
+            .collect();
+
+        if self.seen.insert(seed.clone()) {
+            self.queue.push_back((seed, 0));
+        }
+    }
+
+    /// Assertions binding each input to its bytes in `seed`
+    pub fn bind(&self, state: &mut State, seed: &[Vec<u8>]) -> Vec<Value> {
+        self.inputs
+            .iter()
+            .zip(seed)
+            .map(|(input, bytes)| {
+                let bytes: Vec<Value> = bytes.iter().map(|b| vc(*b as u64)).collect();
+                let value = state.memory.pack(&bytes);
+                state.translate_value(input).eq(&value)
+            })
+            .collect()
+    }
+
+    /// Solve the inputs for `state`, `None` if it is unsat
+    pub fn solve(&self, state: &mut State) -> Option<Vec<Vec<u8>>> {
+        let mut seed = vec![];
+        for input in &self.inputs {
+            let value = state.translate_value(input);
+            let bv = state.solver.to_bv(&value, value.size());
+            seed.push(state.evaluate_bytes_bv(&bv)?);
+        }
+        Some(seed)
+    }
+
+    /// Write each generated input to `dir` as `<name>_NNNNNN`,
+    /// using the names of the inputs in the same order
+    pub fn write(&self, dir: &str, names: &[&str]) -> io::Result<usize> {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir)?;
+        for (id, seed) in self.generated.iter().enumerate() {
+            for (name, bytes) in names.iter().zip(seed) {
+                fs::write(dir.join(format!("{}_{:06}", name, id + 1)), bytes)?;
+            }
+        }
+        Ok(self.generated.len())
+    }
+}
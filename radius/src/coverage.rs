@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+
+/// A loaded binary or library that coverage is reported relative to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub path: String,
+    pub base: u64,
+    pub end: u64,
+}
+
+/// Instruction and basic block coverage aggregated over every state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    /// Executed instruction addresses and their sizes
+    pub instructions: BTreeMap<u64, u64>,
+    /// Executed basic block addresses and their sizes
+    pub blocks: BTreeMap<u64, u64>,
+    pub modules: Vec<Module>,
+}
+
+impl Coverage {
+    /// Index of the module containing `addr`
+    pub fn module_index(&self, addr: u64) -> Option<usize> {
+        self.modules
+            .iter()
+            .position(|m| addr >= m.base && addr < m.end)
+    }
+
+    /// One executed instruction address per line
+    pub fn addresses(&self) -> String {
+        self.instructions
+            .keys()
+            .map(|addr| format!("0x{:x}\n", addr))
+            .collect()
+    }
+
+    /// An r2 script that colors the executed blocks and instructions
+    pub fn r2_script(&self) -> String {
+        let mut script = String::new();
+        for addr in self.blocks.keys() {
+            script += &format!("afbc green @ 0x{:x}\n", addr);
+        }
+        for addr in self.instructions.keys() {
+            script += &format!("ecHi green @ 0x{:x}\n", addr);
+        }
+        script
+    }
+
+    /// drcov version 2 log as read by lighthouse and cutter, blocks
+    /// outside of every module are not included
+    pub fn drcov(&self) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend(b"DRCOV VERSION: 2\nDRCOV FLAVOR: radius2\n");
+        out.extend(
+            format!(
+                "Module Table: version 2, count {}\n\
+                 Columns: id, base, end, entry, checksum, timestamp, path\n",
+                self.modules.len()
+            )
+            .bytes(),
+        );
+        for (id, module) in self.modules.iter().enumerate() {
+            out.extend(
+                format!(
+                    "{:2}, 0x{:x}, 0x{:x}, 0x0000000000000000, 0x00000000, 0x00000000, {}\n",
+                    id, module.base, module.end, module.path
+                )
+                .bytes(),
+            );
+        }
+
+        let mut bbs = vec![];
+        for (addr, size) in &self.blocks {
+            if let Some(id) = self.module_index(*addr) {
+                let offset = (addr - self.modules[id].base) as u32;
+                bbs.extend(offset.to_le_bytes());
+                bbs.extend((*size.min(&0xffff) as u16).to_le_bytes());
+                bbs.extend((id as u16).to_le_bytes());
+            }
+        }
+        out.extend(format!("BB Table: {} bbs\n", bbs.len() / 8).bytes());
+        out.extend(bbs);
+        out
+    }
+
+    /// Write the coverage to `path`, as an r2 script if it ends with `.r2`,
+    /// an address list if it ends with `.txt` and drcov otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let data = if path.ends_with(".r2") {
+            self.r2_script().into_bytes()
+        } else if path.ends_with(".txt") {
+            self.addresses().into_bytes()
+        } else {
+            self.drcov()
+        };
+        fs::File::create(path)?.write_all(&data)
+    }
+}
@@ -0,0 +1,438 @@
+use crate::operations::{genmask, Operations};
+use crate::processor::Word;
+use crate::state::{ExecMode, State};
+use crate::value::{vc, Value};
+
+/// An ESIL word decoded for the concrete interpreter
+#[derive(Debug, Clone, PartialEq)]
+pub enum FastWord {
+    Literal(u64),
+    Register(usize),
+    Operator(Operations),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Item {
+    Literal(u64),
+    Register(usize),
+}
+
+// previous values to restore if the instruction turns out to be symbolic
+enum Undo {
+    Register(usize, Value),
+    Memory(u64, Value, usize),
+}
+
+/// Decode `words` for `execute`, `None` if any word needs the full
+/// symbolic interpreter, such as syscalls, float ops or symbolic literals
+pub fn compile(words: &[Word]) -> Option<Vec<FastWord>> {
+    words
+        .iter()
+        .map(|word| match word {
+            Word::Literal(Value::Concrete(val, 0)) => Some(FastWord::Literal(*val)),
+            Word::Register(index) => Some(FastWord::Register(*index)),
+            Word::Operator(op) if supported(op) => Some(FastWord::Operator(op.to_owned())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn supported(op: &Operations) -> bool {
+    matches!(
+        op,
+        Operations::PcAddress
+            | Operations::If
+            | Operations::Else
+            | Operations::EndIf
+            | Operations::Compare
+            | Operations::LessThan
+            | Operations::LessThanEq
+            | Operations::GreaterThan
+            | Operations::GreaterThanEq
+            | Operations::LeftShift
+            | Operations::LogicalRightShift
+            | Operations::RightShift
+            | Operations::LeftRotation
+            | Operations::RightRotation
+            | Operations::SignExtend
+            | Operations::And
+            | Operations::Or
+            | Operations::Xor
+            | Operations::Add
+            | Operations::Subtract
+            | Operations::Multiply
+            | Operations::Divide
+            | Operations::Modulo
+            | Operations::SignedDivide
+            | Operations::SignedModulo
+            | Operations::Not
+            | Operations::Increment
+            | Operations::Decrement
+            | Operations::Equal
+            | Operations::WeakEqual
+            | Operations::Peek(_)
+            | Operations::Poke(_)
+            | Operations::PeekBits
+            | Operations::PokeBits
+            | Operations::AddressStore
+            | Operations::AddressRestore
+            | Operations::PopCount
+            | Operations::Swap
+            | Operations::Pop
+            | Operations::Duplicate
+            | Operations::Number
+            | Operations::Clear
+            | Operations::Break
+            | Operations::GoTo
+            | Operations::NoOperation
+            | Operations::Zero
+            | Operations::Carry
+            | Operations::Borrow
+            | Operations::Parity
+            | Operations::Overflow
+            | Operations::S
+            | Operations::Ds
+            | Operations::JumpTarget
+            | Operations::Js
+            | Operations::R
+    )
+}
+
+/// Execute compiled `words` using only concrete values. If a symbolic or
+/// tainted value is touched every change is undone and false is returned
+/// so the instruction can be executed by `Processor::parse` instead
+pub fn execute(state: &mut State, words: &[FastWord]) -> bool {
+    if state.esil.mode != ExecMode::Uncon || state.condition.is_some() {
+        return false;
+    }
+
+    let esil = &state.esil;
+    let saved = (
+        esil.previous.to_owned(),
+        esil.current.to_owned(),
+        esil.last_sz,
+        esil.stored_address.to_owned(),
+    );
+    let status = state.status.clone();
+    let mut undo = vec![];
+
+    if run(state, words, &mut undo).is_some() {
+        return true;
+    }
+
+    for change in undo.into_iter().rev() {
+        match change {
+            Undo::Register(index, value) => state.registers.values[index] = value,
+            Undo::Memory(addr, value, length) => state.memory.write_value(addr, &value, length),
+        }
+    }
+    let (previous, current, last_sz, stored_address) = saved;
+    state.esil.mode = ExecMode::Uncon;
+    state.esil.previous = previous;
+    state.esil.current = current;
+    state.esil.last_sz = last_sz;
+    state.esil.stored_address = stored_address;
+    state.status = status;
+    false
+}
+
+// the u64 of a concrete untainted value, like pop_value this
+// includes constant bitvectors that fit in a u64
+#[inline]
+fn concrete(value: &Value) -> Option<u64> {
+    match value {
+        Value::Concrete(val, 0) => Some(*val),
+        Value::Symbolic(bv, 0) if bv.is_const() && bv.get_width() <= 64 => bv.as_u64(),
+        _ => None,
+    }
+}
+
+#[inline]
+fn pop(state: &mut State, stack: &mut Vec<Item>, set_size: bool) -> Option<u64> {
+    match stack.pop().unwrap_or(Item::Literal(0)) {
+        Item::Literal(val) => Some(val),
+        Item::Register(index) => {
+            if set_size {
+                state.esil.last_sz = state.registers.indexes[index].reg_info.size as usize;
+            }
+            concrete(&state.registers.get_value(index))
+        }
+    }
+}
+
+#[inline]
+fn size(state: &State, stack: &[Item]) -> u32 {
+    match stack.last() {
+        Some(Item::Register(index)) => state.registers.indexes[*index].reg_info.size as u32,
+        _ => 64,
+    }
+}
+
+fn equal(
+    state: &mut State,
+    undo: &mut Vec<Undo>,
+    reg: Option<Item>,
+    value: u64,
+    set_esil: bool,
+) {
+    if let Some(Item::Register(index)) = reg {
+        let value_index = state.registers.indexes[index].value_index;
+        let size = state.registers.indexes[index].reg_info.size as usize;
+        let prev = state.registers.get_value(index);
+
+        undo.push(Undo::Register(
+            value_index,
+            state.registers.values[value_index].to_owned(),
+        ));
+        state.registers.set_value(index, vc(value));
+
+        if set_esil {
+            state.esil.last_sz = size;
+            state.esil.current = vc(value);
+            state.esil.previous = prev;
+        }
+    }
+}
+
+fn read(state: &mut State, addr: u64, length: usize) -> Option<u64> {
+    let value = state.memory_read_value(&vc(addr), length);
+    state.esil.current = value.to_owned();
+    state.esil.previous = vc(addr);
+    state.esil.last_sz = 8 * length;
+    concrete(&value)
+}
+
+fn write(state: &mut State, undo: &mut Vec<Undo>, addr: u64, value: u64, length: usize) {
+    undo.push(Undo::Memory(
+        addr,
+        state.memory.read_value(addr, length),
+        length,
+    ));
+    state.memory_write_value(&vc(addr), &vc(value), length);
+    state.esil.previous = vc(addr);
+    state.esil.last_sz = 8 * length;
+}
+
+// mirrors Processor::parse and do_operation for concrete values
+fn run(state: &mut State, words: &[FastWord], undo: &mut Vec<Undo>) -> Option<()> {
+    let mut stack: Vec<Item> = Vec::with_capacity(32);
+    state.stack.clear();
+
+    let mut word_index = 0;
+    while word_index < words.len() {
+        let word = &words[word_index];
+        word_index += 1;
+
+        if state.esil.mode == ExecMode::NoExec {
+            match word {
+                FastWord::Operator(Operations::Else) | FastWord::Operator(Operations::EndIf) => {}
+                _ => continue,
+            }
+        }
+
+        let op = match word {
+            FastWord::Literal(val) => {
+                stack.push(Item::Literal(*val));
+                continue;
+            }
+            FastWord::Register(index) => {
+                stack.push(Item::Register(*index));
+                continue;
+            }
+            FastWord::Operator(op) => op,
+        };
+
+        macro_rules! push {
+            ($value:expr) => {
+                stack.push(Item::Literal(concrete(&$value)?))
+            };
+        }
+
+        macro_rules! binary {
+            ($set_size:expr, $f:expr) => {{
+                let arg1 = vc(pop(state, &mut stack, $set_size)?);
+                let arg2 = vc(pop(state, &mut stack, false)?);
+                push!($f(arg1, arg2));
+            }};
+        }
+
+        match op {
+            Operations::PcAddress => push!(state.esil.prev_pc),
+            Operations::If => {
+                if state.esil.mode != ExecMode::Uncon {
+                    return None;
+                }
+                let cond = pop(state, &mut stack, false)?;
+                state.esil.mode = if cond == 0 {
+                    ExecMode::NoExec
+                } else {
+                    ExecMode::Exec
+                };
+            }
+            Operations::Else => match state.esil.mode {
+                ExecMode::Exec => state.esil.mode = ExecMode::NoExec,
+                ExecMode::NoExec => state.esil.mode = ExecMode::Exec,
+                _ => {}
+            },
+            Operations::EndIf => state.esil.mode = ExecMode::Uncon,
+            Operations::GoTo => {
+                let n = pop(state, &mut stack, false)?;
+                state.esil.mode = ExecMode::Uncon;
+                word_index = n as usize;
+            }
+            Operations::Break => {
+                state.esil.mode = ExecMode::Uncon;
+                break;
+            }
+            Operations::Compare => {
+                let arg1 = vc(pop(state, &mut stack, true)?);
+                let arg2 = vc(pop(state, &mut stack, false)?);
+                state.esil.current = arg1.to_owned() - arg2;
+                state.esil.previous = arg1;
+            }
+            Operations::LessThan => binary!(true, |a: Value, b| a.slt(&b)),
+            Operations::LessThanEq => binary!(true, |a: Value, b| a.slte(&b)),
+            Operations::GreaterThan => binary!(true, |a: Value, b| a.sgt(&b)),
+            Operations::GreaterThanEq => binary!(true, |a: Value, b: Value| a.sgte(&b)),
+            Operations::LeftShift => binary!(false, |a, b| a << b),
+            Operations::LogicalRightShift => binary!(false, |a, b| a >> b),
+            Operations::RightShift => {
+                let sz = size(state, &stack);
+                binary!(false, |a: Value, b| a.asr(b, sz));
+            }
+            Operations::LeftRotation => {
+                let sz = size(state, &stack);
+                binary!(false, |a: Value, b| a.rol(b, sz));
+            }
+            Operations::RightRotation => {
+                let sz = size(state, &stack);
+                binary!(false, |a: Value, b| a.ror(b, sz));
+            }
+            Operations::SignExtend => {
+                let val = pop(state, &mut stack, false)?;
+                let bits = pop(state, &mut stack, false)?;
+                let shift = (64 - bits) as i64;
+                stack.push(Item::Literal(((val << shift) as i64 >> shift) as u64));
+            }
+            Operations::And => binary!(false, |a, b| a & b),
+            Operations::Or => binary!(false, |a, b| a | b),
+            Operations::Xor => binary!(false, |a, b| a ^ b),
+            Operations::Add => binary!(false, |a, b| a + b),
+            Operations::Subtract => binary!(false, |a, b| a - b),
+            Operations::Multiply => binary!(false, |a, b| a * b),
+            Operations::Divide => binary!(false, |a, b| a / b),
+            Operations::Modulo => binary!(false, |a, b| a % b),
+            Operations::SignedDivide => binary!(false, |a: Value, b| a.sdiv(b)),
+            Operations::SignedModulo => binary!(false, |a: Value, b| a.srem(b)),
+            Operations::Not => {
+                let arg1 = vc(pop(state, &mut stack, false)?);
+                push!(!arg1);
+            }
+            Operations::Increment => {
+                let arg1 = pop(state, &mut stack, false)?;
+                stack.push(Item::Literal(arg1.wrapping_add(1)));
+            }
+            Operations::Decrement => {
+                let arg1 = pop(state, &mut stack, false)?;
+                stack.push(Item::Literal(arg1.wrapping_sub(1)));
+            }
+            Operations::Equal | Operations::WeakEqual => {
+                let reg = stack.pop();
+                let value = pop(state, &mut stack, false)?;
+                equal(state, undo, reg, value, *op == Operations::Equal);
+            }
+            Operations::Peek(n) => {
+                let addr = pop(state, &mut stack, false)?;
+                stack.push(Item::Literal(read(state, addr, *n)?));
+            }
+            Operations::Poke(n) => {
+                let addr = pop(state, &mut stack, false)?;
+                let value = pop(state, &mut stack, false)?;
+                write(state, undo, addr, value, *n);
+            }
+            Operations::PeekBits => {
+                let n = (state.memory.bits / 8) as usize;
+                let addr = pop(state, &mut stack, false)?;
+                stack.push(Item::Literal(read(state, addr, n)?));
+            }
+            Operations::PokeBits => {
+                let n = (state.memory.bits / 8) as usize;
+                let addr = pop(state, &mut stack, false)?;
+                let value = pop(state, &mut stack, false)?;
+                write(state, undo, addr, value, n);
+            }
+            Operations::AddressStore => {
+                let addr = pop(state, &mut stack, false)?;
+                state.esil.stored_address = Some(vc(addr));
+                stack.push(Item::Literal(addr));
+            }
+            Operations::AddressRestore => {
+                let addr = state.esil.stored_address.take()?;
+                push!(addr);
+            }
+            Operations::PopCount => {
+                let arg1 = pop(state, &mut stack, false)?;
+                stack.push(Item::Literal(arg1.count_ones() as u64));
+            }
+            Operations::Swap => {
+                let arg1 = stack.pop()?;
+                let arg2 = stack.pop()?;
+                stack.push(arg1);
+                stack.push(arg2);
+            }
+            Operations::Pop => {
+                stack.pop();
+            }
+            Operations::Duplicate => {
+                let item = *stack.last()?;
+                stack.push(item);
+            }
+            Operations::Number => {
+                let value = pop(state, &mut stack, false)?;
+                stack.push(Item::Literal(value));
+            }
+            Operations::Clear => stack.clear(),
+            Operations::Zero => {
+                let mask = vc(genmask((state.esil.last_sz - 1) as u64));
+                push!(!(state.esil.current.and(&mask)));
+            }
+            Operations::Carry | Operations::Borrow => {
+                let bits = pop(state, &mut stack, false)?;
+                let mask = vc(genmask(bits & 0x3f));
+                let cur = state.esil.current.and(&mask);
+                let old = state.esil.previous.and(&mask);
+                if *op == Operations::Carry {
+                    push!(cur.ult(&old));
+                } else {
+                    push!(old.ult(&cur));
+                }
+            }
+            Operations::Parity => {
+                let val = concrete(&state.esil.current)?;
+                stack.push(Item::Literal(!((val & 0xff).count_ones() % 2) as u64));
+            }
+            Operations::Overflow => {
+                let bits = pop(state, &mut stack, false)?;
+                let mask1 = vc(genmask(bits & 0x3f));
+                let mask2 = vc(genmask((bits + 0x3f) & 0x3f));
+
+                let cur = &state.esil.current;
+                let old = &state.esil.previous;
+                let c_in = cur.and(&mask1).ult(&old.and(&mask1));
+                let c_out = cur.and(&mask2).ult(&old.and(&mask2));
+                push!(c_in ^ c_out);
+            }
+            Operations::S => {
+                let shift = vc(pop(state, &mut stack, false)?);
+                push!((state.esil.current.to_owned() >> shift) & vc(1));
+            }
+            Operations::Ds => {
+                let sz = vc(state.esil.last_sz as u64);
+                push!((state.esil.current.to_owned() >> sz) & vc(1));
+            }
+            Operations::R => stack.push(Item::Literal(64 >> 3)),
+            _ => {}
+        }
+    }
+    Some(())
+}
@@ -0,0 +1,777 @@
+use crate::operations::{do_equal, do_operation, pop_value, push_value, Operations, SIZE};
+use crate::processor::Word;
+use crate::registers::Registers;
+use crate::state::{ExecMode, StackItem, State};
+use crate::value::{vc, Value};
+
+use std::collections::{HashMap, HashSet};
+
+/// An operand of an IR instruction. Like values popped from the ESIL
+/// stack, temporaries narrower than 64 bits are extended when used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Const(u64),
+    Temp(usize),
+    /// A temporary that is sign extended
+    Signed(usize),
+}
+
+/// A lifted ESIL operation. Before allocation every temporary is
+/// assigned exactly once, afterwards they are slots that get reused
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    /// Read a register into a temporary
+    Read { dst: usize, reg: usize },
+    /// Set the size used by the flag operations
+    Size(usize),
+    /// A unary or binary operator, `size` is used by shifts and rotations
+    Op {
+        dst: usize,
+        op: Operations,
+        a: Operand,
+        b: Operand,
+        size: u32,
+    },
+    /// A flag computed from the last ESIL result, such as `$z` or `$c`
+    Flag {
+        dst: usize,
+        op: Operations,
+        arg: Option<Operand>,
+    },
+    /// ESIL compare, only sets the last result
+    Compare { a: Operand, b: Operand },
+    /// Write a register, `esil` also sets the last result like `=`
+    Write { reg: usize, value: Operand, esil: bool },
+    Load { dst: usize, addr: Operand, len: usize },
+    Store { addr: Operand, value: Operand, len: usize },
+    /// Skip to `next` if the condition is concrete and zero
+    If { cond: Operand, next: usize },
+    /// Skip to `next` if the if clause was executed
+    Else { next: usize },
+    EndIf,
+}
+
+impl Inst {
+    // the temporary written by a pure instruction
+    fn pure_dst(&self) -> Option<usize> {
+        match self {
+            Inst::Read { dst, .. } | Inst::Op { dst, .. } | Inst::Flag { dst, .. } => Some(*dst),
+            _ => None,
+        }
+    }
+
+    fn dst_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Inst::Read { dst, .. }
+            | Inst::Op { dst, .. }
+            | Inst::Flag { dst, .. }
+            | Inst::Load { dst, .. } => Some(dst),
+            _ => None,
+        }
+    }
+
+    fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Op { a, b, .. } | Inst::Compare { a, b } => vec![a, b],
+            Inst::Flag { arg: Some(arg), .. } => vec![arg],
+            Inst::Write { value, .. } => vec![value],
+            Inst::Load { addr, .. } => vec![addr],
+            Inst::Store { addr, value, .. } => vec![addr, value],
+            Inst::If { cond, .. } => vec![cond],
+            _ => vec![],
+        }
+    }
+
+    fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Inst::Op { a, b, .. } | Inst::Compare { a, b } => vec![a, b],
+            Inst::Flag { arg: Some(arg), .. } => vec![arg],
+            Inst::Write { value, .. } => vec![value],
+            Inst::Load { addr, .. } => vec![addr],
+            Inst::Store { addr, value, .. } => vec![addr, value],
+            Inst::If { cond, .. } => vec![cond],
+            _ => vec![],
+        }
+    }
+
+    fn temps(&self) -> Vec<usize> {
+        self.operands().into_iter().filter_map(temp).collect()
+    }
+}
+
+fn temp(operand: &Operand) -> Option<usize> {
+    match operand {
+        Operand::Temp(t) | Operand::Signed(t) => Some(*t),
+        Operand::Const(_) => None,
+    }
+}
+
+/// The IR of a single instruction, `slots` is the number of
+/// temporaries needed to execute it once allocated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lifted {
+    pub insts: Vec<Inst>,
+    pub slots: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Item {
+    Register(usize),
+    Value(Operand),
+}
+
+struct Lifter<'a> {
+    registers: &'a Registers,
+    insts: Vec<Inst>,
+    stack: Vec<Item>,
+    temps: usize,
+    consts: HashMap<usize, u64>, // registers written with a constant
+    stored: Option<Operand>,
+    mode: ExecMode,
+}
+
+/// Lift the ESIL `words` of the instruction at `pc`, folding constants and
+/// removing dead operations. `None` if any word can't be lifted, such as
+/// syscalls, float ops, symbolic literals or nested conditionals. Constants
+/// are only folded within the instruction, across instructions `eliminate`
+/// just removes dead flag writes
+pub fn lift(words: &[Word], pc: u64, bits: u64, registers: &Registers) -> Option<Lifted> {
+    let mut lifter = Lifter {
+        registers,
+        insts: vec![],
+        stack: vec![],
+        temps: 0,
+        consts: HashMap::new(),
+        stored: None,
+        mode: ExecMode::Uncon,
+    };
+
+    for word in words {
+        if lifter.mode == ExecMode::NoExec {
+            match word {
+                Word::Operator(Operations::Else) | Word::Operator(Operations::EndIf) => {}
+                _ => continue,
+            }
+        }
+        match word {
+            Word::Literal(Value::Concrete(val, 0)) => {
+                lifter.stack.push(Item::Value(Operand::Const(*val)))
+            }
+            Word::Register(index) => lifter.stack.push(Item::Register(*index)),
+            Word::Operator(op) => lifter.operator(op, pc, bits)?,
+            _ => return None,
+        }
+    }
+
+    if lifter.mode != ExecMode::Uncon || lifter.stored.is_some() {
+        return None;
+    }
+
+    let mut insts = lifter.insts;
+    dce(&mut insts);
+    Some(Lifted { insts, slots: 0 })
+}
+
+impl Lifter<'_> {
+    fn temp(&mut self) -> usize {
+        self.temps += 1;
+        self.temps - 1
+    }
+
+    fn push(&mut self, operand: Operand) {
+        self.stack.push(Item::Value(operand));
+    }
+
+    // mirrors pop_value, registers are read when they are popped
+    fn pop(&mut self, set_size: bool, sign_ext: bool) -> Operand {
+        match self.stack.pop() {
+            None => Operand::Const(0),
+            Some(Item::Value(Operand::Temp(t))) if sign_ext => Operand::Signed(t),
+            Some(Item::Value(operand)) => operand,
+            Some(Item::Register(reg)) => {
+                if set_size {
+                    let size = self.registers.indexes[reg].reg_info.size as usize;
+                    self.insts.push(Inst::Size(size));
+                }
+                if let Some(val) = self.consts.get(&reg) {
+                    return Operand::Const(*val);
+                }
+                let dst = self.temp();
+                self.insts.push(Inst::Read { dst, reg });
+                if sign_ext {
+                    Operand::Signed(dst)
+                } else {
+                    Operand::Temp(dst)
+                }
+            }
+        }
+    }
+
+    // mirrors get_size
+    fn size(&self) -> u32 {
+        match self.stack.last() {
+            Some(Item::Register(reg)) => self.registers.indexes[*reg].reg_info.size as u32,
+            _ => 64,
+        }
+    }
+
+    // a popped value that is pushed back has to be extended once
+    fn extended(&mut self, operand: Operand) -> Operand {
+        match operand {
+            Operand::Const(_) => operand,
+            _ => self.op(Operations::Number, operand, Operand::Const(0), 64),
+        }
+    }
+
+    fn op(&mut self, op: Operations, a: Operand, b: Operand, size: u32) -> Operand {
+        if let (Operand::Const(a), Operand::Const(b)) = (a, b) {
+            if let Value::Concrete(val, 0) = apply(&op, vc(a), vc(b), size) {
+                return Operand::Const(val);
+            }
+        }
+        let dst = self.temp();
+        self.insts.push(Inst::Op { dst, op, a, b, size });
+        Operand::Temp(dst)
+    }
+
+    fn flag(&mut self, op: &Operations, arg: Option<Operand>) {
+        let dst = self.temp();
+        let op = op.to_owned();
+        self.insts.push(Inst::Flag { dst, op, arg });
+        self.push(Operand::Temp(dst));
+    }
+
+    fn write(&mut self, reg: usize, value: Operand, esil: bool) {
+        self.insts.push(Inst::Write { reg, value, esil });
+
+        let registers = self.registers;
+        self.consts.retain(|r, _| !overlaps(registers, *r, reg));
+
+        // writes in a symbolic clause are conditional
+        let info = &registers.indexes[reg];
+        let full = info.reg_info.size == info.bounds.size && info.reg_info.size <= 64;
+        if let (Operand::Const(val), ExecMode::Uncon | ExecMode::Exec) = (value, &self.mode) {
+            if full && span(registers, reg).is_some() {
+                let val = vc(val).slice(info.reg_info.size - 1, 0).as_u64();
+                self.consts.insert(reg, val.unwrap_or(0));
+            }
+        }
+    }
+
+    // conditionals are only lifted if the stack is empty at every
+    // clause boundary, so the clauses never need to be merged
+    fn boundary(&self) -> Option<()> {
+        if self.stack.is_empty() && self.stored.is_none() {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn operator(&mut self, op: &Operations, pc: u64, bits: u64) -> Option<()> {
+        match op {
+            Operations::PcAddress => self.push(Operand::Const(pc)),
+            Operations::If => {
+                if self.mode != ExecMode::Uncon {
+                    return None;
+                }
+                let cond = self.pop(false, false);
+                self.boundary()?;
+                self.mode = match cond {
+                    Operand::Const(0) => ExecMode::NoExec,
+                    Operand::Const(_) => ExecMode::Exec,
+                    _ => {
+                        self.insts.push(Inst::If { cond, next: 0 });
+                        ExecMode::If
+                    }
+                };
+            }
+            Operations::Else => {
+                self.boundary()?;
+                self.mode = match self.mode {
+                    ExecMode::Exec => ExecMode::NoExec,
+                    ExecMode::NoExec => ExecMode::Exec,
+                    ExecMode::If => {
+                        self.insts.push(Inst::Else { next: 0 });
+                        ExecMode::Else
+                    }
+                    _ => return None,
+                };
+            }
+            Operations::EndIf => {
+                self.boundary()?;
+                if let ExecMode::If | ExecMode::Else = self.mode {
+                    self.insts.push(Inst::EndIf);
+                }
+                self.mode = ExecMode::Uncon;
+            }
+            Operations::Compare => {
+                let a = self.pop(true, false);
+                let b = self.pop(false, false);
+                self.insts.push(Inst::Compare { a, b });
+            }
+            Operations::LessThan
+            | Operations::LessThanEq
+            | Operations::GreaterThan
+            | Operations::GreaterThanEq => {
+                let a = self.pop(true, true);
+                let b = self.pop(false, true);
+                let value = self.op(op.to_owned(), a, b, 64);
+                self.push(value);
+            }
+            Operations::RightShift | Operations::LeftRotation | Operations::RightRotation => {
+                let size = self.size();
+                let sign_ext = *op == Operations::RightShift;
+                let a = self.pop(false, sign_ext);
+                let b = self.pop(false, sign_ext);
+                let value = self.op(op.to_owned(), a, b, size);
+                self.push(value);
+            }
+            Operations::LeftShift
+            | Operations::LogicalRightShift
+            | Operations::And
+            | Operations::Or
+            | Operations::Xor
+            | Operations::Add
+            | Operations::Subtract
+            | Operations::Multiply
+            | Operations::Divide
+            | Operations::Modulo
+            | Operations::SignedDivide
+            | Operations::SignedModulo => {
+                let a = self.pop(false, false);
+                let b = self.pop(false, false);
+                let value = self.op(op.to_owned(), a, b, 64);
+                self.push(value);
+            }
+            Operations::SignExtend => {
+                let a = self.pop(false, false);
+                match self.pop(false, false) {
+                    Operand::Const(bits) if bits > 0 && bits <= 64 => {
+                        let value = self.op(op.to_owned(), a, Operand::Const(bits), 64);
+                        self.push(value);
+                    }
+                    _ => return None,
+                }
+            }
+            Operations::Not | Operations::PopCount => {
+                let a = self.pop(false, false);
+                let value = self.op(op.to_owned(), a, Operand::Const(0), 64);
+                self.push(value);
+            }
+            Operations::Increment | Operations::Decrement => {
+                let a = self.pop(false, false);
+                let op = if *op == Operations::Increment {
+                    Operations::Add
+                } else {
+                    Operations::Subtract
+                };
+                let value = self.op(op, a, Operand::Const(1), 64);
+                self.push(value);
+            }
+            Operations::Number => {
+                let a = self.pop(false, false);
+                let value = self.extended(a);
+                self.push(value);
+            }
+            Operations::Equal | Operations::WeakEqual => {
+                let reg = self.stack.pop()?;
+                let value = self.pop(false, false);
+                if let Item::Register(reg) = reg {
+                    self.write(reg, value, *op == Operations::Equal);
+                }
+            }
+            Operations::Peek(_) | Operations::PeekBits => {
+                let len = match op {
+                    Operations::Peek(n) => *n,
+                    _ => (bits / 8) as usize,
+                };
+                let addr = self.pop(false, false);
+                let dst = self.temp();
+                self.insts.push(Inst::Load { dst, addr, len });
+                self.push(Operand::Temp(dst));
+            }
+            Operations::Poke(_) | Operations::PokeBits => {
+                let len = match op {
+                    Operations::Poke(n) => *n,
+                    _ => (bits / 8) as usize,
+                };
+                let addr = self.pop(false, false);
+                let value = self.pop(false, false);
+                self.insts.push(Inst::Store { addr, value, len });
+            }
+            Operations::AddressStore => {
+                let addr = self.pop(false, false);
+                let addr = self.extended(addr);
+                self.stored = Some(addr);
+                self.push(addr);
+            }
+            Operations::AddressRestore => {
+                let addr = self.stored.take()?;
+                self.push(addr);
+            }
+            Operations::Swap => {
+                let arg1 = self.stack.pop()?;
+                let arg2 = self.stack.pop()?;
+                self.stack.push(arg1);
+                self.stack.push(arg2);
+            }
+            Operations::Pop => {
+                self.stack.pop();
+            }
+            Operations::Duplicate => {
+                let item = *self.stack.last()?;
+                self.stack.push(item);
+            }
+            Operations::Clear => self.stack.clear(),
+            Operations::Zero | Operations::Parity | Operations::Ds => self.flag(op, None),
+            Operations::Carry
+            | Operations::Borrow
+            | Operations::Overflow
+            | Operations::S => {
+                let arg = self.pop(false, false);
+                self.flag(op, Some(arg));
+            }
+            Operations::R => self.push(Operand::Const(64 >> 3)),
+            Operations::NoOperation | Operations::JumpTarget | Operations::Js => {}
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+// mirrors do_operation for everything but symbolic popcounts
+fn apply(op: &Operations, a: Value, b: Value, size: u32) -> Value {
+    match op {
+        Operations::LessThan => a.slt(&b),
+        Operations::LessThanEq => a.slte(&b),
+        Operations::GreaterThan => a.sgt(&b),
+        Operations::GreaterThanEq => a.sgte(&b),
+        Operations::LeftShift => a << b,
+        Operations::LogicalRightShift => a >> b,
+        Operations::RightShift => a.asr(b, size),
+        Operations::LeftRotation => a.rol(b, size),
+        Operations::RightRotation => a.ror(b, size),
+        Operations::SignExtend => {
+            let bits = b.as_u64().unwrap_or(64);
+            match a {
+                Value::Concrete(val, t) => {
+                    let shift = (64 - bits) as i64;
+                    Value::Concrete(((val << shift) as i64 >> shift) as u64, t)
+                }
+                Value::Symbolic(val, t) => {
+                    Value::Symbolic(val.slice((bits - 1) as u32, 0).sext(64 - bits as u32), t)
+                }
+            }
+        }
+        Operations::And => a & b,
+        Operations::Or => a | b,
+        Operations::Xor => a ^ b,
+        Operations::Add => a + b,
+        Operations::Subtract => a - b,
+        Operations::Multiply => a * b,
+        Operations::Divide => a / b,
+        Operations::Modulo => a % b,
+        Operations::SignedDivide => a.sdiv(b),
+        Operations::SignedModulo => a.srem(b),
+        Operations::Not => !a,
+        Operations::PopCount => match a {
+            Value::Concrete(val, t) => Value::Concrete(val.count_ones() as u64, t),
+            _ => a,
+        },
+        _ => a, // Number
+    }
+}
+
+// remove pure instructions whose results are never used
+fn dce(insts: &mut Vec<Inst>) {
+    let mut live = HashSet::new();
+    let mut keep = vec![true; insts.len()];
+    for (i, inst) in insts.iter().enumerate().rev() {
+        if let Some(dst) = inst.pure_dst() {
+            if !live.contains(&dst) {
+                keep[i] = false;
+                continue;
+            }
+        }
+        live.extend(inst.temps());
+    }
+
+    let mut keep = keep.into_iter();
+    insts.retain(|_| keep.next().unwrap_or(true));
+}
+
+// the register file span of a register, None for zero registers
+fn span(registers: &Registers, reg: usize) -> Option<(u64, u64)> {
+    let info = &registers.indexes[reg].reg_info;
+    if info.offset == -1i64 as u64 {
+        None
+    } else {
+        Some((info.offset, info.offset + info.size))
+    }
+}
+
+fn overlaps(registers: &Registers, r1: usize, r2: usize) -> bool {
+    match (span(registers, r1), span(registers, r2)) {
+        (Some((start1, end1)), Some((start2, end2))) => start1 < end2 && start2 < end1,
+        _ => false,
+    }
+}
+
+// true if r2 lies within r1, like Registers::is_sub
+fn contains(registers: &Registers, r1: usize, r2: usize) -> bool {
+    match (span(registers, r1), span(registers, r2)) {
+        (Some((start1, end1)), Some((start2, end2))) => start2 >= start1 && end2 <= end1,
+        _ => false,
+    }
+}
+
+// a weak register write that `next` overwrites before it is read
+fn is_dead(insts: &[Inst], index: usize, next: &[Inst], registers: &Registers) -> bool {
+    let reg = match insts[index] {
+        Inst::Write { reg, esil: false, .. } => reg,
+        _ => return false,
+    };
+
+    let reads =
+        |inst: &Inst| matches!(inst, Inst::Read { reg: r, .. } if overlaps(registers, *r, reg));
+    let conditional = matches!(
+        insts[..index]
+            .iter()
+            .rev()
+            .find(|inst| matches!(inst, Inst::If { .. } | Inst::EndIf)),
+        Some(Inst::If { .. })
+    );
+
+    if conditional || insts[index + 1..].iter().any(reads) {
+        return false;
+    }
+
+    let mut depth = 0;
+    for inst in next {
+        match inst {
+            Inst::Read { .. } if reads(inst) => return false,
+            Inst::Write { reg: r, .. } if depth == 0 && contains(registers, *r, reg) => return true,
+            Inst::If { .. } => depth += 1,
+            Inst::EndIf => depth -= 1,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Remove register writes, mostly flags, that are overwritten by the next
+/// instruction before being read. `chained[i]` must only be true if the
+/// instruction after `lifted[i]` is executed right after it with no hooks
+pub fn eliminate(lifted: &mut [Option<Lifted>], chained: &[bool], registers: &Registers) {
+    let pc = registers.pc.as_ref().map(|pc| pc.index);
+    for i in 1..lifted.len() {
+        let (head, tail) = lifted.split_at_mut(i);
+        if let (Some(curr), Some(next)) = (&mut head[i - 1], &tail[0]) {
+            let jumps = curr.insts.iter().any(|inst| match inst {
+                Inst::Write { reg, .. } => match pc {
+                    Some(pc) => overlaps(registers, *reg, pc),
+                    None => true,
+                },
+                _ => false,
+            });
+            if !chained[i - 1] || jumps {
+                continue;
+            }
+
+            let insts = &curr.insts;
+            let dead: Vec<bool> = (0..insts.len())
+                .map(|index| is_dead(insts, index, &next.insts, registers))
+                .collect();
+
+            if dead.contains(&true) {
+                let mut dead = dead.into_iter();
+                curr.insts.retain(|_| !dead.next().unwrap_or(false));
+                dce(&mut curr.insts);
+            }
+        }
+    }
+}
+
+impl Lifted {
+    /// Assign temporaries to reusable slots, each temporary is freed after its
+    /// last use. Also sets the targets of conditional skips
+    pub fn allocate(&mut self) {
+        let mut last = HashMap::new();
+        for (i, inst) in self.insts.iter().enumerate() {
+            for t in inst.temps() {
+                last.insert(t, i);
+            }
+        }
+
+        let mut slots: HashMap<usize, usize> = HashMap::new();
+        let mut free: Vec<usize> = vec![];
+        let mut count = 0;
+        for (i, inst) in self.insts.iter_mut().enumerate() {
+            let temps = inst.temps();
+            for operand in inst.operands_mut() {
+                match operand {
+                    Operand::Temp(t) | Operand::Signed(t) => *t = slots[&*t],
+                    Operand::Const(_) => {}
+                }
+            }
+            for t in temps {
+                if last[&t] == i {
+                    if let Some(slot) = slots.remove(&t) {
+                        free.push(slot);
+                    }
+                }
+            }
+
+            if let Some(dst) = inst.dst_mut() {
+                let slot = free.pop().unwrap_or_else(|| {
+                    count += 1;
+                    count - 1
+                });
+                if last.contains_key(dst) {
+                    slots.insert(*dst, slot);
+                } else {
+                    free.push(slot);
+                }
+                *dst = slot;
+            }
+        }
+        self.slots = count;
+
+        let mut cond = None;
+        let mut other = None;
+        for i in 0..self.insts.len() {
+            match self.insts[i] {
+                Inst::If { .. } => cond = Some(i),
+                Inst::Else { .. } => {
+                    if let Some(Inst::If { next, .. }) = cond.map(|c| &mut self.insts[c]) {
+                        *next = i + 1;
+                    }
+                    other = Some(i);
+                }
+                Inst::EndIf => {
+                    if let Some(Inst::Else { next }) = other.map(|e| &mut self.insts[e]) {
+                        *next = i;
+                    } else if let Some(Inst::If { next, .. }) = cond.map(|c| &mut self.insts[c]) {
+                        *next = i;
+                    }
+                    cond = None;
+                    other = None;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// mirrors the extension done by pop_value
+fn operand(temps: &[Value], operand: &Operand) -> Value {
+    let (value, sign_ext) = match operand {
+        Operand::Const(val) => return vc(*val),
+        Operand::Temp(t) => (&temps[*t], false),
+        Operand::Signed(t) => (&temps[*t], true),
+    };
+
+    match value {
+        Value::Symbolic(bv, t) if bv.is_const() && bv.get_width() <= 64 => {
+            Value::Concrete(bv.as_u64().unwrap(), *t)
+        }
+        Value::Symbolic(bv, t) if bv.get_width() < SIZE as u32 => {
+            let szdiff = SIZE as u32 - bv.get_width();
+            if sign_ext {
+                Value::Symbolic(bv.sext(szdiff), *t)
+            } else {
+                Value::Symbolic(bv.uext(szdiff), *t)
+            }
+        }
+        _ => value.to_owned(),
+    }
+}
+
+/// Execute the lifted IR of an instruction, returns false without changing
+/// the state if it has to be parsed instead because the ESIL of the previous
+/// instruction left a conditional open
+pub fn execute(state: &mut State, lifted: &Lifted) -> bool {
+    if state.esil.mode != ExecMode::Uncon || state.condition.is_some() {
+        return false;
+    }
+
+    state.stack.clear();
+    let mut temps = vec![Value::Concrete(0, 0); lifted.slots];
+    let mut taken = false; // a concrete if clause was executed
+
+    let insts = &lifted.insts;
+    let mut index = 0;
+    while index < insts.len() {
+        let inst = &insts[index];
+        index += 1;
+
+        match inst {
+            Inst::Read { dst, reg } => temps[*dst] = state.registers.get_value(*reg),
+            Inst::Size(size) => state.esil.last_sz = *size,
+            Inst::Op { dst, op, a, b, size } => {
+                let a = operand(&temps, a);
+                let b = operand(&temps, b);
+                temps[*dst] = if *op == Operations::PopCount && a.is_symbolic() {
+                    push_value(state, a);
+                    do_operation(state, op);
+                    pop_value(state, false, false)
+                } else {
+                    apply(op, a, b, *size)
+                };
+            }
+            Inst::Flag { dst, op, arg } => {
+                if let Some(arg) = arg {
+                    push_value(state, operand(&temps, arg));
+                }
+                do_operation(state, op);
+                temps[*dst] = pop_value(state, false, false);
+            }
+            Inst::Compare { a, b } => {
+                let a = operand(&temps, a);
+                state.esil.current = a.to_owned() - operand(&temps, b);
+                state.esil.previous = a;
+            }
+            Inst::Write { reg, value, esil } => {
+                let value = operand(&temps, value);
+                do_equal(state, StackItem::StackRegister(*reg), value, *esil);
+            }
+            Inst::Load { dst, addr, len } => {
+                push_value(state, operand(&temps, addr));
+                do_operation(state, &Operations::Peek(*len));
+                temps[*dst] = pop_value(state, false, false);
+            }
+            Inst::Store { addr, value, len } => {
+                push_value(state, operand(&temps, value));
+                push_value(state, operand(&temps, addr));
+                do_operation(state, &Operations::Poke(*len));
+            }
+            Inst::If { cond, next } => match operand(&temps, cond) {
+                Value::Concrete(val, _) => {
+                    taken = val != 0;
+                    if !taken {
+                        index = *next;
+                    }
+                }
+                Value::Symbolic(val, _) => {
+                    state.esil.mode = ExecMode::If;
+                    let cond_bv = val._eq(&state.bvv(0, val.get_width())).not();
+                    state.condition = Some(cond_bv);
+                }
+            },
+            Inst::Else { next } => {
+                if state.esil.mode == ExecMode::If {
+                    state.esil.mode = ExecMode::Else;
+                    state.condition = state.condition.as_ref().map(|cond| cond.not());
+                } else if taken {
+                    index = *next;
+                }
+            }
+            Inst::EndIf => {
+                state.esil.mode = ExecMode::Uncon;
+                state.condition = None;
+            }
+        }
+    }
+    true
+}
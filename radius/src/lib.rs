@@ -2,6 +2,16 @@ extern crate boolector;
 extern crate r2pipe;
 extern crate serde_json;
 
+/// Save r2 results and ESIL tokens between runs
+pub mod cache;
+/// Concolic execution from concrete seed inputs
+pub mod concolic;
+/// Instruction and block coverage of a run
+pub mod coverage;
+/// Fast interpreter for fully concrete instructions
+pub mod fast;
+/// Lift ESIL into an IR with constant folding and dead code elimination
+pub mod ir;
 /// Memory used in a program state
 pub mod memory;
 mod operations;
@@ -20,11 +30,19 @@ pub mod solver;
 /// A program state, containing the registers, memory, and solver context
 pub mod state;
 mod test;
+/// Generate test cases for every branch
+pub mod testgen;
+/// Record and replay execution traces
+pub mod trace;
 /// Asbstraction for concrete and symbolic values used during execution
 pub mod value;
 
+pub use crate::concolic::Concolic;
+pub use crate::coverage::Coverage;
 pub use crate::radius::{Radius, RadiusOption};
 pub use crate::registers::Registers;
-pub use crate::sims::{Sim, SimMethod};
-pub use crate::state::State;
+pub use crate::sims::{make_sim, Sim, SimFunction, SimMethod};
+pub use crate::state::{Event, EventContext, EventTrigger, State, WatchAccess};
+pub use crate::testgen::{InputKind, TestGenerator};
+pub use crate::trace::{Trace, TraceEntry};
 pub use crate::value::{vc, Value};
\ No newline at end of file
@@ -1,25 +1,31 @@
+use crate::concolic::Concolic;
 use crate::processor::Word;
 use crate::r2_api::hex_encode;
-use crate::radius::{Radius, RadiusOption, RunMode};
+use crate::radius::{Radius, RadiusOption};
+use crate::sims::fs::MountMode;
 use boolector::BV;
 use clap::{App, Arg};
 use colored::*;
-use std::path::Path;
 use std::time::Instant;
-use std::{fs, process};
+use std::{fs, io, process};
 
-use crate::state::StateStatus;
+use crate::testgen::{InputKind, TestGenerator};
+use crate::trace::Trace;
 use crate::value::{Value, vc};
 
-use std::collections::{HashSet, HashMap};
+use std::collections::HashMap;
 use std::ascii::escape_default;
 use std::str;
 
 //use ahash::AHashMap;
 //type HashMap<P, Q> = AHashMap<P, Q>;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
 
+pub mod cache;
+pub mod concolic;
+pub mod coverage;
+pub mod fast;
+pub mod ir;
 pub mod memory;
 pub mod operations;
 pub mod processor;
@@ -29,6 +35,8 @@ pub mod registers;
 pub mod sims;
 pub mod solver;
 pub mod state;
+pub mod testgen;
+pub mod trace;
 pub mod value;
 
 macro_rules! occurs {
@@ -56,6 +64,20 @@ fn show(bs: &[u8]) -> String {
     }
 }
 
+// read a seed file or every file in a corpus directory
+fn read_seeds(path: &str) -> io::Result<Vec<Vec<u8>>> {
+    if fs::metadata(path)?.is_dir() {
+        let mut paths: Vec<_> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .collect();
+        paths.sort();
+        paths.iter().map(fs::read).collect()
+    } else {
+        Ok(vec![fs::read(path)?])
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonOutput {
     pub symbols: HashMap<String, String>,
@@ -93,6 +115,26 @@ fn main() {
                 .multiple(true)
                 .help("Add a symbolic file"),
         )
+        .arg(
+            Arg::with_name("mount")
+                .long("mount")
+                .value_names(&["HOST", "PATH"])
+                .multiple(true)
+                .help("Mount a host directory read-only"),
+        )
+        .arg(
+            Arg::with_name("mount_cow")
+                .long("mount-cow")
+                .value_names(&["HOST", "PATH"])
+                .multiple(true)
+                .help("Mount a host directory copy-on-write"),
+        )
+        .arg(
+            Arg::with_name("export_fs")
+                .long("export-fs")
+                .takes_value(true)
+                .help("Write files created or modified by the program to supplied dir"),
+        )
         .arg(
             Arg::with_name("json")
                 .short("j")
@@ -137,12 +179,43 @@ fn main() {
                 .long("no-sims")
                 .help("Do not simulate imports"),
         )
+        .arg(
+            Arg::with_name("no_cache")
+                .long("no-cache")
+                .help("Do not save or reuse r2 results between runs"),
+        )
+        .arg(
+            Arg::with_name("cache_dir")
+                .long("cache-dir")
+                .takes_value(true)
+                .help("Directory of the cache (default ~/.cache/radius2)"),
+        )
+        .arg(
+            Arg::with_name("signatures")
+                .long("signatures")
+                .takes_value(true)
+                .multiple(true)
+                .help("Zignature or FLIRT files to find libc functions to simulate"),
+        )
         .arg(
             Arg::with_name("fuzz")
                 .short("F")
                 .long("fuzz")
                 .takes_value(true)
-                .help("Generate testcases and write to supplied dir"),
+                .help("Generate a testcase for every branch and write to supplied dir"),
+        )
+        .arg(
+            Arg::with_name("concolic")
+                .long("concolic")
+                .takes_value(true)
+                .help("Generate new inputs from the seeds concolically and write to supplied dir"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_names(&["NAME", "PATH"])
+                .multiple(true)
+                .help("Seed file or corpus directory with concrete values for a symbol"),
         )
         .arg(
             Arg::with_name("max")
@@ -209,6 +282,17 @@ fn main() {
                 .multiple(true)
                 .help("Breakpoint code xrefs to strings"),
         )
+        .arg(
+            Arg::with_name("break_exit")
+                .long("break-exit")
+                .help("Break when the program exits"),
+        )
+        .arg(
+            Arg::with_name("exit_code")
+                .long("exit-code")
+                .takes_value(true)
+                .help("Break when the program exits with this code"),
+        )
         .arg(
             Arg::with_name("avoid")
                 .short("x")
@@ -258,7 +342,7 @@ fn main() {
                 .long("env")
                 .takes_value(true)
                 .multiple(true)
-                .help("Environment variable for the target program"),
+                .help("Environment variable for the target program (NAME=SYMBOL for symbolic)"),
         )
         .arg(
             Arg::with_name("symbol")
@@ -268,6 +352,13 @@ fn main() {
                 .multiple(true)
                 .help("Create a symbolic value"),
         )
+        .arg(
+            Arg::with_name("sym_length")
+                .long("sym-length")
+                .takes_value(true)
+                .multiple(true)
+                .help("Give the file filled by SYMBOL a symbolic length"),
+        )
         .arg(
             Arg::with_name("set")
                 .short("S")
@@ -316,6 +407,41 @@ fn main() {
                 .multiple(true)
                 .help("Hook the provided address with an ESIL expression"),
         )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .value_names(&["ADDR", "LEN"])
+                .multiple(true)
+                .help("Print reads and writes of LEN bytes at ADDR"),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .takes_value(true)
+                .help("Write the execution trace of the result state to file"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .help("Replay a trace file instead of exploring"),
+        )
+        .arg(
+            Arg::with_name("fast")
+                .long("fast")
+                .help("Execute concrete instructions without the solver until a symbolic value is used"),
+        )
+        .arg(
+            Arg::with_name("lift")
+                .long("lift")
+                .help("Execute instructions lifted to an IR instead of parsing their ESIL"),
+        )
+        .arg(
+            Arg::with_name("coverage")
+                .long("coverage")
+                .takes_value(true)
+                .help("Write coverage to file as drcov, .txt addresses or .r2 script"),
+        )
         .arg(
             Arg::with_name("r2_command")
                 .short("r")
@@ -375,17 +501,30 @@ fn main() {
         RadiusOption::SimAll(all_sims),
         RadiusOption::LoadLibs(!libpaths.is_empty()),
         RadiusOption::LoadPlugins(plugins),
+        RadiusOption::Trace(occurs!(matches, "trace") || occurs!(matches, "replay")),
+        RadiusOption::Coverage(occurs!(matches, "coverage")),
+        RadiusOption::FastConcrete(occurs!(matches, "fast")),
+        RadiusOption::Lift(occurs!(matches, "lift")),
+        RadiusOption::Cache(!occurs!(matches, "no_cache")),
     ];
 
     for lib in libpaths {
         options.push(RadiusOption::LibPath(lib.to_owned()));
     }
 
+    let signatures: Vec<&str> = collect!(matches, "signatures");
+    for sig in signatures {
+        options.push(RadiusOption::Signatures(sig.to_owned()));
+    }
+
+    if let Some(dir) = matches.value_of("cache_dir") {
+        options.push(RadiusOption::CacheDir(dir.to_owned()));
+    }
+
     let threads: usize = 1;
     let start = Instant::now();
 
     let path = matches.value_of("path").unwrap_or("-");
-    let dir = Path::new(matches.value_of("fuzz").unwrap_or("."));
 
     // just a guardrail cuz the error otherwise is vv unclear
     if path != "-" && !path.contains(':') && fs::metadata(path).is_err() {
@@ -395,10 +534,6 @@ fn main() {
 
     let mut radius = Radius::new_with_options(matches.value_of("path"), &options);
 
-    if !dir.exists() {
-        fs::create_dir(&dir).unwrap();
-    }
-
     let max_states = matches
         .value_of("max")
         .unwrap_or("256")
@@ -407,12 +542,14 @@ fn main() {
 
     // translate pcode to ESIL
     if occurs!(matches, "ghidra") {
+        radius.r2api.key_cache("pdgp");
         radius.cmd("pdgp").unwrap_or_default();
     }
 
-    // execute provided r2 commands
+    // execute provided r2 commands, they can change what is cached
     let cmds: Vec<&str> = collect!(matches, "r2_command");
     for cmd in cmds {
+        radius.r2api.key_cache(cmd);
         let r = radius.cmd(cmd);
         if occurs!(matches, "verbose") && r.is_ok() {
             println!("{}", r.unwrap());
@@ -477,8 +614,43 @@ fn main() {
         radius.breakpoint(bp);
     }
 
+    // print accesses to watched memory
+    let watches: Vec<&str> = collect!(matches, "watch");
+    for i in 0..watches.len() / 2 {
+        let addr = radius.get_address(watches[2 * i]).unwrap();
+        let len = radius.get_address(watches[2 * i + 1]).unwrap_or(1) as usize;
+        radius.watch(addr, len, "rw", move |_state, access| {
+            println!(
+                "{} 0x{:08x} {} 0x{:x}[{}] : {:?} -> {:?}",
+                "watch".yellow(),
+                access.pc,
+                access.perm,
+                addr,
+                len,
+                access.old,
+                access.new
+            );
+        });
+    }
+
     radius.avoid(&avoid);
 
+    if let Some(code) = matches.value_of("exit_code") {
+        // only the low byte is kept so -1 is the same as 255
+        let parsed = if let Some(hex) = code.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else {
+            code.parse::<i64>()
+        };
+        let code = parsed.unwrap_or_else(|e| {
+            eprintln!("could not parse exit code {}: {}", code, e);
+            process::exit(1);
+        });
+        radius.break_on_exit(Some(code as u64));
+    } else if occurs!(matches, "break_exit") {
+        radius.break_on_exit(None);
+    }
+
     for merge in merges {
         radius.mergepoint(merge);
     }
@@ -502,6 +674,7 @@ fn main() {
     let mut symbol_map = HashMap::new();
     let mut symbol_types = HashMap::new();
     let mut has_stdin = false;
+    let mut generator = TestGenerator::new(matches.value_of("fuzz").unwrap_or("."), vec![]);
 
     let symbols: Vec<&str> = collect!(matches, "symbol");
     for i in 0..matches.occurrences_of("symbol") as usize {
@@ -521,7 +694,11 @@ fn main() {
         let sym_value = state.symbolic_value(sym_name, length);
         //symbol_types.insert(sym_name, symbols[3 * i + 2]);
         symbol_map.insert(sym_name, sym_value.as_bv().unwrap());
-        state.context.insert(sym_name.to_owned(), vec![sym_value]);
+
+        // AT_RANDOM is concrete unless it is declared as a symbol
+        if sym_name != "AT_RANDOM" || !radius.set_random(&mut state, &sym_value) {
+            state.context.insert(sym_name.to_owned(), vec![sym_value]);
+        }
 
         if sym_name.to_lowercase() == "stdin" {
             files.extend(vec!["0", sym_name]);
@@ -547,8 +724,26 @@ fn main() {
 
         for (t, args) in [argvs, envs].iter().enumerate() {
             for arg in args {
-                let value = if let Some(sym) = symbol_map.get(arg) {
-                    Value::Symbolic(sym.clone(), 0)
+                let env_sym = arg
+                    .split_once('=')
+                    .filter(|_| t == 1)
+                    .and_then(|(name, val)| symbol_map.get(val).map(|sym| (name, sym)));
+
+                let (value, length) = if let Some(sym) = symbol_map.get(arg) {
+                    (Value::Symbolic(sym.clone(), 0), sym.get_width() as usize / 8)
+                } else if let Some((name, sym)) = env_sym {
+                    // NAME=symbol, the value of the variable is symbolic
+                    let sym = Value::Symbolic(sym.clone(), 0);
+                    let len = sym.size() as usize / 8;
+                    let mut bytes: Vec<Value> = format!("{}=", name)
+                        .bytes()
+                        .map(|b| Value::Concrete(b as u64, 0))
+                        .collect();
+
+                    let mut sym_bytes = vec![Value::Concrete(0, 0); len];
+                    state.memory.unpack(&sym, len, &mut sym_bytes);
+                    bytes.extend(sym_bytes);
+                    (state.memory.pack(&bytes), bytes.len())
                 } else {
                     // @ signs to prevent parsing as radius args
                     let narg = if arg.starts_with("@") {
@@ -563,12 +758,14 @@ fn main() {
                         .map(|b| Value::Concrete(*b as u64, 0))
                         .collect();
 
-                    state.memory.pack(&bytes)
+                    (state.memory.pack(&bytes), bytes.len())
                 };
 
                 if t == 0 {
+                    generator.add_input(InputKind::Argv(argv.len()), value.clone(), length);
                     argv.push(value);
                 } else {
+                    generator.add_input(InputKind::Env(envv.len()), value.clone(), length);
                     envv.push(value);
                 }
             }
@@ -597,7 +794,19 @@ fn main() {
         }
     }
 
+    // mount host directories
+    for (name, mode) in [("mount", MountMode::ReadOnly), ("mount_cow", MountMode::CopyOnWrite)] {
+        let mounts: Vec<&str> = collect!(matches, name);
+        for i in 0..mounts.len() / 2 {
+            state
+                .filesystem
+                .mount(mounts[2 * i], mounts[2 * i + 1], mode.clone());
+        }
+    }
+
     // collect the added files
+    let sym_lengths: Vec<&str> = collect!(matches, "sym_length");
+    let mut length_map = HashMap::new();
     for i in 0..files.len() / 2usize {
         let file = files[2 * i];
         let name = files[2 * i + 1];
@@ -605,22 +814,42 @@ fn main() {
             let length = symbol_map[name].get_width() as usize;
             let value = Value::Symbolic(sym.clone(), 0);
             let bytes = state.unpack(&value, length / 8);
-            if let Ok(fd) = files[2 * i].parse() {
+            let len_name = format!("{}_length", name);
+            let sym_len = if let Ok(fd) = files[2 * i].parse() {
+                generator.add_input(InputKind::Fd(fd), value, length / 8);
                 state.filesystem.fill(fd, &bytes);
+                if sym_lengths.contains(&name) {
+                    Some(state.set_file_length(fd, &len_name))
+                } else {
+                    None
+                }
             } else {
+                generator.add_input(InputKind::File(file.to_owned()), value, length / 8);
                 state.filesystem.add_file(files[2 * i], &bytes);
+                if sym_lengths.contains(&name) {
+                    state.set_path_length(file, &len_name)
+                } else {
+                    None
+                }
+            };
+
+            if let Some(sym_len) = sym_len {
+                length_map.insert(name, sym_len.as_bv().unwrap());
             }
         } else {
             let content = files[2 * i + 1];
+            let bytes: Vec<Value> = content
+                .as_bytes()
+                .iter()
+                .map(|b| Value::Concrete(*b as u64, 0))
+                .collect();
+
+            let value = state.memory.pack(&bytes);
             if let Ok(fd) = file.parse() {
+                generator.add_input(InputKind::Fd(fd), value, bytes.len());
                 state.fill_file_string(fd, content)
             } else {
-                let bytes: Vec<Value> = content
-                    .as_bytes()
-                    .iter()
-                    .map(|b| Value::Concrete(*b as u64, 0))
-                    .collect();
-
+                generator.add_input(InputKind::File(file.to_owned()), value, bytes.len());
                 state.filesystem.add_file(file, &bytes);
             }
         }
@@ -688,8 +917,63 @@ fn main() {
     // run the thing
     let run_start = Instant::now();
 
-    if !fuzz {
-        let result = if !occurs!(matches, "merge_all") {
+    if let Some(dir) = matches.value_of("concolic") {
+        let inputs = symbol_names
+            .iter()
+            .map(|name| Value::Symbolic(symbol_map[name].clone(), 0))
+            .collect();
+        let mut concolic = Concolic::new(inputs);
+
+        // every file in a corpus is a separate seed
+        let seeds: Vec<&str> = collect!(matches, "seed");
+        let mut corpus = vec![vec![]; symbol_names.len()];
+        for i in 0..seeds.len() / 2 {
+            let path = seeds[2 * i + 1];
+            if let Some(index) = symbol_names.iter().position(|name| *name == seeds[2 * i]) {
+                match read_seeds(path) {
+                    Ok(data) => corpus[index].extend(data),
+                    Err(e) => eprintln!("could not read seed {}: {}", path, e),
+                }
+            }
+        }
+
+        // symbols with fewer seeds reuse their last one
+        let count = corpus.iter().map(|seeds| seeds.len()).max().unwrap_or(0);
+        for i in 0..count.max(1) {
+            let seed: Vec<Vec<u8>> = corpus
+                .iter()
+                .map(|seeds| seeds.get(i).or(seeds.last()).cloned().unwrap_or_default())
+                .collect();
+            concolic.add_seed(&seed);
+        }
+
+        radius.concolic(state, &mut concolic);
+        match concolic.write(dir, &symbol_names) {
+            Ok(count) if !do_json => println!("wrote {} inputs to {}", count, dir),
+            Err(e) => eprintln!("could not write inputs: {}", e),
+            _ => {}
+        }
+
+        if profile {
+            let usecs = run_start.elapsed().as_micros();
+            let steps = radius.get_steps();
+            println!(
+                "run time:\t{}\ninstructions:\t{}\ninstr/usec:\t{:0.6}\nruns:\t{}\nedges:\t{}",
+                usecs,
+                steps,
+                (steps as f64 / usecs as f64),
+                concolic.runs,
+                concolic.edges.len()
+            );
+        }
+    } else if !fuzz {
+        let result = if let Some(path) = matches.value_of("replay") {
+            let trace = Trace::read(path).unwrap_or_else(|e| {
+                eprintln!("could not read trace {}: {}", path, e);
+                process::exit(1);
+            });
+            radius.replay(state, &trace)
+        } else if !occurs!(matches, "merge_all") {
             radius.run(state, threads)
         } else {
             let mut states = radius.run_all(state);
@@ -718,6 +1002,12 @@ fn main() {
         }
 
         if let Some(mut end_state) = result {
+            if let (Some(path), Some(trace)) = (matches.value_of("trace"), &end_state.trace) {
+                if let Err(e) = trace.write(path) {
+                    eprintln!("could not write trace {}: {}", path, e);
+                }
+            }
+
             // collect the ESIL strings to evaluate after running
             let constraints: Vec<&str> = collect!(matches, "constrain_after");
             for i in 0..matches.occurrences_of("constrain_after") as usize {
@@ -773,6 +1063,15 @@ fn main() {
             }
             let solve_start = Instant::now();
 
+            // find the shortest inputs with symbolic lengths
+            let mut lengths = HashMap::new();
+            for (symbol, len) in &length_map {
+                let len = Value::Symbolic(end_state.translate(len).unwrap(), 0);
+                let min = end_state.minimize(&len);
+                end_state.assert(&len.eq(&vc(min)));
+                lengths.insert(*symbol, min as usize);
+            }
+
             if !do_json {
                 println!()
             };
@@ -780,14 +1079,23 @@ fn main() {
                 let val = Value::Symbolic(end_state.translate(&symbol_map[symbol]).unwrap(), 0);
 
                 if let Some(bv) = end_state.solver.eval_to_bv(&val) {
-                    let str_opt = end_state.evaluate_string_bv(&bv);
+                    let mut str_opt = end_state.evaluate_string_bv(&bv);
                     let sym_type = symbol_types[symbol];
-                    let hex = end_state.solver.hex_solution(&bv).unwrap_or_default();
+                    let mut hex = end_state.solver.hex_solution(&bv).unwrap_or_default();
+
+                    // only show the input up to its length
+                    if let Some(len) = lengths.get(symbol) {
+                        let bytes = end_state.evaluate_bytes_bv(&bv).unwrap_or_default();
+                        let bytes = &bytes[..(*len).min(bytes.len())];
+                        str_opt = String::from_utf8(bytes.to_owned()).ok();
+                        hex = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                    }
                     if !do_json {
                         if sym_type == "str" && str_opt.is_some() {
                             println!("  {} : {:?}", symbol.green(), str_opt.unwrap());
                         } else if sym_type == "str" {
-                            let bytes = end_state.evaluate_bytes_bv(&bv).unwrap();
+                            let mut bytes = end_state.evaluate_bytes_bv(&bv).unwrap();
+                            bytes.truncate(*lengths.get(symbol).unwrap_or(&bytes.len()));
                             println!("  {} : \"{}\"", symbol.green(), show(&bytes));
                         } else {
                             println!("  {} : 0x{}", symbol.green(), hex);
@@ -844,79 +1152,47 @@ fn main() {
                     json_out.stderr = out;
                 }
             }
+            if let Some(dir) = matches.value_of("export_fs") {
+                match end_state.export_filesystem(dir) {
+                    Ok(count) if !do_json => println!("wrote {} files to {}", count, dir),
+                    Err(e) => eprintln!("could not export filesystem: {}", e),
+                    _ => {}
+                }
+            }
         }
 
         if do_json {
             println!("{}", serde_json::to_string(&json_out).unwrap_or_default());
         }
     } else {
-        // TODO this is temporary until I integrate a real testcase gen mode in processor
-        let mut pcs: HashMap<u64, usize> = HashMap::new();
-        let mut states = VecDeque::new();
-        let mut solutions: HashSet<Vec<u8>> = HashSet::new();
-        states.push_back(state);
-
-        let mut file_counts: HashMap<&str, usize> = HashMap::new();
-
-        for symbol in symbol_map.keys() {
-            file_counts.insert(symbol, 0);
+        generator.max_states = max_states;
+        if let Err(e) = radius.generate(state, &mut generator) {
+            eprintln!("could not write test cases: {}", e);
         }
-
-        while !states.is_empty() {
-            let num_states = states.len();
-            let mut s = states.pop_front().unwrap();
-            let cpc = s.registers.get_pc().as_u64().unwrap();
-
-            radius.processor.fetch_instruction(&mut s, cpc);
-            let tn = radius.processor.instructions[&cpc].instruction.type_num;
-
-            let new_states = radius.processor.run(s, RunMode::Step);
-            //new_states.push(s);
-
-            for mut new_state in new_states {
-                let pc = new_state.registers.get_pc().as_u64().unwrap();
-                let active = new_state.status == StateStatus::Active;
-                if pcs.entry(pc).and_modify(|c| *c += 1).or_insert(1) > &mut 1 {
-                    if active && num_states <= max_states {
-                        states.push_back(new_state);
-                    }
-                    continue;
-                }
-                // after (conditional) calls and jumps
-                if tn & 0xf == 1 || tn & 0xf == 4 {
-                    for symbol in symbol_map.keys() {
-                        let val = new_state.translate(&symbol_map[symbol]).unwrap();
-
-                        if let Some(bytes) = new_state.evaluate_bytes_bv(&val) {
-                            if !solutions.contains(&bytes) {
-                                let c = file_counts[symbol];
-                                let filename = &format!("{}{:04}", symbol, c);
-                                fs::write(dir.join(filename), &bytes).unwrap();
-                                file_counts.insert(symbol, c + 1);
-                                solutions.insert(bytes);
-                            }
-                        }
-                    }
-                }
-                if active {
-                    states.push_front(new_state);
-                }
-            }
+        if generator.dropped > 0 {
+            eprintln!("dropped {} states over the limit of {}", generator.dropped, max_states);
         }
 
         if profile {
             let usecs = run_start.elapsed().as_micros();
             let steps = radius.get_steps();
             println!(
-                "run time:\t{}\ninstructions:\t{}\ninstr/usec:\t{:0.6}\ngenerated:\t{}",
+                "run time:\t{}\ninstructions:\t{}\ninstr/usec:\t{:0.6}\ngenerated:\t{}\ncrashes:\t{}",
                 usecs,
                 steps,
                 (steps as f64 / usecs as f64),
-                file_counts.values().sum::<usize>()
+                generator.cases,
+                generator.crash_cases
             );
         }
     }
 
+    if let Some(path) = matches.value_of("coverage") {
+        if let Err(e) = radius.get_coverage().write(path) {
+            eprintln!("could not write coverage {}: {}", path, e);
+        }
+    }
+
     if profile {
         println!("total time:\t{}", start.elapsed().as_micros());
     }
@@ -180,30 +180,41 @@ impl Memory {
     }
 
     // its hard to overstate how much all of this sucks
-    pub fn add_std_streams(&mut self) {
-        let mut fd = 0;
+    /// Allocate FILE structs for stdin, stdout and stderr and point the
+    /// binary's stream globals at them. Returns the FILE addresses by fd
+    pub fn add_std_streams(&mut self) -> Vec<u64> {
+        let layout = get_file_layout(&self.r2api.info.bin.os, self.bits);
+        let mut streams = vec![];
         let stds = ["stdin", "stdout", "stderr"];
-        for std in &stds {
+        for (fd, std) in stds.iter().enumerate() {
             let mut addr = self.r2api.get_address(&("obj.".to_owned() + std)).unwrap();
-            let mut offset = 112; // linux
             if addr == 0 {
                 addr = self
                     .r2api
                     .get_address(&("reloc.__".to_owned() + std + "p"))
                     .unwrap();
-                offset = 18; // macos, this is jank af
             }
 
             if addr != 0 {
-                // from libc.rs should be in a common place
-                let file_struct = self.alloc(&Value::Concrete(216, 0));
-                self.write_value(file_struct + offset, &Value::Concrete(fd, 0), 4);
+                let flags = if fd == 0 {
+                    layout.magic | layout.no_writes
+                } else {
+                    layout.magic | layout.no_reads
+                };
+
+                let file_struct = self.alloc(&Value::Concrete(layout.size, 0));
+                let fd = Value::Concrete(fd as u64, 0);
+                let flags = Value::Concrete(flags, 0);
+                self.write_value(file_struct + layout.flags, &flags, layout.flags_size);
+                self.write_value(file_struct + layout.fileno, &fd, layout.fileno_size);
                 self.write_value(
                     addr,
                     &Value::Concrete(file_struct, 0),
                     (self.bits / 8) as usize,
                 );
-                fd += 1;
+                streams.push(file_struct);
+            } else {
+                streams.push(0);
             }
         }
 
@@ -217,6 +228,7 @@ impl Memory {
                 (self.bits / 8) as usize,
             );
         }
+        streams
     }
 
     pub fn brk(&mut self, address: u64) -> u64 {
@@ -778,3 +790,57 @@ impl Heap {
         }
     }
 }
+
+/// Offsets and flag values of the FILE struct for a platform
+#[derive(Debug, Clone)]
+pub struct FileLayout {
+    pub size: u64,
+    pub flags: u64,
+    pub flags_size: usize,
+    pub fileno: u64,
+    pub fileno_size: usize,
+    pub magic: u64,
+    pub no_reads: u64,  // flag set for write only streams
+    pub no_writes: u64, // flag set for read only streams
+    pub eof: u64,
+    pub err: u64,
+}
+
+/// Get the FILE layout, glibc `_IO_FILE` or darwin `__sFILE`
+pub fn get_file_layout(os: &str, bits: u64) -> FileLayout {
+    if os == "darwin" {
+        // darwin uses __SRD and __SWR instead of NO flags, close enough
+        let (size, flags) = if bits == 64 { (152, 16) } else { (88, 12) };
+        FileLayout {
+            size,
+            flags,
+            flags_size: 2,
+            fileno: flags + 2,
+            fileno_size: 2,
+            magic: 0,
+            no_reads: 0x8,
+            no_writes: 0x4,
+            eof: 0x20,
+            err: 0x40,
+        }
+    } else {
+        // _fileno offset of _IO_FILE, LINUX_FILENO_OFFSET in the libc sims
+        let (size, fileno) = if bits == 64 {
+            (216, 112)
+        } else {
+            (148, 56)
+        };
+        FileLayout {
+            size,
+            flags: 0,
+            flags_size: 4,
+            fileno,
+            fileno_size: 4,
+            magic: 0xfbad0000,
+            no_reads: 0x4,
+            no_writes: 0x8,
+            eof: 0x10,
+            err: 0x20,
+        }
+    }
+}
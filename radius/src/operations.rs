@@ -1,5 +1,6 @@
-use crate::state::{StackItem, State};
+use crate::state::{Event, EventContext, EventTrigger, StackItem, State};
 use crate::value::{vc, Value};
+use serde::{Deserialize, Serialize};
 use std::f64;
 
 pub const OPS: [&str; 16] = [
@@ -8,7 +9,7 @@ pub const OPS: [&str; 16] = [
 
 pub const SIZE: u64 = 64;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Operations {
     Trap,
     Interrupt,
@@ -264,7 +265,11 @@ pub fn pop_value(state: &mut State, set_size: bool, sign_ext: bool) -> Value {
                 let reg = &state.registers.indexes[index];
                 state.esil.last_sz = reg.reg_info.size as usize;
             }
-            state.registers.get_value(index)
+            let value = state.registers.get_value(index);
+            if state.has_event_hooks {
+                register_read(state, index, &value);
+            }
+            value
         }
     };
 
@@ -291,6 +296,14 @@ pub fn pop_value(state: &mut State, set_size: bool, sign_ext: bool) -> Value {
     }
 }
 
+fn register_read(state: &mut State, index: usize, value: &Value) {
+    let name = state.registers.indexes[index].reg_info.name.to_owned();
+    state.do_hooked(
+        &Event::RegisterRead(EventTrigger::After),
+        &EventContext::RegisterContext(name, value.to_owned()),
+    );
+}
+
 #[inline]
 pub fn pop_stack_value(
     state: &mut State,
@@ -307,7 +320,11 @@ pub fn pop_stack_value(
                 let reg = state.registers.indexes.get(index).unwrap();
                 state.esil.last_sz = reg.reg_info.size as usize;
             }
-            state.registers.get_value(index)
+            let value = state.registers.get_value(index);
+            if state.has_event_hooks {
+                register_read(state, index, &value);
+            }
+            value
         }
     };
 
@@ -378,6 +395,14 @@ pub fn do_equal(state: &mut State, reg: StackItem, value: Value, set_esil: bool)
         let size = register.reg_info.size as usize;
         let prev = state.registers.get_value(index);
 
+        if state.has_event_hooks {
+            let name = register.reg_info.name.to_owned();
+            state.do_hooked(
+                &Event::RegisterWrite(EventTrigger::Before),
+                &EventContext::RegisterContext(name, value.to_owned()),
+            );
+        }
+
         if let Some(cond) = &state.condition {
             state.registers.set_value(
                 index,
@@ -389,6 +414,14 @@ pub fn do_equal(state: &mut State, reg: StackItem, value: Value, set_esil: bool)
             state.registers.set_value(index, value.to_owned());
         }
 
+        if state.has_event_hooks {
+            let name = state.registers.indexes[index].reg_info.name.to_owned();
+            state.do_hooked(
+                &Event::RegisterWrite(EventTrigger::After),
+                &EventContext::RegisterContext(name, state.registers.get_value(index)),
+            );
+        }
+
         if set_esil {
             state.esil.last_sz = size;
             state.esil.current = value;
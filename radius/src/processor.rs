@@ -2,16 +2,26 @@ use crate::operations::{
     do_operation, pop_concrete, pop_stack_value, pop_value, push_value, Operations, OPS,
 };
 use crate::r2_api::{hex_decode, CallingConvention, Instruction, Syscall};
+use crate::solver::BitVec;
 use crate::value::{vc, Value};
 
 use crate::state::{
-    Event, EventContext, EventTrigger, ExecMode, StackItem, State, StateStatus, DO_EVENT_HOOKS,
+    Event, EventContext, EventTrigger, ExecMode, StackItem, State, StateStatus,
 };
 
-use crate::sims::syscall::syscall;
-use crate::sims::{Sim, SimMethod};
-
-use std::collections::BinaryHeap;
+use crate::sims::dalvik::{self, Invoke};
+use crate::sims::syscall::{get_syscall_num, syscall};
+use crate::sims::libc::push_return;
+use crate::sims::{Sim, SimCall, SimFrame, SimFunction};
+use crate::concolic::{Branch, Concolic};
+use crate::coverage::Coverage;
+use crate::fast::{self, FastWord};
+use crate::ir::{self, Lifted};
+use crate::testgen::TestGenerator;
+use crate::trace::{Trace, TraceEntry};
+
+use std::collections::{BinaryHeap, VecDeque};
+use std::io;
 use std::mem;
 use std::rc::Rc;
 use colored::*;
@@ -23,6 +33,7 @@ const INSTR_NUM: usize = 64;
 // const COLOR: bool = true;
 const CALL_TYPE: i64 = 3;
 const RETN_TYPE: i64 = 5;
+const UJMP_TYPE: i64 = 2; // the low byte of ijmp, rjmp, mjmp etc. too
 // const NOP_TYPE: i64 = 8;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -43,19 +54,26 @@ pub enum RunMode {
 
 pub type HookMethod = fn(&mut State) -> bool;
 
+/// A hook callback, a `HookMethod` or any closure
+pub type Hook = Rc<dyn Fn(&mut State) -> bool>;
+
 #[derive(Clone)]
 pub struct Processor {
     pub instructions: BTreeMap<u64, InstructionEntry>,
-    pub hooks: HashMap<u64, Vec<HookMethod>>,
+    pub hooks: HashMap<u64, Vec<Hook>>,
     pub esil_hooks: HashMap<u64, Vec<String>>,
     pub sims: HashMap<u64, Sim>,
-    pub traps: HashMap<u64, SimMethod>,
-    pub interrupts: HashMap<u64, SimMethod>,
+    pub traps: HashMap<u64, SimFunction>,
+    pub interrupts: HashMap<u64, SimFunction>,
     pub syscalls: HashMap<u64, Syscall>,
+    pub dalvik_sims: HashMap<String, Sim>,
+    pub java_classes: HashMap<String, bool>,
     pub breakpoints: HashSet<u64>,
     pub mergepoints: HashSet<u64>,
     pub avoidpoints: HashSet<u64>,
     pub visited: HashSet<u64>,
+    pub blocks: HashSet<u64>,
+    pub switches: HashMap<u64, Vec<u64>>, // case targets of switch jumps
     pub merges: BTreeMap<u64, State>,
     pub crashes: Vec<State>,
     pub selfmodify: bool,
@@ -66,7 +84,12 @@ pub struct Processor {
     pub automerge: bool,
     pub color: bool,
     pub topological: bool, // execute blocks in topological sort order
+    pub break_exit: bool,  // break on exit instead of dropping the state
+    pub exit_code: Option<u64>, // required exit code when breaking on exit
     pub steps: u64,        // number of state steps
+    pub coverage: bool,    // collect visited instructions and blocks
+    pub fast: bool,        // run fully concrete instructions without the solver
+    pub lift: bool,        // execute instructions lifted to the IR
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -75,6 +98,7 @@ pub enum InstructionFlag {
     Hook,
     ESILHook,
     Sim,
+    Invoke,
     Merge,
     Avoid,
     Break,
@@ -85,6 +109,8 @@ pub struct InstructionEntry {
     pub instruction: Instruction,
     pub tokens: Vec<Word>,
     pub flags: HashSet<InstructionFlag>,
+    pub fast: Option<Vec<FastWord>>, // tokens compiled for the concrete fast path
+    pub ir: Option<Lifted>,          // lifted tokens, executed instead of parsing them
 }
 
 impl Processor {
@@ -106,10 +132,14 @@ impl Processor {
             traps: HashMap::new(),
             interrupts: HashMap::new(),
             syscalls: HashMap::new(),
+            dalvik_sims: HashMap::new(),
+            java_classes: HashMap::new(),
             breakpoints: HashSet::new(),
             mergepoints: HashSet::new(),
             avoidpoints: HashSet::new(),
             visited: HashSet::new(),
+            blocks: HashSet::new(),
+            switches: HashMap::new(),
             merges: BTreeMap::new(),
             crashes: vec![],
             selfmodify,
@@ -120,6 +150,11 @@ impl Processor {
             topological,
             automerge,
             color,
+            break_exit: false,
+            exit_code: None,
+            coverage: false,
+            fast: false,
+            lift: false,
             steps: 0, //states: vec!()
         }
     }
@@ -170,6 +205,23 @@ impl Processor {
         tokens
     }
 
+    /// tokenize using the disk cache if it is enabled, tokens are not
+    /// cached when a word is substituted from the context
+    pub fn tokenize_cached(&self, state: &mut State, esil: &str) -> Vec<Word> {
+        let substituted = esil.split(',').any(|word| state.context.contains_key(word));
+        let disk = state.r2api.disk.clone().filter(|_| !substituted);
+        if let Some(disk) = disk {
+            if let Some(words) = disk.lock().unwrap().get_tokens(esil) {
+                return words;
+            }
+            let words = self.tokenize(state, esil);
+            disk.lock().unwrap().add_tokens(esil, &words);
+            words
+        } else {
+            self.tokenize(state, esil)
+        }
+    }
+
     /// attempt to tokenize word as number literal (eg. 0x8)
     #[inline]
     pub fn get_literal(&self, word: &str) -> Option<Word> {
@@ -234,7 +286,7 @@ impl Processor {
 
     // perform an emulated syscall using the definitions in syscall.rs
     pub fn do_syscall(&self, state: &mut State) {
-        let sys_val = state.registers.get_with_alias("SN");
+        let sys_val = get_syscall_num(state);
         let sys_num = state.solver.evalcon_to_u64(&sys_val).unwrap();
         //let pc = state.registers.get_pc().as_u64().unwrap();
 
@@ -244,8 +296,23 @@ impl Processor {
             for arg in cc.args {
                 args.push(state.registers.get(arg.as_str()));
             }
+
+            if state.has_event_hooks {
+                let context = EventContext::SyscallContext(sys_val.clone(), args.clone());
+                state.do_hooked(&Event::Syscall(EventTrigger::Before), &context);
+            }
+
             let ret = syscall(sys.name.as_str(), state, &args);
+            if state.trace.is_some() {
+                let pc = state.esil.prev_pc.as_u64().unwrap_or(0);
+                state.record(TraceEntry::Syscall { pc, num: sys_num, ret: ret.as_u64() });
+            }
             state.registers.set(cc.ret.as_str(), ret);
+
+            if state.has_event_hooks {
+                let context = EventContext::SyscallContext(sys_val, args);
+                state.do_hooked(&Event::Syscall(EventTrigger::After), &context);
+            }
         }
     }
 
@@ -396,16 +463,34 @@ impl Processor {
                         }
                         Operations::Trap => {
                             let trap = pop_concrete(state, false, false);
-                            let sys_val = state.registers.get_with_alias("SN");
+                            let sys_val = get_syscall_num(state);
                             if let Some(trap_sim) = self.traps.get(&trap) {
                                 // provide syscall args
                                 let cc = state.r2api.get_syscall_cc().unwrap_or_default();
-                                let mut args = vec![sys_val];
+                                let mut args = vec![sys_val.clone()];
                                 for arg in cc.args {
                                     args.push(state.registers.get(arg.as_str()));
                                 }
+
+                                if state.has_event_hooks {
+                                    let context =
+                                        EventContext::SyscallContext(sys_val, args[1..].to_vec());
+                                    state.do_hooked(&Event::Syscall(EventTrigger::Before), &context);
+                                }
+
                                 let ret = trap_sim(state, &args);
+                                if state.trace.is_some() {
+                                    let pc = state.esil.prev_pc.as_u64().unwrap_or(0);
+                                    let num = state.solver.evalcon_to_u64(&args[0]).unwrap_or(trap);
+                                    state.record(TraceEntry::Syscall { pc, num, ret: ret.as_u64() });
+                                }
                                 state.registers.set(cc.ret.as_str(), ret);
+
+                                if state.has_event_hooks {
+                                    let context =
+                                        EventContext::SyscallContext(args[0].clone(), args[1..].to_vec());
+                                    state.do_hooked(&Event::Syscall(EventTrigger::After), &context);
+                                }
                             }
                         }
                         Operations::Syscall => self.do_syscall(state),
@@ -502,6 +587,7 @@ impl Processor {
                     new_tokens.push(word.to_owned());
                 }
             }
+            mut_prev_instr.fast = fast::compile(&new_tokens);
             mut_prev_instr.tokens = new_tokens;
             self.instructions.insert(prev_pc, mut_prev_instr);
         }
@@ -541,6 +627,8 @@ impl Processor {
         instr: &Instruction,
         flags: &HashSet<InstructionFlag>,
         words: &[Word],
+        fast_words: Option<&[FastWord]>,
+        lifted: Option<&Lifted>,
     ) {
         if state.check && state.check_crash(&vc(instr.offset), &vc(instr.size), 'x') {
             return;
@@ -594,15 +682,51 @@ impl Processor {
             if new_flags.contains(&InstructionFlag::Sim) {
                 let sim = &self.sims[&pc];
                 let cc = state.r2api.get_cc(pc).unwrap_or_default();
-                let args = self.get_args(state, &cc);
 
-                let ret = (sim.function)(state, &args);
-                state.registers.set_with_alias(cc.ret.as_str(), ret);
+                // a guest function called by the sim returned to it
+                let ret = if let Some(frame) = self.sim_return(state, pc) {
+                    let mut args = frame.data;
+                    args.push(state.registers.get_with_alias(cc.ret.as_str()));
+                    (frame.resume)(state, &args)
+                } else {
+                    let args = self.get_args(state, &cc);
+                    if state.has_event_hooks {
+                        let context = EventContext::SimContext(sim.symbol.to_owned(), args.clone());
+                        state.do_hooked(&Event::SimCall(EventTrigger::Before), &context);
+                    }
+
+                    let ret = (sim.function)(state, &args);
 
-                // don't ret if sim changes the PC value
-                // this is bad hax because thats all i do
-                if state.registers.get_pc() == vc(pc) {
-                    self.ret(state);
+                    if state.has_event_hooks {
+                        let context = EventContext::SimContext(sim.symbol.to_owned(), args);
+                        state.do_hooked(&Event::SimCall(EventTrigger::After), &context);
+                    }
+                    ret
+                };
+
+                if state.trace.is_some() {
+                    let name = sim.symbol.to_owned();
+                    state.record(TraceEntry::Sim { pc, name, ret: ret.as_u64() });
+                }
+
+                if let Some(call) = state.sim_call.take() {
+                    self.sim_call(state, pc, call);
+                } else {
+                    state.registers.set_with_alias(cc.ret.as_str(), ret);
+
+                    // don't ret if sim changes the PC value
+                    // this is bad hax because thats all i do
+                    if state.registers.get_pc() == vc(pc) {
+                        self.ret(state);
+                    }
+                }
+                skip = true;
+                update = false;
+            }
+            if new_flags.contains(&InstructionFlag::Invoke) {
+                dalvik::invoke(state, instr, &self.dalvik_sims);
+                if instr.type_num == CALL_TYPE {
+                    state.backtrace.pop();
                 }
                 skip = true;
                 update = false;
@@ -633,6 +757,15 @@ impl Processor {
             if state.strict && instr.disasm == "invalid" {
                 //panic!("Executed invalid instruction");
                 state.set_inactive();
+            } else if let Some(lifted) = lifted.filter(|_| !state.has_event_hooks) {
+                if !ir::execute(state, lifted) {
+                    self.parse(state, words);
+                }
+            } else if let Some(fast_words) = fast_words.filter(|_| !state.has_event_hooks) {
+                // fall back to the full interpreter once a symbolic value is touched
+                if !fast::execute(state, fast_words) {
+                    self.parse(state, words);
+                }
             } else {
                 self.parse(state, words);
             }
@@ -661,6 +794,60 @@ impl Processor {
         }
     }
 
+    /// Call the guest function requested by the sim at `pc`, the
+    /// function returns to `pc` where the sim is resumed
+    pub fn sim_call(&self, state: &mut State, pc: u64, call: SimCall) {
+        let func = state.solver.evalcon_to_u64(&call.func).unwrap_or(0);
+        let cc = state.r2api.get_cc(func).unwrap_or_default();
+        let ptrlen = state.memory.bits / 8;
+
+        let entry_sp = state.registers.get_with_alias("SP");
+        let link = if state.registers.aliases.contains_key("LR") {
+            Some(state.registers.get_with_alias("LR"))
+        } else {
+            None
+        };
+
+        // make aligned room for the args if they are passed on the stack
+        let stack_args = if cc.args.is_empty() { call.args.len() as u64 } else { 0 };
+        let sp = entry_sp.sub(&vc(stack_args * ptrlen)).and(&vc(!15));
+        state.registers.set_with_alias("SP", sp.clone());
+
+        state.sim_frames.push(SimFrame {
+            pc,
+            sp: sp.as_u64().unwrap_or(0),
+            entry_sp,
+            link,
+            resume: call.resume,
+            data: call.data,
+        });
+
+        push_return(state, pc);
+        state.backtrace.push((func, pc));
+        state.registers.set_pc(vc(func));
+        state.set_args(call.args);
+    }
+
+    /// Get the frame of the sim at `pc` if a guest function it called
+    /// has returned to it, restoring the stack and link register
+    pub fn sim_return(&self, state: &mut State, pc: u64) -> Option<SimFrame> {
+        let sp = state.registers.get_with_alias("SP").as_u64()?;
+        let index = state
+            .sim_frames
+            .iter()
+            .rposition(|frame| frame.pc == pc && frame.sp == sp)?;
+
+        // frames above this one were abandoned, eg. by a longjmp
+        let frame = state.sim_frames.remove(index);
+        state.sim_frames.truncate(index);
+
+        state.registers.set_with_alias("SP", frame.entry_sp.to_owned());
+        if let Some(link) = &frame.link {
+            state.registers.set_with_alias("LR", link.to_owned());
+        }
+        Some(frame)
+    }
+
     // weird method that just performs a return
     pub fn ret(&self, state: &mut State) {
         let ret_esil = state.r2api.get_ret().unwrap_or_default();
@@ -703,9 +890,10 @@ impl Processor {
             };
 
             let mut prev: Option<u64> = None;
+            let mut fetched = vec![];
             for instr in instrs {
                 let size = instr.size;
-                let words = self.tokenize(state, &instr.esil);
+                let words = self.tokenize_cached(state, &instr.esil);
 
                 let mut flags = HashSet::new();
                 let mut opt = self.optimized && !self.selfmodify;
@@ -727,6 +915,9 @@ impl Processor {
                 if self.sims.contains_key(&pc_tmp) {
                     flags.insert(InstructionFlag::Sim);
                 }
+                if !self.dalvik_sims.is_empty() && self.is_framework_invoke(state, &instr) {
+                    flags.insert(InstructionFlag::Invoke);
+                }
 
                 // don't optimize if hooked / bp for accuracy
                 if !flags.is_empty() {
@@ -735,6 +926,8 @@ impl Processor {
 
                 let instr_entry = InstructionEntry {
                     instruction: instr,
+                    fast: fast::compile(&words),
+                    ir: None,
                     tokens: words,
                     flags,
                 };
@@ -746,11 +939,75 @@ impl Processor {
                     prev = Some(pc_tmp);
                 }
                 self.instructions.insert(pc_tmp, instr_entry);
+                fetched.push(pc_tmp);
                 pc_tmp = pc_tmp.wrapping_add(size);
             }
+
+            if self.lift {
+                self.lift_instructions(state, &fetched);
+            }
+        }
+    }
+
+    /// Lift the fetched instructions at `pcs` to the IR, flags that are
+    /// overwritten by the following instruction are removed
+    fn lift_instructions(&mut self, state: &State, pcs: &[u64]) {
+        let mut lifted: Vec<Option<Lifted>> = pcs
+            .iter()
+            .map(|pc| {
+                let tokens = &self.instructions[pc].tokens;
+                ir::lift(tokens, *pc, state.memory.bits, &state.registers)
+            })
+            .collect();
+
+        // the next instruction must always follow and have no hooks
+        let chained: Vec<bool> = pcs
+            .iter()
+            .zip(pcs.iter().skip(1))
+            .map(|(pc, next)| {
+                let instr = &self.instructions[pc].instruction;
+                !self.selfmodify
+                    && instr.jump == 0
+                    && pc.wrapping_add(instr.size) == *next
+                    && self.instructions[next].flags.is_empty()
+            })
+            .collect();
+
+        ir::eliminate(&mut lifted, &chained, &state.registers);
+        for (pc, lifted) in pcs.iter().zip(lifted) {
+            let entry = self.instructions.get_mut(pc).unwrap();
+            entry.ir = lifted.map(|mut lifted| {
+                lifted.allocate();
+                lifted
+            });
         }
     }
 
+    // check if the instruction invokes a method of a class outside the apk
+    pub fn is_framework_invoke(&mut self, state: &mut State, instr: &Instruction) -> bool {
+        let class = if let Some(invoke) = Invoke::parse(&instr.disasm) {
+            invoke.class
+        } else {
+            return false;
+        };
+
+        if let Some(framework) = self.java_classes.get(&class) {
+            return *framework;
+        }
+
+        let name = class.trim_start_matches('L').trim_end_matches(';').replace('/', ".");
+        let framework = match state.r2api.get_java_class(&class) {
+            Ok(info) => {
+                let found = info.classname.trim_start_matches('L').trim_end_matches(';');
+                found.replace('/', ".") != name
+            }
+            Err(_) => true,
+        };
+
+        self.java_classes.insert(class, framework);
+        framework
+    }
+
     pub fn execute_instruction(&mut self, state: &mut State, pc_val: u64) {
         self.fetch_instruction(state, pc_val);
 
@@ -760,7 +1017,12 @@ impl Processor {
         if self.debug {
             self.print_instr(state, &instr.instruction);
         }
-        self.execute(state, &instr.instruction, &instr.flags, &instr.tokens);
+        if let Some(trace) = &mut state.trace {
+            trace.step(pc_val, instr.instruction.size);
+        }
+        let fast_words = instr.fast.as_deref().filter(|_| self.fast);
+        let lifted = instr.ir.as_ref().filter(|_| self.lift);
+        self.execute(state, &instr.instruction, &instr.flags, &instr.tokens, fast_words, lifted);
     }
 
     /// Take single step with the state provided
@@ -771,11 +1033,15 @@ impl Processor {
         let pc_allocs = 32;
         let pc_value = state.registers.get_pc();
 
-        if let Some(pc_val) = pc_value.as_u64() {
+        let pc = if let Some(pc_val) = pc_value.as_u64() {
+            if self.coverage {
+                self.visited.insert(pc_val);
+            }
             self.execute_instruction(state, pc_val);
+            pc_val
         } else {
             panic!("got an unexpected sym PC: {:?}", pc_value);
-        }
+        };
 
         let new_pc = state.registers.get_pc();
         //let pcs;
@@ -791,7 +1057,7 @@ impl Processor {
                 println!("\n{} : {:?}\n", "symbolic PC".red(), pc_val);
             }
 
-            if DO_EVENT_HOOKS && state.has_event_hooks {
+            if state.has_event_hooks {
                 state.do_hooked(
                     &Event::SymbolicExec(EventTrigger::Before),
                     &EventContext::ExecContext(new_pc.clone(), vec![]),
@@ -814,11 +1080,11 @@ impl Processor {
                     .collect();
 
                 if state.esil.pcs.is_empty() {
-                    state.esil.pcs = state.evaluate_many(&pc_val);
+                    state.esil.pcs = self.jump_targets(state, pc, &new_pc, &pc_val);
                 }
             }
 
-            if DO_EVENT_HOOKS && state.has_event_hooks {
+            if state.has_event_hooks {
                 state.do_hooked(
                     &Event::SymbolicExec(EventTrigger::After),
                     &EventContext::ExecContext(new_pc.clone(), state.esil.pcs.clone()),
@@ -826,6 +1092,10 @@ impl Processor {
             }
         }
 
+        if self.coverage {
+            self.cover_blocks(state, pc);
+        }
+
         if state.esil.pcs.len() > 1 || new_pc.as_u64().is_none() {
             let mut states: Vec<State> = Vec::with_capacity(pc_allocs);
 
@@ -835,24 +1105,55 @@ impl Processor {
                 return states;
             }
 
+            if state.has_event_hooks {
+                state.do_hooked(
+                    &Event::Fork(EventTrigger::Before),
+                    &EventContext::ForkContext(new_pc.clone(), state.esil.pcs.clone()),
+                );
+            }
+
+            // the new states share the trace up to here
+            if let Some(trace) = &mut state.trace {
+                trace.split();
+            }
+
             let last = state.esil.pcs.len() - 1;
             for new_pc_val in &state.esil.pcs[..last] {
                 let mut new_state = state.clone();
+                let mut condition = None;
                 if let Some(pc_val) = new_pc.as_bv() {
                     let a = pc_val._eq(&new_state.bvv(*new_pc_val, pc_val.get_width()));
                     new_state.solver.assert_bv(&a);
+                    condition = Some(a);
                 }
                 new_state.registers.set_pc(Value::Concrete(*new_pc_val, 0));
+                if new_state.trace.is_some() {
+                    let condition = condition.map(|c| format!("{:?}", c));
+                    new_state.record(TraceEntry::Branch { pc, target: *new_pc_val, condition });
+                }
                 states.push(new_state);
             }
 
             let new_pc_val = state.esil.pcs[last];
+            let mut condition = None;
             if let Some(pc_val) = new_pc.as_bv() {
                 let pc_bv = pc_val;
                 let a = pc_bv._eq(&state.bvv(new_pc_val, pc_bv.get_width()));
                 state.solver.assert_bv(&a);
+                condition = Some(a);
             }
             state.registers.set_pc(Value::Concrete(new_pc_val, 0));
+            if state.trace.is_some() {
+                let condition = condition.map(|c| format!("{:?}", c));
+                state.record(TraceEntry::Branch { pc, target: new_pc_val, condition });
+            }
+
+            if state.has_event_hooks {
+                state.do_hooked(
+                    &Event::Fork(EventTrigger::After),
+                    &EventContext::ForkContext(new_pc, state.esil.pcs.clone()),
+                );
+            }
             states
         } else if self.selfmodify {
             let mut states: Vec<State> = Vec::with_capacity(pc_allocs);
@@ -877,6 +1178,334 @@ impl Processor {
         }
     }
 
+    /// Distinct targets of the switch table jump at `pc` from r2's analysis
+    fn switch_cases(&mut self, state: &mut State, pc: u64) -> Vec<u64> {
+        if let Some(cases) = self.switches.get(&pc) {
+            return cases.to_owned();
+        }
+
+        // only indirect jumps can go through a switch table
+        let indirect = self
+            .instructions
+            .get(&pc)
+            .map(|entry| entry.instruction.type_num & 0xff == UJMP_TYPE);
+        if indirect != Some(true) {
+            return vec![];
+        }
+
+        let mut blocks = state.r2api.get_function_blocks(pc).unwrap_or_default();
+        if blocks.is_empty() {
+            // analyze the function from the closest flag before pc
+            let flag = state.r2api.get_flag(pc).unwrap_or_default();
+            let name = flag.split(" + ").next().unwrap_or_default().trim().to_owned();
+            let entry = state.r2api.get_address(&name).ok().filter(|e| *e != 0 && *e <= pc);
+            if let Some(entry) = entry {
+                blocks = state.r2api.get_blocks(entry).unwrap_or_default();
+            }
+        }
+
+        let mut cases = vec![];
+        for block in blocks {
+            let contains = block.addr <= pc && pc < block.addr + block.size;
+            if let Some(switch) = block.switch_op.filter(|s| contains || s.addr == pc) {
+                for case in switch.cases {
+                    if !cases.contains(&case.jump) {
+                        cases.push(case.jump);
+                    }
+                }
+            }
+        }
+
+        self.switches.insert(pc, cases.clone());
+        cases
+    }
+
+    /// Get the possible targets of a symbolic jump. Jumps through a switch
+    /// table try each distinct case once instead of enumerating every index
+    /// and only evaluate the targets that are not cases
+    fn jump_targets(
+        &mut self,
+        state: &mut State,
+        pc: u64,
+        new_pc: &Value,
+        pc_val: &BitVec,
+    ) -> Vec<u64> {
+        let cases = self.switch_cases(state, pc);
+        if cases.is_empty() {
+            return state.evaluate_many(pc_val);
+        }
+
+        let mut targets: Vec<u64> = cases
+            .iter()
+            .cloned()
+            .filter(|case| state.check(&new_pc.eq(&vc(*case))))
+            .collect();
+
+        state.solver.push();
+        for case in &cases {
+            let case_bv = state.solver.bvv(*case, pc_val.get_width());
+            state.solver.assert_bv(&pc_val._eq(&case_bv).not());
+        }
+        let others = if state.solver.is_sat() {
+            state.evaluate_many(pc_val)
+        } else {
+            vec![]
+        };
+        state.solver.pop();
+
+        let limited = others.len() == state.solver.eval_max;
+        targets.extend(others);
+        if limited {
+            // same as evaluate_many, constrain it to the targets we found
+            state.solver.assert_in(pc_val, &targets);
+        }
+        targets
+    }
+
+    // the targets of jumps and any other non-sequential pc start blocks
+    fn cover_blocks(&mut self, state: &State, pc: u64) {
+        if let Some(entry) = self.instructions.get(&pc) {
+            let instr = &entry.instruction;
+            let fallthrough = pc.wrapping_add(instr.size);
+            for target in &state.esil.pcs {
+                if instr.jump != 0 || *target != fallthrough {
+                    self.blocks.insert(*target);
+                }
+            }
+        }
+    }
+
+    /// Get the instruction and block coverage of every state run so far
+    pub fn get_coverage(&self) -> Coverage {
+        let mut coverage = Coverage::default();
+        for addr in &self.visited {
+            if let Some(entry) = self.instructions.get(addr) {
+                coverage.instructions.insert(*addr, entry.instruction.size);
+            }
+        }
+
+        // blocks run until a branch, the start of another block or unvisited code
+        for start in &self.blocks {
+            let mut addr = *start;
+            while let Some(size) = coverage.instructions.get(&addr) {
+                let instr = &self.instructions[&addr].instruction;
+                addr += size;
+                let kind = instr.r#type.as_str();
+                if instr.jump != 0
+                    || kind.contains("jmp")
+                    || kind.contains("call")
+                    || kind.contains("ret")
+                    || self.blocks.contains(&addr)
+                {
+                    break;
+                }
+            }
+            if addr > *start {
+                coverage.blocks.insert(*start, addr - start);
+            }
+        }
+        coverage
+    }
+
+    /// Replay a recorded `trace` from `state` without forking, taking the
+    /// recorded branch at every decision. Returns `None` if a recorded
+    /// branch is unsatisfiable for this state
+    pub fn replay(&mut self, mut state: State, trace: &Trace) -> Option<State> {
+        let mut branches = trace.branches().into_iter().peekable();
+        let steps = trace.steps();
+
+        for _ in 0..steps {
+            match state.status {
+                StateStatus::Active | StateStatus::PostMerge => {}
+                _ => break,
+            }
+
+            self.steps += 1;
+            state.visit();
+            let pc = state.registers.get_pc().as_u64()?;
+            self.execute_instruction(&mut state, pc);
+
+            // branches are only recorded where step forks
+            let new_pc = state.registers.get_pc();
+            let forked = new_pc.is_symbolic() || (self.force && state.esil.pcs.len() > 1);
+            let target = match branches.peek() {
+                Some((bpc, target)) if *bpc == pc && forked => {
+                    let target = *target;
+                    branches.next();
+                    Some(target)
+                }
+                _ if new_pc.is_symbolic() => state.solver.evalcon_to_u64(&new_pc),
+                _ => None,
+            };
+
+            if let Some(target) = target {
+                if new_pc.is_symbolic() {
+                    let taken = new_pc.eq(&vc(target));
+                    if !state.check(&taken) {
+                        return None;
+                    }
+                    state.assert(&taken);
+                }
+                state.registers.set_pc(vc(target));
+                let condition = None;
+                state.record(TraceEntry::Branch { pc, target, condition });
+            }
+        }
+        Some(state)
+    }
+
+    /// Explore every path from `state` breadth first, writing a test case
+    /// with `generator` for the initial state, each new branch edge, each
+    /// path that exits or stops at a new edge and each new crash address.
+    /// Returns the number of cases written
+    pub fn generate(
+        &mut self,
+        mut state: State,
+        generator: &mut TestGenerator,
+    ) -> io::Result<usize> {
+        generator.write_case(&mut state, false)?;
+
+        let mut states = VecDeque::new();
+        states.push_back(state);
+
+        while let Some(mut state) = states.pop_front() {
+            let pc = state.registers.get_pc().as_u64().unwrap();
+            let new_states = self.step(&mut state);
+            let branched = !new_states.is_empty();
+
+            for mut new_state in new_states.into_iter().chain(Some(state)) {
+                match new_state.status {
+                    StateStatus::Active | StateStatus::PostMerge => {}
+                    StateStatus::Crash(addr, _perm) => {
+                        if generator.crashes.insert(addr) {
+                            generator.write_case(&mut new_state, true)?;
+                        }
+                        continue;
+                    }
+                    StateStatus::Exit | StateStatus::Break | StateStatus::Merge => {
+                        let target = new_state.registers.get_pc().as_u64().unwrap_or(pc);
+                        if generator.edges.insert((pc, target)) {
+                            generator.write_case(&mut new_state, false)?;
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                }
+
+                if branched {
+                    let target = new_state.registers.get_pc().as_u64().unwrap();
+                    if generator.edges.insert((pc, target)) {
+                        generator.write_case(&mut new_state, false)?;
+                    }
+                }
+
+                if states.len() < generator.max_states {
+                    states.push_back(new_state);
+                } else {
+                    generator.dropped += 1;
+                }
+            }
+        }
+        Ok(generator.count())
+    }
+
+    /// Run `state` for at most `max_steps` instructions without forking,
+    /// taking the branch that the `seed` assertions select whenever the next
+    /// PC is symbolic. The branches are returned in order and the condition
+    /// of each one is asserted in a new solver scope, so popping a scope
+    /// removes a branch and every assertion made after it
+    pub fn concolic_run(
+        &mut self,
+        mut state: State,
+        seed: &[Value],
+        max_steps: usize,
+    ) -> (State, Vec<Branch>) {
+        let mut branches = vec![];
+        let mut steps = 0;
+
+        while let StateStatus::Active | StateStatus::PostMerge = state.status {
+            if steps >= max_steps {
+                break;
+            }
+            steps += 1;
+            self.steps += 1;
+            state.visit();
+            let pc = state.registers.get_pc().as_u64().unwrap();
+            if self.coverage {
+                self.visited.insert(pc);
+            }
+            self.execute_instruction(&mut state, pc);
+
+            let new_pc = state.registers.get_pc();
+            if new_pc.is_concrete() {
+                continue;
+            }
+
+            state.solver.push();
+            for assertion in seed {
+                state.assert(assertion);
+            }
+            let target = state.solver.evalcon_to_u64(&new_pc);
+            state.solver.pop();
+
+            // the seed does not satisfy the constraints of this path
+            let target = if let Some(target) = target {
+                target
+            } else {
+                state.set_inactive();
+                break;
+            };
+
+            let condition = new_pc.eq(&vc(target));
+            state.solver.push();
+            state.assert(&condition);
+            state.registers.set_pc(vc(target));
+            state.record(TraceEntry::Branch { pc, target, condition: None });
+            branches.push(Branch { pc, target, condition });
+        }
+        (state, branches)
+    }
+
+    /// Generational concolic search from the seeds in `concolic`. Each seed
+    /// is run concretely and the branches after its bound are negated one
+    /// at a time to generate new seeds. Returns the number of new seeds
+    pub fn concolic(&mut self, state: State, concolic: &mut Concolic) -> usize {
+        while let Some((seed, bound)) = concolic.queue.pop_front() {
+            if concolic.runs >= concolic.max_runs {
+                break;
+            }
+            concolic.runs += 1;
+
+            let mut run = state.clone();
+            let assertions = concolic.bind(&mut run, &seed);
+            let (mut end, branches) = self.concolic_run(run, &assertions, concolic.max_steps);
+
+            // pop the scopes back to each branch, keeping the assertions made
+            // before it, and negate it. children are kept in branch order
+            let mut children = vec![];
+            for (i, branch) in branches.iter().enumerate().rev() {
+                concolic.edges.insert((branch.pc, branch.target));
+                end.solver.pop();
+                if i >= bound {
+                    end.solver.push();
+                    end.assert(&!branch.condition.clone());
+                    if let Some(child) = concolic.solve(&mut end) {
+                        children.push((child, i + 1));
+                    }
+                    end.solver.pop();
+                }
+            }
+
+            for (child, bound) in children.into_iter().rev() {
+                if concolic.seen.insert(child.clone()) {
+                    concolic.generated.push(child.clone());
+                    concolic.queue.push_back((child, bound));
+                }
+            }
+        }
+        concolic.generated.len()
+    }
+
     /// run the state until completion based on mode
     pub fn run(&mut self, state: State, mode: RunMode) -> Vec<State> {
         // use binary heap as priority queue to prioritize states
@@ -890,6 +1519,12 @@ impl Processor {
         let split = mode == RunMode::Parallel;
         let step = mode == RunMode::Step;
 
+        if self.coverage {
+            if let Some(pc) = states.peek().unwrap().registers.get_pc().as_u64() {
+                self.blocks.insert(pc);
+            }
+        }
+
         loop {
             //println!("{} states", states.len());
             if states.is_empty() {
@@ -908,6 +1543,16 @@ impl Processor {
             let mut current_rc = states.pop().unwrap();
             let current_state = Rc::make_mut(&mut current_rc);
 
+            if current_state.has_event_hooks {
+                match current_state.status {
+                    StateStatus::Active | StateStatus::PostMerge | StateStatus::Merge => {}
+                    _ => current_state.do_hooked(
+                        &Event::Terminate(EventTrigger::Before),
+                        &EventContext::TerminateContext(current_state.status.to_owned()),
+                    ),
+                }
+            }
+
             match current_state.status {
                 StateStatus::Active | StateStatus::PostMerge => {
                     let new_states = self.step(current_state);
@@ -940,6 +1585,19 @@ impl Processor {
                         }
                     }
                 }
+                StateStatus::Exit if self.break_exit => {
+                    // only the low byte makes it to the parent
+                    let exit_code = current_state.exit_code();
+                    if let (Some(code), Some(exit_code)) = (self.exit_code, exit_code) {
+                        current_state.assert(&exit_code.slice(7, 0).eq(&vc(code & 0xff)));
+                    }
+                    if current_state.is_sat() {
+                        results.push(current_state.to_owned());
+                        if mode != RunMode::Multiple {
+                            return results;
+                        }
+                    }
+                }
                 StateStatus::Crash(_addr, _len) => {
                     self.crashes.push(current_state.to_owned());
                 }
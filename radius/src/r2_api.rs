@@ -1,5 +1,8 @@
+use crate::cache::{self, DiskCache};
 use r2pipe::{R2Pipe, R2PipeSpawnOptions};
 use serde::{Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::io;
 use std::sync::{Arc, Mutex};
 use std::u64;
 use std::u8;
@@ -160,6 +163,8 @@ pub struct BinInfo {
     pub endian: String,
     pub os: String,
     pub nx: bool,
+    #[serde(default)]
+    pub intrp: String,
 }
 
 impl Default for BinInfo {
@@ -172,6 +177,7 @@ impl Default for BinInfo {
             endian: "little".to_string(),
             os: "".to_string(),
             nx: false,
+            intrp: "".to_string(),
         }
     }
 }
@@ -279,6 +285,34 @@ pub struct BasicBlock {
 
     #[serde(default = "zero")]
     pub fail: i64,
+
+    #[serde(default)]
+    pub switch_op: Option<SwitchOp>,
+}
+
+/// A switch table jump found by r2's analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchOp {
+    pub addr: u64,
+
+    #[serde(default)]
+    pub min_val: u64,
+
+    #[serde(default)]
+    pub max_val: u64,
+
+    #[serde(default)]
+    pub def_val: u64,
+
+    #[serde(default)]
+    pub cases: Vec<SwitchCase>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchCase {
+    pub addr: u64,
+    pub jump: u64,
+    pub value: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -422,6 +456,19 @@ pub struct FridaInfo {
     pub pid: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flag {
+    pub name: String,
+
+    #[serde(default)]
+    pub realname: String,
+
+    pub offset: u64,
+
+    #[serde(default)]
+    pub size: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entrypoint {
     pub vaddr: u64,
@@ -494,6 +541,7 @@ pub struct R2Api {
     pub mode: Mode,
     do_cache: bool,
     cache: HashMap<String, String>,
+    pub disk: Option<Arc<Mutex<DiskCache>>>, // results saved between runs
 }
 
 impl R2Api {
@@ -515,6 +563,7 @@ impl R2Api {
             mode: Mode::Default,
             do_cache: true,
             cache: HashMap::new(),
+            disk: None,
         };
 
         r2api.info = r2api.get_info().unwrap();
@@ -561,6 +610,67 @@ impl R2Api {
         }
     }
 
+    // command saved to the disk cache if it is enabled, `query` runs it otherwise
+    pub fn pcmd(
+        &mut self,
+        cmd: &str,
+        query: fn(&mut Self, &str) -> R2Result<String>,
+    ) -> R2Result<String> {
+        if let Some(disk) = self.disk.clone() {
+            if let Some(result) = disk.lock().unwrap().get_command(cmd) {
+                return Ok(result);
+            }
+            let result = query(self, cmd)?;
+            if !result.trim().is_empty() {
+                disk.lock().unwrap().add_command(cmd, &result);
+            }
+            Ok(result)
+        } else {
+            query(self, cmd)
+        }
+    }
+
+    /// Save disassembly, calling conventions, blocks, imports, segments and
+    /// ESIL tokens in `dir` and reuse them in later runs. The cache is keyed
+    /// by the binary, the loaded `libs`, the r2 version and the r2 `args`
+    pub fn enable_disk_cache(
+        &mut self,
+        dir: &Path,
+        args: &[&str],
+        libs: &[String],
+    ) -> io::Result<()> {
+        if self.mode != Mode::Default {
+            // debugger and frida memory can change between runs
+            return Err(io::ErrorKind::Unsupported.into());
+        }
+
+        let version = self.cmd("?V").unwrap_or_default();
+        let mut key = cache::hash(cache::HASH_START, version.trim().as_bytes());
+        for arg in args {
+            key = cache::hash(key, arg.as_bytes());
+            key = cache::hash(key, &[0]);
+        }
+
+        let mut files = vec![self.info.core.file.to_owned()];
+        files.extend(libs.iter().cloned());
+        for file in &files {
+            key = cache::hash(key, file.as_bytes());
+            key = cache::hash(key, &fs::read(file)?);
+        }
+
+        self.disk = Some(Arc::new(Mutex::new(DiskCache::load(dir, key))));
+        Ok(())
+    }
+
+    /// Write the disk cache if it is enabled
+    pub fn save_disk_cache(&self) -> io::Result<()> {
+        if let Some(disk) = &self.disk {
+            disk.lock().unwrap().save()
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn get_info(&mut self) -> R2Result<Information> {
         let json = self.cmd("ij")?;
         Ok(serde_json::from_str(json.as_str()).unwrap())
@@ -577,7 +687,7 @@ impl R2Api {
     }
 
     pub fn get_cc(&mut self, pc: u64) -> R2Result<CallingConvention> {
-        let json = self.ccmd(format!("af @ {}; afcrj @ {}", pc, pc).as_str())?;
+        let json = self.pcmd(format!("af @ {}; afcrj @ {}", pc, pc).as_str(), R2Api::ccmd)?;
         r2_result(serde_json::from_str(json.as_str()))
     }
 
@@ -742,7 +852,7 @@ impl R2Api {
     }*/
 
     pub fn get_segments(&mut self) -> R2Result<Vec<Segment>> {
-        let json = self.ccmd("iSj")?;
+        let json = self.pcmd("iSj", R2Api::ccmd)?;
         r2_result(serde_json::from_str(json.as_str()))
     }
 
@@ -794,7 +904,13 @@ impl R2Api {
 
     pub fn get_blocks(&mut self, addr: u64) -> R2Result<Vec<BasicBlock>> {
         let cmd = format!("af @ {}; afbj @ {}", addr, addr);
-        let json = self.cmd(cmd.as_str())?;
+        let json = self.pcmd(cmd.as_str(), R2Api::cmd)?;
+        r2_result(serde_json::from_str(json.as_str()))
+    }
+
+    /// Blocks of the function containing `addr` if it was already analyzed
+    pub fn get_function_blocks(&mut self, addr: u64) -> R2Result<Vec<BasicBlock>> {
+        let json = self.cmd(&format!("afbj @ {}", addr))?;
         r2_result(serde_json::from_str(json.as_str()))
     }
 
@@ -942,7 +1058,21 @@ impl R2Api {
     }
 
     pub fn set_option(&mut self, key: &str, value: &str) -> R2Result<String> {
-        self.cmd(format!("e {}={}", key, value).as_str())
+        let option = format!("e {}={}", key, value);
+        self.key_cache(&option);
+        self.cmd(&option)
+    }
+
+    /// Move the disk cache to one keyed on `cmd` too, for commands that
+    /// change r2 results. It is the same in every run that uses `cmd`
+    pub fn key_cache(&mut self, cmd: &str) {
+        if let Some(disk) = &self.disk {
+            let mut disk = disk.lock().unwrap();
+            let _ = disk.save();
+            let dir = disk.path.parent().map(Path::to_owned).unwrap_or_default();
+            let key = cache::hash(cache::hash(disk.key, cmd.as_bytes()), &[0]);
+            *disk = DiskCache::load(&dir, key);
+        }
     }
 
     // is.j returns a weird format
@@ -963,7 +1093,7 @@ impl R2Api {
 
     pub fn get_imports(&mut self) -> R2Result<Vec<Import>> {
         if self.mode != Mode::Frida {
-            let json = self.ccmd("iij")?;
+            let json = self.pcmd("iij", R2Api::ccmd)?;
             r2_result(serde_json::from_str(json.as_str()))
         } else {
             // so jank i dont even know
@@ -990,7 +1120,7 @@ impl R2Api {
 
     pub fn disassemble(&mut self, addr: u64, num: usize) -> R2Result<Vec<Instruction>> {
         let cmd = format!("pdj {} @ {}", num, addr);
-        let json = self.cmd(cmd.as_str())?;
+        let json = self.pcmd(cmd.as_str(), R2Api::cmd)?;
         r2_result(serde_json::from_str(json.as_str()))
     }
 
@@ -1074,6 +1204,16 @@ impl R2Api {
         r2_result(serde_json::from_str(json.as_str()))
     }
 
+    /// Check if the current file is a static elf, one with no
+    /// interpreter (PT_INTERP) and no needed libraries (DT_NEEDED)
+    pub fn is_static(&mut self) -> bool {
+        // not cached since it is asked for every open file
+        let info = self.get_info().unwrap_or_default();
+        let libs = self.cmd("ilj").unwrap_or_default();
+        let libs: Vec<String> = serde_json::from_str(&libs).unwrap_or_default();
+        info.bin.bintype == "elf" && info.bin.intrp.is_empty() && libs.is_empty()
+    }
+
     pub fn get_relocations(&mut self) -> R2Result<Vec<Relocation>> {
         let json = self.ccmd("irj")?;
         r2_result(serde_json::from_str(json.as_str()))
@@ -1088,6 +1228,32 @@ impl R2Api {
         self.cmd(&format!("fd @ 0x{:x}", addr))
     }
 
+    // flags in a single flagspace, "*" for all of them
+    pub fn get_flags(&mut self, space: &str) -> R2Result<Vec<Flag>> {
+        self.cmd(&format!("fs {}", space))?;
+        let json = self.cmd("fj");
+        self.cmd("fs *")?;
+        r2_result(serde_json::from_str(json?.as_str()))
+    }
+
+    // match function signatures against the current file, flirt .sig/.pat
+    // files rename functions to flirt.name, zignatures add sign.*.name_N flags
+    pub fn match_signatures(&mut self, path: &str) -> R2Result<Vec<Flag>> {
+        if path.ends_with(".sig") || path.ends_with(".pat") {
+            self.cmd("aa")?; // flirt only matches analyzed functions
+            self.cmd(&format!("zF {}", path))?;
+            let flags = self.get_flags("*")?;
+            Ok(flags
+                .into_iter()
+                .filter(|f| f.name.starts_with("flirt."))
+                .collect())
+        } else {
+            self.cmd(&format!("zo {}", path))?;
+            self.cmd("z/")?;
+            self.get_flags("sign")
+        }
+    }
+
     // load libraries, return list of full loaded paths
     pub fn load_libraries(&mut self, lib_paths: &[String]) -> R2Result<Vec<String>> {
         let paths = self.load_library_helper(lib_paths, &[])?;
@@ -1171,6 +1337,7 @@ impl R2Api {
     pub fn clear(&mut self) {}
 
     pub fn close(&mut self) {
+        let _ = self.save_disk_cache();
         self.r2p.lock().unwrap().close();
     }
 }
@@ -1,17 +1,36 @@
-pub use crate::processor::{HookMethod, Processor, RunMode};
-use crate::r2_api::{BasicBlock, FunctionInfo, Information, Instruction, R2Api, R2Result};
-use crate::state::State;
+pub use crate::processor::{Hook, HookMethod, Processor, RunMode};
+use crate::r2_api::{BasicBlock, FunctionInfo, Information, Instruction, R2Api, R2Result, Syscall};
+use crate::state::{Event, EventContext, EventHook, EventTrigger, State, WatchAccess};
+use crate::cache;
+use crate::concolic::Concolic;
+use crate::coverage::{Coverage, Module};
+use crate::testgen::TestGenerator;
+use crate::trace::Trace;
 //use crate::value::Value;
-use crate::sims::syscall::indirect;
-use crate::sims::{get_sims, zero, Sim, SimMethod};
+use crate::sims::libc;
+use crate::sims::syscall::{get_syscall_table, indirect};
+use crate::sims::{get_dalvik_sims, get_sims, make_sim, signature_symbol, zero, Sim};
 use crate::value::{vc, Value};
 
 // use std::collections::VecDeque;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
 // use std::thread;
 
+// auxiliary vector keys
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+const AT_RANDOM: u64 = 25;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum RadiusOption {
     /// Use simulated syscalls
@@ -20,6 +39,10 @@ pub enum RadiusOption {
     Sims(bool),
     /// Sim all imports, with stub if missing
     SimAll(bool),
+    /// Also sim local function symbols, always on for static binaries
+    SimSymbols(bool),
+    /// Zignature or FLIRT file to identify functions in stripped binaries
+    Signatures(String),
     /// Optimize executed ESIL expressions
     Optimize(bool),
     /// Enable debug output
@@ -50,6 +73,19 @@ pub enum RadiusOption {
     ColorOutput(bool),
     /// Path to load library from
     LibPath(String),
+    /// Record an execution trace for each state
+    Trace(bool),
+    /// Collect instruction and block coverage
+    Coverage(bool),
+    /// Execute fully concrete instructions with a fast interpreter
+    FastConcrete(bool),
+    /// Lift instructions to an IR with folded constants and dead flags removed
+    Lift(bool),
+    /// Save r2 results and ESIL tokens between runs, off by default
+    /// for library use, the command line enables it unless `--no-cache`
+    Cache(bool),
+    /// Directory of the cache, defaults to `~/.cache/radius2`
+    CacheDir(String),
 }
 
 /// Main Radius struct that coordinates and configures
@@ -78,6 +114,10 @@ pub struct Radius {
     pub debug: bool,
     /// Panic on invalid instructions
     pub strict: bool,
+    /// Record execution traces in new states
+    pub trace: bool,
+    event_hooks: Vec<(Event, Rc<EventHook>)>,
+    created: bool, // a state was created
 }
 
 impl Radius {
@@ -111,7 +151,9 @@ impl Radius {
     pub fn new_with_options<T: AsRef<str>>(filename: Option<T>, options: &[RadiusOption]) -> Self {
         let mut argv = vec!["-2"];
         let mut eval_max = 256;
+        let mut signatures: Vec<String> = vec![];
         let mut paths = vec![];
+        let mut cache_dir = cache::default_dir();
         for o in options {
             if let RadiusOption::R2Argument(arg) = o {
                 argv.push(*arg);
@@ -119,6 +161,10 @@ impl Radius {
                 eval_max = *m;
             } else if let RadiusOption::LibPath(p) = o {
                 paths.push(p.to_owned());
+            } else if let RadiusOption::Signatures(p) = o {
+                signatures.push(p.to_owned());
+            } else if let RadiusOption::CacheDir(dir) = o {
+                cache_dir = Some(PathBuf::from(dir));
             }
         }
 
@@ -140,6 +186,7 @@ impl Radius {
             argv.push("-e asm.functions=false");
         }
 
+        let cache_args = argv.clone();
         let args = if !argv.is_empty() || filename.is_none() {
             Some(argv)
         } else {
@@ -151,6 +198,7 @@ impl Radius {
         // r2api.cmd("eco darkda").unwrap(); // i like darkda
 
         let arch = &r2api.info.bin.arch;
+        let dalvik = arch.as_str() == "dalvik";
 
         // don't optimize dalvik & arm
         let opt = !options.contains(&RadiusOption::Optimize(false))
@@ -162,32 +210,72 @@ impl Radius {
         let topo = options.contains(&RadiusOption::Topological(true));
         let check = options.contains(&RadiusOption::Permissions(true));
         let sim_all = options.contains(&RadiusOption::SimAll(true));
+        let sim_symbols = options.contains(&RadiusOption::SimSymbols(true));
         let selfmod = options.contains(&RadiusOption::SelfModify(true));
         let strict = options.contains(&RadiusOption::Strict(true));
         let automerge = options.contains(&RadiusOption::AutoMerge(true));
+        let trace = options.contains(&RadiusOption::Trace(true));
 
         let mut processor = Processor::new(selfmod, opt, debug, lazy, force, topo, automerge, color);
+        processor.coverage = options.contains(&RadiusOption::Coverage(true));
+        processor.fast = options.contains(&RadiusOption::FastConcrete(true));
+        processor.lift = options.contains(&RadiusOption::Lift(true));
         let processors = Arc::new(Mutex::new(vec![]));
 
         if !options.contains(&RadiusOption::Syscalls(false)) {
             let syscalls = r2api.get_syscalls().unwrap();
             if let Some(sys) = syscalls.get(0) {
-                processor.traps.insert(sys.swi, indirect);
+                processor.traps.insert(sys.swi, Rc::new(indirect));
             }
             for sys in &syscalls {
                 processor.syscalls.insert(sys.num, sys.to_owned());
             }
+
+            // r2's tables are incomplete, so add the builtin ones over them
+            let bin = &r2api.info.bin;
+            let table = get_syscall_table(&bin.os, &bin.arch, bin.bits);
+            if !table.is_empty() {
+                // xnu uses svc 0x80 on arm and int 0x80 on x86 too, x86_64 xnu
+                // uses the syscall instruction which doesn't go through the traps
+                let darwin_arm = bin.os == "darwin" && bin.arch == "arm";
+                let swi = if darwin_arm || (bin.arch == "x86" && bin.bits == 32) {
+                    0x80
+                } else {
+                    syscalls.first().map(|s| s.swi).unwrap_or(0)
+                };
+                processor.traps.entry(swi).or_insert_with(|| Rc::new(indirect));
+                for (num, name) in table {
+                    let sys = Syscall {
+                        name: name.to_string(),
+                        swi,
+                        num: *num,
+                    };
+                    processor.syscalls.insert(*num, sys);
+                }
+            }
         }
 
-        let _libs = if options.contains(&RadiusOption::LoadLibs(true)) {
+        let libs = if options.contains(&RadiusOption::LoadLibs(true)) {
             r2api.load_libraries(&paths).unwrap()
         } else {
             vec![]
         };
 
+        // a binary that can't be read or hashed just isn't cached
+        if let Some(dir) = cache_dir.filter(|_| options.contains(&RadiusOption::Cache(true))) {
+            let _ = r2api.enable_disk_cache(&dir, &cache_args, &libs);
+        }
+
         // this is weird, idk
         if use_sims {
-            Radius::register_sims(&mut r2api, &mut processor, sim_all);
+            Radius::register_sims(&mut r2api, &mut processor, sim_all, sim_symbols);
+            Radius::register_signatures(&mut r2api, &mut processor, &signatures);
+
+            if dalvik {
+                for sim in get_dalvik_sims() {
+                    processor.dalvik_sims.insert(sim.symbol.to_owned(), sim);
+                }
+            }
         }
 
         Radius {
@@ -198,6 +286,9 @@ impl Radius {
             check,
             debug,
             strict,
+            trace,
+            event_hooks: vec![],
+            created: false,
         }
     }
 
@@ -219,7 +310,7 @@ impl Radius {
         let mut state = self.init_state();
         state.memory.add_stack();
         state.memory.add_heap();
-        state.memory.add_std_streams();
+        state.add_std_streams();
         state
     }
 
@@ -284,20 +375,29 @@ impl Radius {
         let mut state = self.init_state();
         state.memory.add_stack();
         state.memory.add_heap();
-        state.memory.add_std_streams();
+        state.add_std_streams();
+
+        // default to just argv[0] and an empty env
+        let path = self.r2api.info.core.file.clone();
+        let argv0: Vec<Value> = path.bytes().map(|b| Value::Concrete(b as u64, 0)).collect();
+        let argv0 = state.memory.pack(&argv0);
+        self.set_argv_env(&mut state, &[argv0], &[]);
 
         let start_main_reloc = self.r2api.get_address("reloc.__libc_start_main").unwrap_or(0);
         if start_main_reloc != 0 {
             self.r2api.cmd("af").unwrap(); // analyze entrypoint
             let callers = self.r2api.get_references(start_main_reloc).unwrap_or_default();
             if !callers.is_empty() {
-                self.hook(callers[0].from, __libc_start_main);
+                let sim = make_sim("__libc_start_main", libc::__libc_start_main, 7);
+                self.simulate(callers[0].from, sim);
             }
         }
         state
     }
 
-    /// Set argv and env with arrays of values
+    /// Set argv, env and the auxiliary vector on the stack with arrays of values,
+    /// replacing any that were set before, eg. the defaults from `entry_state`.
+    /// Env values can be symbolic, eg. `NAME=` followed by a symbolic string
     pub fn set_argv_env(&mut self, state: &mut State, args: &[Value], env: &[Value]) {
         // we write args to both regs and stack
         // i think this is ok
@@ -338,30 +438,116 @@ impl Radius {
             state.memory_write_value(&current, &Value::Concrete(0, 0), ptrlen);
             current = current + Value::Concrete(ptrlen as u64, 0);
         }
+
+        // only elf loaders pass an auxiliary vector
+        if self.r2api.info.bin.bintype != "elf" {
+            return;
+        }
+
+        state.context.insert("auxv".to_owned(), vec![current.clone()]);
+        for (key, value) in self.get_auxv(state) {
+            state.memory_write_value(&current, &Value::Concrete(key, 0), ptrlen);
+            current = current + Value::Concrete(ptrlen as u64, 0);
+            state.memory_write_value(&current, &value, ptrlen);
+            current = current + Value::Concrete(ptrlen as u64, 0);
+        }
+    }
+
+    // the auxiliary vector entries that libc init actually reads
+    fn get_auxv(&mut self, state: &mut State) -> Vec<(u64, Value)> {
+        let entrypoints = self.r2api.get_entrypoints().unwrap_or_default();
+        let (entry, baddr) = entrypoints
+            .first()
+            .map(|e| (e.vaddr, e.baddr))
+            .unwrap_or_default();
+
+        // e_phoff, e_phentsize and e_phnum from the elf header
+        let header = |state: &mut State, offset: u64, length: usize| {
+            let value = state.memory_read_value(&Value::Concrete(baddr + offset, 0), length);
+            value.as_u64().unwrap_or(0)
+        };
+        let (phoff, phent, phnum) = if state.memory.bits == 64 {
+            (header(state, 0x20, 8), header(state, 0x36, 2), header(state, 0x38, 2))
+        } else {
+            (header(state, 0x1c, 4), header(state, 0x2a, 2), header(state, 0x2c, 2))
+        };
+
+        // 16 bytes used for the stack canary and pointer guard, they are
+        // concrete unless set_random is used. setting argv again keeps them
+        let random_addr = if let Some(random) = state.context.get("AT_RANDOM") {
+            random[1].as_u64().unwrap()
+        } else {
+            let random_addr = state.memory.alloc(&Value::Concrete(16, 0));
+            for (i, bytes) in [0x8f2d_61c4_e7a3_0b59, 0x3c71_d0e8_94f6_a215].iter().enumerate() {
+                let addr = Value::Concrete(random_addr + 8 * i as u64, 0);
+                state.memory_write_value(&addr, &Value::Concrete(*bytes, 0), 8);
+            }
+            let random = state.memory_read_value(&Value::Concrete(random_addr, 0), 16);
+            state
+                .context
+                .insert("AT_RANDOM".to_owned(), vec![random, Value::Concrete(random_addr, 0)]);
+            random_addr
+        };
+
+        vec![
+            (AT_PHDR, Value::Concrete(baddr + phoff, 0)),
+            (AT_PHENT, Value::Concrete(phent, 0)),
+            (AT_PHNUM, Value::Concrete(phnum, 0)),
+            (AT_PAGESZ, Value::Concrete(0x1000, 0)),
+            (AT_ENTRY, Value::Concrete(entry, 0)),
+            (AT_RANDOM, Value::Concrete(random_addr, 0)),
+            (AT_NULL, Value::Concrete(0, 0)),
+        ]
+    }
+
+    /// Replace the AT_RANDOM bytes of a state with an auxiliary vector by
+    /// `value`, eg. a symbolic value to explore the canary and pointer guard
+    pub fn set_random(&mut self, state: &mut State, value: &Value) -> bool {
+        if let Some(random) = state.context.get_mut("AT_RANDOM") {
+            random[0] = value.to_owned();
+            let addr = random[1].to_owned();
+            state.memory_write_value(&addr, value, 16);
+            true
+        } else {
+            false
+        }
     }
 
     /// A default initial state
     pub fn init_state(&mut self) -> State {
-        State::new(
-            &mut self.r2api,
-            self.eval_max,
-            self.debug,
-            false,
-            self.check,
-            self.strict,
-        )
+        self.new_state(false)
     }
 
     /// A "blank" state with uninitialized values set to be symbolic
     pub fn blank_state(&mut self) -> State {
-        State::new(
+        self.new_state(true)
+    }
+
+    // every state gets the event hooks and a trace if they are enabled
+    fn new_state(&mut self, blank: bool) -> State {
+        let mut state = State::new(
             &mut self.r2api,
             self.eval_max,
             self.debug,
-            true,
+            blank,
             self.check,
             self.strict,
-        )
+        );
+        for (event, hook) in &self.event_hooks {
+            state.hook_event(event.to_owned(), hook.to_owned());
+        }
+        if self.trace {
+            state.trace = Some(Trace::new());
+        }
+        self.created = true;
+        state
+    }
+
+    // watches added after a state exists are easy to miss so warn about it
+    fn warn_created(&self, what: &str) {
+        if self.created {
+            eprintln!("{} only applies to states created after it is called", what);
+        }
     }
 
     /// A blank state except for PC and SP
@@ -376,7 +562,7 @@ impl Radius {
         state.registers.set_with_alias("SP", Value::Concrete(sp, 0));
         state.memory.add_stack();
         state.memory.add_heap();
-        state.memory.add_std_streams();
+        state.add_std_streams();
         state
     }
 
@@ -386,11 +572,13 @@ impl Radius {
     ///
     /// ## Arguments
     /// * `addr` - the address to hook
-    /// * `hook_callback` - the function to call once the address is reached
+    /// * `hook_callback` - the function or closure to call once the address is reached
     ///
     /// ## Example
     /// ```
     /// use radius2::{Radius, State, vc};
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
     /// let mut radius = Radius::new("/bin/sh");
     ///
     /// fn callback(state: &mut State) -> bool {
@@ -398,13 +586,24 @@ impl Radius {
     ///     true // do not skip instruction
     /// }
     /// radius.hook(0x400cb0, callback);
+    ///
+    /// // closures can capture counters, collectors etc.
+    /// let hits = Rc::new(Cell::new(0));
+    /// let counter = hits.clone();
+    /// radius.hook(0x400cb0, move |_state| {
+    ///     counter.set(counter.get() + 1);
+    ///     true
+    /// });
     /// ```
-    pub fn hook(&mut self, addr: u64, hook_callback: HookMethod) {
+    pub fn hook<F>(&mut self, addr: u64, hook_callback: F)
+    where
+        F: Fn(&mut State) -> bool + 'static,
+    {
         self.processor
             .hooks
             .entry(addr)
             .or_insert(vec![])
-            .push(hook_callback);
+            .push(Rc::new(hook_callback));
     }
 
     /// Hook an address with an esil expression. The instruction
@@ -422,15 +621,29 @@ impl Radius {
     }
 
     /// Hook a symbol with a callback that is passed each state that reaches it
-    pub fn hook_symbol(&mut self, sym: &str, hook_callback: HookMethod) {
+    pub fn hook_symbol<F>(&mut self, sym: &str, hook_callback: F)
+    where
+        F: Fn(&mut State) -> bool + 'static,
+    {
         let addr = self.get_address(sym).unwrap();
         self.hook(addr, hook_callback);
     }
 
     // internal method to register import sims
-    fn register_sims(r2api: &mut R2Api, processor: &mut Processor, sim_all: bool) {
+    fn register_sims(
+        r2api: &mut R2Api,
+        processor: &mut Processor,
+        sim_all: bool,
+        sim_symbols: bool,
+    ) {
         let sims = get_sims();
         let files = r2api.get_files().unwrap();
+        // mach-o symbols have a leading underscore, _printf etc
+        let prefix = if r2api.info.bin.bintype == "mach0" {
+            "_"
+        } else {
+            ""
+        };
 
         for file in files {
             if file.uri.starts_with("null://") {
@@ -439,15 +652,17 @@ impl Radius {
 
             r2api.set_file_fd(file.fd);
             let symbols = r2api.get_imports().unwrap();
+            let statically_linked = r2api.is_static();
             let mut symmap: HashMap<String, u64> = HashMap::new();
 
             for symbol in symbols {
                 symmap.insert(symbol.name, symbol.plt);
             }
 
-            // TODO expand this to handle other symbols
             for sim in &sims {
-                let addropt = symmap.remove(&sim.symbol);
+                let addropt = symmap
+                    .remove(&sim.symbol)
+                    .or_else(|| symmap.remove(&format!("{}{}", prefix, sim.symbol)));
                 if let Some(addr) = addropt {
                     processor.sims.insert(addr, sim.to_owned());
                 }
@@ -456,14 +671,26 @@ impl Radius {
             if sim_all {
                 for name in symmap.keys() {
                     // we are gonna go with zero by default
-                    processor.sims.insert(
-                        symmap[name],
-                        Sim {
-                            symbol: name.to_owned(),
-                            function: zero,
-                            arguments: 0,
-                        },
-                    );
+                    processor.sims.insert(symmap[name], make_sim(name, zero, 0));
+                }
+            }
+
+            // static binaries have the libc functions as local symbols
+            if sim_symbols || statically_linked {
+                let mut localmap: HashMap<String, u64> = HashMap::new();
+                for symbol in r2api.get_symbols().unwrap_or_default() {
+                    if !symbol.is_imported && symbol.r#type == "FUNC" && symbol.vaddr != 0 {
+                        localmap.insert(symbol.name, symbol.vaddr);
+                    }
+                }
+
+                for sim in &sims {
+                    let addropt = localmap
+                        .remove(&sim.symbol)
+                        .or_else(|| localmap.remove(&format!("{}{}", prefix, sim.symbol)));
+                    if let Some(addr) = addropt {
+                        processor.sims.entry(addr).or_insert_with(|| sim.to_owned());
+                    }
                 }
             }
         }
@@ -472,12 +699,43 @@ impl Radius {
         r2api.set_file_fd(3);
     }
 
-    /// Register a trap to call the provided `SimMethod`
-    pub fn trap(&mut self, trap_num: u64, sim: SimMethod) {
-        self.processor.traps.insert(trap_num, sim);
+    // identify functions in stripped binaries with signature files
+    fn register_signatures(r2api: &mut R2Api, processor: &mut Processor, signatures: &[String]) {
+        if signatures.is_empty() {
+            return;
+        }
+
+        let mut simmap: HashMap<String, Sim> = HashMap::new();
+        for sim in get_sims() {
+            simmap.insert(sim.symbol.to_owned(), sim);
+        }
+
+        for path in signatures {
+            for flag in r2api.match_signatures(path).unwrap_or_default() {
+                let name = signature_symbol(&flag.name);
+                let simopt = simmap
+                    .get(name)
+                    .or_else(|| simmap.get(name.trim_start_matches('_')));
+
+                if let Some(sim) = simopt {
+                    processor
+                        .sims
+                        .entry(flag.offset)
+                        .or_insert_with(|| sim.to_owned());
+                }
+            }
+        }
     }
 
-    /// Register a `SimMethod` for the provided function address
+    /// Register a trap to call the provided `SimMethod` or closure
+    pub fn trap<F>(&mut self, trap_num: u64, sim: F)
+    where
+        F: Fn(&mut State, &[Value]) -> Value + 'static,
+    {
+        self.processor.traps.insert(trap_num, Rc::new(sim));
+    }
+
+    /// Register a `Sim` for the provided function address
     ///
     /// ## Arguments
     /// * `addr` - address of the function to simulate (usually the PLT address)
@@ -485,7 +743,7 @@ impl Radius {
     ///
     /// ## Example
     /// ```
-    /// use radius2::{Radius, State, Sim, Value, vc};
+    /// use radius2::{make_sim, Radius, State, Value, vc};
     /// let mut radius = Radius::new("/bin/sh");
     /// let scanf = radius.get_address("__isoc99_scanf").unwrap();
     ///
@@ -493,17 +751,159 @@ impl Radius {
     ///     state.memory_write_value(&args[1], &vc(42), 8);
     ///     vc(1)
     /// }
+    /// radius.simulate(scanf, make_sim("scanf", scanf_sim, 2));
     ///
-    /// radius.simulate(scanf, Sim{
-    ///     symbol: "scanf".to_owned(),
-    ///     function: scanf_sim,
-    ///     arguments: 2
-    /// });
+    /// // or with a closure capturing the value to write
+    /// let value = vc(1337);
+    /// radius.simulate(scanf, make_sim("scanf", move |state: &mut State, args: &[Value]| {
+    ///     state.memory_write_value(&args[1], &value, 8);
+    ///     vc(1)
+    /// }, 2));
     /// ```
     pub fn simulate(&mut self, addr: u64, sim: Sim) {
         self.processor.sims.insert(addr, sim);
     }
 
+    /// Hook an `Event` such as memory reads and writes, forks or syscalls
+    /// in every state created after this is called. Events are only
+    /// dispatched for states that have at least one event hook
+    ///
+    /// ## Example
+    /// ```
+    /// use radius2::{Radius, Event, EventContext, EventTrigger};
+    /// let mut radius = Radius::new("/bin/sh");
+    ///
+    /// radius.hook_event(Event::Syscall(EventTrigger::Before), |_state, context| {
+    ///     if let EventContext::SyscallContext(num, args) = context {
+    ///         println!("syscall {:?} {:?}", num, args);
+    ///     }
+    /// });
+    /// ```
+    pub fn hook_event<F>(&mut self, event: Event, hook: F)
+    where
+        F: Fn(&mut State, &EventContext) + 'static,
+    {
+        self.event_hooks.push((event, Rc::new(hook)));
+    }
+
+    /// Watch `len` bytes at `addr` for reads and writes, `perms` is some
+    /// combination of "rw". The callback gets the PC of the access and
+    /// the watched value before and after it. Like `hook_event` this
+    /// only applies to states created after it is called
+    ///
+    /// ## Example
+    /// ```
+    /// use radius2::Radius;
+    /// let mut radius = Radius::new("/bin/sh");
+    ///
+    /// radius.watch(0x100000, 4, "rw", |_state, access| {
+    ///     println!("0x{:x} {} {:?} -> {:?}", access.pc, access.perm, access.old, access.new);
+    /// });
+    /// ```
+    pub fn watch<F>(&mut self, addr: u64, len: usize, perms: &str, callback: F)
+    where
+        F: Fn(&mut State, &WatchAccess) + 'static,
+    {
+        self.warn_created("watch");
+        let (read, write) = (perms.contains('r'), perms.contains('w'));
+        let old: Rc<RefCell<Option<Value>>> = Rc::new(RefCell::new(None));
+
+        if write {
+            let old = old.clone();
+            let before: Rc<EventHook> = Rc::new(move |state, context| {
+                if let (_, Some((address, length))) = memory_accesses(context) {
+                    if overlaps(state, address, length, addr, len) {
+                        *old.borrow_mut() = Some(state.memory.read_value(addr, len));
+                    }
+                }
+            });
+            for event in memory_events(EventTrigger::Before, false) {
+                self.event_hooks.push((event, before.clone()));
+            }
+        }
+
+        let after: Rc<EventHook> = Rc::new(move |state, context| {
+            let (reads, _) = memory_accesses(context);
+            let pc = state.esil.prev_pc.as_u64().unwrap_or(0);
+
+            if read && reads.iter().any(|(a, l)| overlaps(state, a, l, addr, len)) {
+                let value = state.memory.read_value(addr, len);
+                let access = WatchAccess {
+                    pc,
+                    perm: 'r',
+                    old: value.clone(),
+                    new: value,
+                };
+                callback(state, &access);
+            }
+
+            let prev = old.borrow_mut().take();
+            if let Some(prev) = prev {
+                let access = WatchAccess {
+                    pc,
+                    perm: 'w',
+                    old: prev,
+                    new: state.memory.read_value(addr, len),
+                };
+                callback(state, &access);
+            }
+        });
+        for event in memory_events(EventTrigger::After, read) {
+            self.event_hooks.push((event, after.clone()));
+        }
+    }
+
+    /// Watch any part of the register `name` for reads and writes by
+    /// instructions. Like `hook_event` this only applies to states
+    /// created after it is called
+    pub fn watch_register<F>(&mut self, name: &str, callback: F)
+    where
+        F: Fn(&mut State, &WatchAccess) + 'static,
+    {
+        self.warn_created("watch_register");
+        let callback = Rc::new(callback);
+        let old: Rc<RefCell<Option<Value>>> = Rc::new(RefCell::new(None));
+
+        let (reg, prev) = (name.to_owned(), old.clone());
+        self.hook_event(Event::RegisterWrite(EventTrigger::Before), move |state, context| {
+            if let EventContext::RegisterContext(written, _) = context {
+                if same_register(state, written, &reg) {
+                    *prev.borrow_mut() = Some(state.registers.get(&reg));
+                }
+            }
+        });
+
+        let (reg, cb) = (name.to_owned(), callback.clone());
+        self.hook_event(Event::RegisterWrite(EventTrigger::After), move |state, _context| {
+            let prev = old.borrow_mut().take();
+            if let Some(prev) = prev {
+                let access = WatchAccess {
+                    pc: state.esil.prev_pc.as_u64().unwrap_or(0),
+                    perm: 'w',
+                    old: prev,
+                    new: state.registers.get(&reg),
+                };
+                cb(state, &access);
+            }
+        });
+
+        let reg = name.to_owned();
+        self.hook_event(Event::RegisterRead(EventTrigger::After), move |state, context| {
+            if let EventContext::RegisterContext(read, _) = context {
+                if same_register(state, read, &reg) {
+                    let value = state.registers.get(&reg);
+                    let access = WatchAccess {
+                        pc: state.esil.prev_pc.as_u64().unwrap_or(0),
+                        perm: 'r',
+                        old: value.clone(),
+                        new: value,
+                    };
+                    callback(state, &access);
+                }
+            }
+        });
+    }
+
     /// Add a breakpoint at the provided address.
     /// This is where execution will stop after `run` is called
     pub fn breakpoint(&mut self, addr: u64) {
@@ -516,6 +916,13 @@ impl Radius {
         self.processor.mergepoints.insert(addr);
     }
 
+    /// Break when a state exits instead of dropping it. If `code` is
+    /// provided the exit code of the state is constrained to it
+    pub fn break_on_exit(&mut self, code: Option<u64>) {
+        self.processor.break_exit = true;
+        self.processor.exit_code = code;
+    }
+
     /// Add addresses that will be avoided during execution. Any
     /// `State` that reaches these addresses will be marked inactive
     ///
@@ -539,6 +946,32 @@ impl Radius {
                 .sum::<u64>()
     }
 
+    /// Get the coverage collected with `RadiusOption::Coverage` by every
+    /// state run so far, with the loaded binaries as modules
+    pub fn get_coverage(&mut self) -> Coverage {
+        let mut coverage = self.processor.get_coverage();
+
+        for file in self.r2api.get_files().unwrap_or_default() {
+            if file.uri.starts_with("null://") {
+                continue;
+            }
+            self.r2api.set_file_fd(file.fd);
+            let segments = self.r2api.get_segments().unwrap_or_default();
+            // segments without permissions like __PAGEZERO are not mapped,
+            // the first segment of a PIE at base 0 is
+            let mapped = segments.iter().filter(|s| s.vsize != 0 && s.perm.contains(['r', 'x']));
+            let base = mapped.clone().map(|s| s.vaddr).min();
+            let end = mapped.map(|s| s.vaddr + s.vsize).max();
+
+            if let (Some(base), Some(end)) = (base, end) {
+                let path = file.uri.to_owned();
+                coverage.modules.push(Module { path, base, end });
+            }
+        }
+        self.r2api.set_file_fd(3);
+        coverage
+    }
+
     /// Execute function and return the resulting state
     pub fn call_function(&mut self, sym: &str, state: State, args: Vec<Value>) -> Option<State> {
         let addr = self.r2api.get_address(sym).unwrap_or_default();
@@ -600,6 +1033,64 @@ impl Radius {
         self.processor.run(state, RunMode::Single).pop()
     }
 
+    /// Replay a `Trace` recorded with `RadiusOption::Trace` from `state`,
+    /// following the recorded branches instead of forking
+    ///
+    /// ## Example
+    /// ```
+    /// use radius2::{Radius, RadiusOption};
+    /// let mut radius = Radius::new_with_options(Some("/bin/ls"), &[RadiusOption::Trace(true)]);
+    /// let state = radius.entry_state();
+    /// let end = radius.run(state, 1).unwrap();
+    ///
+    /// let trace = end.trace.clone().unwrap();
+    /// let state = radius.entry_state();
+    /// let replayed = radius.replay(state, &trace).unwrap();
+    /// assert_eq!(replayed.trace.unwrap().divergence(&trace), None);
+    /// ```
+    pub fn replay(&mut self, state: State, trace: &Trace) -> Option<State> {
+        self.processor.replay(state, trace)
+    }
+
+    /// Explore every path from `state` and write a test case for each new
+    /// branch edge and crash with `generator`, see `TestGenerator`
+    ///
+    /// ## Example
+    /// ```
+    /// use radius2::{InputKind, Radius, TestGenerator};
+    /// let mut radius = Radius::new("/bin/cat");
+    /// let mut state = radius.entry_state();
+    /// let stdin = state.symbolic_value("stdin", 64);
+    /// let bytes = state.unpack(&stdin, 8);
+    /// state.filesystem.fill(0, &bytes);
+    ///
+    /// let mut generator = TestGenerator::new("/tmp/cases", vec![]);
+    /// generator.add_input(InputKind::Fd(0), stdin, 8);
+    /// let count = radius.generate(state, &mut generator).unwrap();
+    /// ```
+    pub fn generate(&mut self, state: State, generator: &mut TestGenerator) -> io::Result<usize> {
+        self.processor.generate(state, generator)
+    }
+
+    /// Generational concolic search from the seeds added to `concolic`,
+    /// returns the number of new inputs found, see `Concolic`
+    ///
+    /// ## Example
+    /// ```
+    /// use radius2::{Concolic, Radius, Value};
+    /// let mut radius = Radius::new("../tests/simple");
+    /// let mut state = radius.call_state(0x5fa);
+    /// let num = Value::Symbolic(state.bv("num", 32), 0);
+    /// state.registers.set("edi", num.clone());
+    ///
+    /// let mut concolic = Concolic::new(vec![num]);
+    /// concolic.add_seed(&[vec![0, 0, 0, 0]]);
+    /// let count = radius.concolic(state, &mut concolic);
+    /// ```
+    pub fn concolic(&mut self, state: State, concolic: &mut Concolic) -> usize {
+        self.processor.concolic(state, concolic)
+    }
+
     /// Run radare2 analysis
     pub fn analyze(&mut self, n: usize) {
         let _r = self.r2api.analyze(n);
@@ -688,17 +1179,63 @@ impl Radius {
     }
 }
 
-pub fn __libc_start_main(state: &mut State) -> bool {
-    let mut args = state.get_args();
-    let main = args.remove(0);
+// address and length of a memory access
+type Access<'a> = (&'a Value, &'a Value);
+
+// memory ranges read and the range written by an event
+fn memory_accesses(context: &EventContext) -> (Vec<Access<'_>>, Option<Access<'_>>) {
+    match context {
+        EventContext::ReadContext(addr, len) => (vec![(addr, len)], None),
+        EventContext::WriteContext(addr, len) => (vec![], Some((addr, len))),
+        EventContext::SearchContext(addr, _, len) => (vec![(addr, len)], None),
+        EventContext::StrlenContext(addr, len) => (vec![(addr, len)], None),
+        EventContext::CompareContext(dst, src, len) => (vec![(dst, len), (src, len)], None),
+        EventContext::MoveContext(dst, src, len) => (vec![(src, len)], Some((dst, len))),
+        _ => (vec![], None),
+    }
+}
 
-    // TODO go to init then main
-    // but we need a nice arch neutral way to push ret
-    // so until then
+// the events with the memory accesses in `memory_accesses`, the ones
+// that write and also the ones that read if `reads` is set
+fn memory_events(trigger: EventTrigger, reads: bool) -> Vec<Event> {
+    let t = trigger;
+    let mut events = vec![
+        Event::Write(t.clone()),
+        Event::SymbolicWrite(t.clone()),
+        Event::Move(t.clone()),
+        Event::SymbolicMove(t.clone()),
+    ];
+    if reads {
+        events.extend(vec![
+            Event::Read(t.clone()),
+            Event::SymbolicRead(t.clone()),
+            Event::Search(t.clone()),
+            Event::SymbolicSearch(t.clone()),
+            Event::Compare(t.clone()),
+            Event::SymbolicCompare(t.clone()),
+            Event::StringLength(t.clone()),
+            Event::SymbolicStrlen(t),
+        ]);
+    }
+    events
+}
 
-    // go to main
-    state.registers.set_with_alias("PC", main);
-    state.set_args(args);
+// check if an access of `length` at `address` can touch the watched range
+fn overlaps(state: &mut State, address: &Value, length: &Value, addr: u64, len: usize) -> bool {
+    let end = addr + len as u64;
+    if let (Some(a), Some(l)) = (address.as_u64(), length.as_u64()) {
+        a < end && addr < a.wrapping_add(l)
+    } else {
+        let cond = address.ult(&vc(end)).and(&vc(addr).ult(&address.add(length)));
+        state.check(&cond)
+    }
+}
 
-    false
+// registers share a value if one is part of the other, eg. eax and rax
+fn same_register(state: &State, reg: &str, watched: &str) -> bool {
+    let regs = &state.registers.regs;
+    match (regs.get(reg), regs.get(watched)) {
+        (Some(r), Some(w)) => r.value_index == w.value_index,
+        _ => false,
+    }
 }
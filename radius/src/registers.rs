@@ -5,10 +5,10 @@ use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Bounds {
-    tstr: String,
-    start: u64,
-    end: u64,
-    size: u64,
+    pub tstr: String,
+    pub start: u64,
+    pub end: u64,
+    pub size: u64,
 }
 
 #[derive(Debug, Clone)]
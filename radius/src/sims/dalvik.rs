@@ -0,0 +1,645 @@
+use crate::r2_api::Instruction;
+use crate::sims::{format, libc, Sim};
+use crate::state::State;
+use crate::value::{vc, Value};
+use std::collections::HashMap;
+
+const MAX_LEN: u64 = 8192;
+
+// java.lang.String references are modeled as pointers to null terminated
+// bytes, which is exactly what const-string gives us from the dex strings
+// everything else created by the sims lives on the heap in these layouts
+const OBJECT_SIZE: u64 = 16;
+const ARRAY_LENGTH: u64 = 0;
+const ARRAY_DATA: u64 = 8;
+
+/// A parsed `invoke-*` instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invoke {
+    pub kind: String,
+    pub class: String,
+    pub method: String,
+    pub params: Vec<String>,
+    pub ret: String,
+    pub registers: Vec<String>,
+}
+
+impl Invoke {
+    /// Parse the disassembly of an invoke, eg.
+    /// `invoke-virtual {v1, v2}, Ljava/lang/String;->equals(Ljava/lang/Object;)Z`
+    pub fn parse(disasm: &str) -> Option<Invoke> {
+        if !disasm.starts_with("invoke-") {
+            return None;
+        }
+
+        let kind = disasm.split_whitespace().next()?.to_owned();
+        let reg_start = disasm.find('{')?;
+        let reg_end = disasm.find('}')?;
+        let registers = parse_registers(&disasm[reg_start + 1..reg_end]);
+
+        let target = disasm[reg_end + 1..].trim_start_matches([',', ' ']);
+        let sig_start = target.find('(')?;
+        let sig_end = target.find(')')?;
+        let head = &target[target.find('L').unwrap_or(0)..sig_start];
+
+        // r2 has printed these as Lcls;->method, Lcls;.method.name and Lcls.method
+        let (class, method) = if let Some(ind) = head.find(';') {
+            let method = head[ind + 1..]
+                .trim_start_matches("->")
+                .trim_start_matches('.')
+                .trim_start_matches("method.");
+            (head[..ind + 1].to_owned(), method.to_owned())
+        } else {
+            let ind = head.rfind('.')?;
+            (format!("{};", &head[..ind]), head[ind + 1..].to_owned())
+        };
+
+        Some(Invoke {
+            kind,
+            class,
+            method,
+            params: parse_descriptors(&target[sig_start + 1..sig_end]),
+            ret: target[sig_end + 1..].trim().to_owned(),
+            registers,
+        })
+    }
+
+    /// The key used to look up the Sim for this method, eg.
+    /// `Ljava/lang/String;->equals(Ljava/lang/Object;)`
+    pub fn key(&self) -> String {
+        format!("{}->{}({})", self.class, self.method, self.params.join(""))
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.kind.starts_with("invoke-static")
+    }
+
+    /// Get the java level arguments, wide values are put back together
+    pub fn get_args(&self, state: &mut State) -> Vec<Value> {
+        let regs: Vec<Value> = self
+            .registers
+            .iter()
+            .map(|r| get_register(state, r))
+            .collect();
+
+        let mut args = Vec::with_capacity(8);
+        let mut ind = 0;
+        if !self.is_static() && !regs.is_empty() {
+            args.push(regs[0].to_owned());
+            ind = 1;
+        }
+
+        for param in &self.params {
+            if ind >= regs.len() {
+                break;
+            } else if is_wide(param) && ind + 1 < regs.len() {
+                let low = regs[ind].slice(31, 0).uext(&vc(32));
+                let high = regs[ind + 1].slice(31, 0) << vc(32);
+                args.push(high | low);
+                ind += 2;
+            } else {
+                args.push(regs[ind].to_owned());
+                ind += 1;
+            }
+        }
+        args
+    }
+}
+
+fn parse_registers(regs: &str) -> Vec<String> {
+    if let Some(ind) = regs.find("..") {
+        // range invokes look like {v0 .. v4}
+        let start = regs[..ind].trim().trim_start_matches('v').parse::<u64>();
+        let end = regs[ind + 2..].trim().trim_start_matches('v').parse::<u64>();
+        if let (Ok(start), Ok(end)) = (start, end) {
+            return (start..=end).map(|r| format!("v{}", r)).collect();
+        }
+        vec![]
+    } else {
+        regs.split(',')
+            .map(|r| r.trim().to_owned())
+            .filter(|r| !r.is_empty())
+            .collect()
+    }
+}
+
+/// Split a string of type descriptors like `I[BLjava/lang/String;J`
+pub fn parse_descriptors(params: &str) -> Vec<String> {
+    let mut descs = vec![];
+    let mut current = String::new();
+    let mut in_class = false;
+
+    for c in params.chars() {
+        current.push(c);
+        if in_class {
+            if c == ';' {
+                in_class = false;
+                descs.push(current.clone());
+                current.clear();
+            }
+        } else if c == 'L' {
+            in_class = true;
+        } else if c != '[' {
+            descs.push(current.clone());
+            current.clear();
+        }
+    }
+    descs
+}
+
+fn is_wide(desc: &str) -> bool {
+    desc == "J" || desc == "D"
+}
+
+fn get_register(state: &mut State, reg: &str) -> Value {
+    if state.registers.get_register(reg).is_some() {
+        state.registers.get(reg)
+    } else {
+        vc(0)
+    }
+}
+
+fn set_register(state: &mut State, reg: &str, value: Value) {
+    if state.registers.get_register(reg).is_some() {
+        state.registers.set(reg, value);
+    }
+}
+
+// unique-ish symbolic value for the return of an unsimulated method
+fn framework_value(state: &mut State, invoke: &Invoke) -> Value {
+    if invoke.ret == "V" {
+        return vc(0);
+    }
+
+    let count = state.context.get("framework").map_or(0, |f| f.len());
+    let name: String = format!("{}_{}_{}", invoke.class, invoke.method, count)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let bits = if is_wide(&invoke.ret) { 64 } else { 32 };
+    let value = state.symbolic_value(name.trim_matches('_'), bits);
+    state
+        .context
+        .entry("framework".to_owned())
+        .or_default()
+        .push(value.clone());
+
+    value
+}
+
+/// Perform an `invoke-*` of a method outside the APK. If there is a Sim
+/// for the method it is called, otherwise the result is a new symbolic value.
+/// The result is written to the register of the following `move-result`
+pub fn invoke(state: &mut State, instr: &Instruction, sims: &HashMap<String, Sim>) {
+    let next = instr.offset.wrapping_add(instr.size);
+    state.registers.set_pc(vc(next));
+
+    let invoke = if let Some(inv) = Invoke::parse(&instr.disasm) {
+        inv
+    } else {
+        return;
+    };
+
+    let args = invoke.get_args(state);
+    let ret = if let Some(sim) = sims.get(&invoke.key()) {
+        let ret = (sim.function)(state, &args);
+        // constructors with sims create the object they initialize
+        if invoke.method == "<init>" && !invoke.registers.is_empty() {
+            set_register(state, &invoke.registers[0], ret.clone());
+        }
+        ret
+    } else {
+        framework_value(state, &invoke)
+    };
+
+    if invoke.ret == "V" {
+        return;
+    }
+
+    let result = state.r2api.disassemble(next, 1).unwrap_or_default();
+    if let Some(move_result) = result.first() {
+        if move_result.disasm.starts_with("move-result") {
+            let reg = move_result
+                .disasm
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_owned();
+
+            if move_result.disasm.starts_with("move-result-wide") {
+                let num = reg.trim_start_matches('v').parse::<u64>().unwrap_or(0);
+                set_register(state, &reg, ret.slice(31, 0));
+                set_register(state, &format!("v{}", num + 1), ret.slice(63, 32));
+            } else {
+                set_register(state, &reg, ret.slice(31, 0));
+            }
+
+            let after = next.wrapping_add(move_result.size);
+            state.registers.set_pc(vc(after));
+        }
+    }
+}
+
+fn new_object(state: &mut State) -> Value {
+    let obj = state.memory_alloc(&vc(OBJECT_SIZE));
+    state.memory_write_value(&obj, &vc(0), OBJECT_SIZE as usize);
+    add_object(state, &obj);
+    obj
+}
+
+// remember objects that are not strings so they are compared by reference
+fn add_object(state: &mut State, obj: &Value) {
+    state
+        .context
+        .entry("objects".to_owned())
+        .or_default()
+        .push(obj.to_owned());
+}
+
+// anything readable that the sims did not create as another kind of object
+// is a string. boxed integers are plain values so they are never readable
+fn is_string(state: &mut State, value: &Value) -> bool {
+    if let Some(addr) = value.as_u64() {
+        let object = state
+            .context
+            .get("objects")
+            .is_some_and(|objs| objs.iter().any(|obj| obj.as_u64() == Some(addr)));
+
+        !object && addr != 0 && state.memory.check_permission(addr, 1, 'r')
+    } else {
+        false
+    }
+}
+
+// keys are equal like equals(), strings by contents and the rest by reference
+fn keys_equal(state: &mut State, key: &Value, other: &Value) -> Value {
+    let same = key.eq(other);
+    if is_string(state, key) && is_string(state, other) {
+        let cmp = libc::strcmp(state, &[key.to_owned(), other.to_owned()]);
+        same | cmp.eq(&vc(0))
+    } else {
+        same
+    }
+}
+
+fn new_string(state: &mut State, string: &str) -> Value {
+    let addr = state.memory_alloc(&vc(string.len() as u64 + 1));
+    state.memory_write_string(addr.as_u64().unwrap(), string);
+    addr
+}
+
+fn new_array(state: &mut State, length: &Value, size: u64) -> Value {
+    let len = state.solver.max_value(length);
+    let arr = state.memory_alloc(&vc(ARRAY_DATA + len * size));
+    state.memory_write_value(&(arr.clone() + vc(ARRAY_LENGTH)), length, 4);
+    add_object(state, &arr);
+    arr
+}
+
+fn array_length(state: &mut State, arr: &Value) -> Value {
+    state.memory_read_value(&(arr.to_owned() + vc(ARRAY_LENGTH)), 4)
+}
+
+fn concat(state: &mut State, s1: &Value, s2: &Value) -> Value {
+    let len1 = state.memory_strlen(s1, &vc(MAX_LEN));
+    let len2 = state.memory_strlen(s2, &vc(MAX_LEN)) + vc(1);
+    let addr = state.memory_alloc(&(len1.clone() + len2.clone()));
+    state.memory_move(&addr, s1, &len1);
+    state.memory_move(&(addr.clone() + len1), s2, &len2);
+    addr
+}
+
+// key of the context entry holding the contents of a collection object
+fn collection_key(state: &mut State, obj: &Value) -> String {
+    let addr = state.solver.evalcon_to_u64(obj).unwrap_or_default();
+    format!("collection_{:x}", addr)
+}
+
+pub fn string_init(state: &mut State, _args: &[Value]) -> Value {
+    new_string(state, "")
+}
+
+pub fn string_init_bytes(state: &mut State, args: &[Value]) -> Value {
+    let length = array_length(state, &args[1]);
+    let addr = state.memory_alloc(&(length.clone() + vc(1)));
+    state.memory_move(&addr, &(args[1].to_owned() + vc(ARRAY_DATA)), &length);
+    state.memory_write_value(&(addr.clone() + length), &vc(0), 1);
+    addr
+}
+
+pub fn string_equals(state: &mut State, args: &[Value]) -> Value {
+    libc::strcmp(state, args).eq(&vc(0))
+}
+
+pub fn string_compare_to(state: &mut State, args: &[Value]) -> Value {
+    libc::strcmp(state, args)
+}
+
+pub fn string_length(state: &mut State, args: &[Value]) -> Value {
+    libc::strlen(state, args)
+}
+
+pub fn string_is_empty(state: &mut State, args: &[Value]) -> Value {
+    libc::strlen(state, args).eq(&vc(0))
+}
+
+pub fn string_char_at(state: &mut State, args: &[Value]) -> Value {
+    let addr = args[0].to_owned() + args[1].slice(31, 0);
+    state.memory_read_value(&addr, 1)
+}
+
+pub fn string_concat(state: &mut State, args: &[Value]) -> Value {
+    concat(state, &args[0], &args[1])
+}
+
+pub fn string_contains(state: &mut State, args: &[Value]) -> Value {
+    !libc::strstr(state, args).eq(&vc(0))
+}
+
+pub fn string_starts_with(state: &mut State, args: &[Value]) -> Value {
+    let length = state.memory_strlen(&args[1], &vc(MAX_LEN));
+    state.memory_compare(&args[0], &args[1], &length).eq(&vc(0))
+}
+
+pub fn string_index_of(state: &mut State, args: &[Value]) -> Value {
+    let found = libc::strchr(state, args);
+    let index = found.sub(&args[0]);
+    state.cond(&found.eq(&vc(0)), &vc(-1i64 as u64), &index)
+}
+
+pub fn string_hash_code(state: &mut State, args: &[Value]) -> Value {
+    let length = state.memory_strlen(&args[0], &vc(MAX_LEN));
+    let len = state.solver.max_value(&length);
+    let data = state.memory_read(&args[0], &vc(len));
+
+    // s[0]*31^(n-1) + s[1]*31^(n-2) + ... + s[n-1]
+    let mut hash = vc(0);
+    for (i, c) in data.iter().enumerate() {
+        let next = hash.mul(&vc(31)).add(&c.uext(&vc(8)));
+        hash = state.cond(&vc(i as u64).ult(&length), &next, &hash);
+    }
+    hash.slice(31, 0)
+}
+
+pub fn string_get_bytes(state: &mut State, args: &[Value]) -> Value {
+    let length = state.memory_strlen(&args[0], &vc(MAX_LEN));
+    let arr = new_array(state, &length, 1);
+    state.memory_move(&(arr.clone() + vc(ARRAY_DATA)), &args[0], &length);
+    arr
+}
+
+pub fn string_to_char_array(state: &mut State, args: &[Value]) -> Value {
+    let length = state.memory_strlen(&args[0], &vc(MAX_LEN));
+    let arr = new_array(state, &length, 2);
+    let len = state.solver.max_value(&length);
+    let data = state.memory_read(&args[0], &vc(len));
+
+    for (i, c) in data.iter().enumerate() {
+        let addr = arr.clone() + vc(ARRAY_DATA + 2 * i as u64);
+        state.memory_write_value(&addr, &c.uext(&vc(8)), 2);
+    }
+    arr
+}
+
+pub fn string_value_of_int(state: &mut State, args: &[Value]) -> Value {
+    let addr = state.memory_alloc(&vc(16));
+    format::itoa_helper(state, &args[0], &addr, &vc(10), true, 32);
+    addr
+}
+
+pub fn string_value_of_char(state: &mut State, args: &[Value]) -> Value {
+    let addr = state.memory_alloc(&vc(2));
+    state.memory_write_value(&addr, &args[0].slice(7, 0), 1);
+    state.memory_write_value(&(addr.clone() + vc(1)), &vc(0), 1);
+    addr
+}
+
+pub fn this(_state: &mut State, args: &[Value]) -> Value {
+    args[0].to_owned()
+}
+
+pub fn builder_init(state: &mut State, _args: &[Value]) -> Value {
+    let obj = new_object(state);
+    let empty = new_string(state, "");
+    state.memory_write_ptr(&obj, &empty);
+    obj
+}
+
+pub fn builder_init_string(state: &mut State, args: &[Value]) -> Value {
+    let obj = new_object(state);
+    let string = libc::strdup(state, &args[1..]);
+    state.memory_write_ptr(&obj, &string);
+    obj
+}
+
+pub fn builder_append(state: &mut State, args: &[Value]) -> Value {
+    let current = state.memory_read_ptr(&args[0]);
+    let string = concat(state, &current, &args[1]);
+    state.memory_write_ptr(&args[0], &string);
+    args[0].to_owned()
+}
+
+pub fn builder_append_int(state: &mut State, args: &[Value]) -> Value {
+    let string = string_value_of_int(state, &args[1..]);
+    builder_append(state, &[args[0].to_owned(), string])
+}
+
+pub fn builder_append_char(state: &mut State, args: &[Value]) -> Value {
+    let string = string_value_of_char(state, &args[1..]);
+    builder_append(state, &[args[0].to_owned(), string])
+}
+
+pub fn builder_to_string(state: &mut State, args: &[Value]) -> Value {
+    state.memory_read_ptr(&args[0])
+}
+
+pub fn builder_length(state: &mut State, args: &[Value]) -> Value {
+    let current = state.memory_read_ptr(&args[0]);
+    libc::strlen(state, &[current])
+}
+
+pub fn parse_int(state: &mut State, args: &[Value]) -> Value {
+    format::atoi_helper(state, &args[0], &vc(10), 32)
+}
+
+pub fn parse_int_radix(state: &mut State, args: &[Value]) -> Value {
+    format::atoi_helper(state, &args[0], &args[1], 32)
+}
+
+// boxed Integers are modeled as just their value
+pub fn integer_value_of(_state: &mut State, args: &[Value]) -> Value {
+    args[0].to_owned()
+}
+
+fn arrays_equals_helper(state: &mut State, args: &[Value], size: u64) -> Value {
+    let len1 = array_length(state, &args[0]);
+    let len2 = array_length(state, &args[1]);
+    let length = len1.mul(&vc(size));
+
+    let data1 = args[0].to_owned() + vc(ARRAY_DATA);
+    let data2 = args[1].to_owned() + vc(ARRAY_DATA);
+    let cmp = state.memory_compare(&data1, &data2, &length);
+    len1.eq(&len2) & cmp.eq(&vc(0))
+}
+
+pub fn arrays_equals_bytes(state: &mut State, args: &[Value]) -> Value {
+    arrays_equals_helper(state, args, 1)
+}
+
+pub fn arrays_equals_chars(state: &mut State, args: &[Value]) -> Value {
+    arrays_equals_helper(state, args, 2)
+}
+
+pub fn arrays_equals_ints(state: &mut State, args: &[Value]) -> Value {
+    arrays_equals_helper(state, args, 4)
+}
+
+fn arrays_copy_of_helper(state: &mut State, args: &[Value], size: u64) -> Value {
+    let length = args[1].slice(31, 0);
+    let arr = new_array(state, &length, size);
+    let old_len = array_length(state, &args[0]);
+    let copy_len = state.cond(&old_len.ult(&length), &old_len, &length);
+
+    let src = args[0].to_owned() + vc(ARRAY_DATA);
+    let dst = arr.clone() + vc(ARRAY_DATA);
+    state.memory_move(&dst, &src, &copy_len.mul(&vc(size)));
+    arr
+}
+
+pub fn arrays_copy_of_bytes(state: &mut State, args: &[Value]) -> Value {
+    arrays_copy_of_helper(state, args, 1)
+}
+
+pub fn arrays_copy_of_chars(state: &mut State, args: &[Value]) -> Value {
+    arrays_copy_of_helper(state, args, 2)
+}
+
+pub fn arrays_copy_of_ints(state: &mut State, args: &[Value]) -> Value {
+    arrays_copy_of_helper(state, args, 4)
+}
+
+fn arrays_fill_helper(state: &mut State, args: &[Value], size: u64) -> Value {
+    let length = array_length(state, &args[0]);
+    let len = state.solver.max_value(&length);
+
+    for i in 0..len {
+        let addr = args[0].to_owned() + vc(ARRAY_DATA + i * size);
+        let old = state.memory_read_value(&addr, size as usize);
+        let value = state.cond(&vc(i).ult(&length), &args[1], &old);
+        state.memory_write_value(&addr, &value, size as usize);
+    }
+    vc(0)
+}
+
+pub fn arrays_fill_bytes(state: &mut State, args: &[Value]) -> Value {
+    arrays_fill_helper(state, args, 1)
+}
+
+pub fn arrays_fill_chars(state: &mut State, args: &[Value]) -> Value {
+    arrays_fill_helper(state, args, 2)
+}
+
+pub fn arrays_fill_ints(state: &mut State, args: &[Value]) -> Value {
+    arrays_fill_helper(state, args, 4)
+}
+
+// lists and maps keep their contents in the state context
+// maps are stored as key, value, key, value...
+pub fn collection_init(state: &mut State, _args: &[Value]) -> Value {
+    let obj = new_object(state);
+    let key = collection_key(state, &obj);
+    state.context.insert(key, vec![]);
+    obj
+}
+
+fn collection_get(state: &mut State, obj: &Value) -> Vec<Value> {
+    let key = collection_key(state, obj);
+    state.context.get(&key).cloned().unwrap_or_default()
+}
+
+fn collection_set(state: &mut State, obj: &Value, values: Vec<Value>) {
+    let key = collection_key(state, obj);
+    state.context.insert(key, values);
+}
+
+pub fn list_add(state: &mut State, args: &[Value]) -> Value {
+    let mut values = collection_get(state, &args[0]);
+    values.push(args[1].to_owned());
+    collection_set(state, &args[0], values);
+    vc(1)
+}
+
+pub fn list_get(state: &mut State, args: &[Value]) -> Value {
+    let values = collection_get(state, &args[0]);
+    let index = args[1].slice(31, 0);
+
+    let mut result = vc(0);
+    for (i, value) in values.iter().enumerate() {
+        result = state.cond(&index.eq(&vc(i as u64)), value, &result);
+    }
+    result
+}
+
+pub fn list_size(state: &mut State, args: &[Value]) -> Value {
+    vc(collection_get(state, &args[0]).len() as u64)
+}
+
+pub fn list_is_empty(state: &mut State, args: &[Value]) -> Value {
+    vc(collection_get(state, &args[0]).is_empty() as u64)
+}
+
+pub fn list_contains(state: &mut State, args: &[Value]) -> Value {
+    let values = collection_get(state, &args[0]);
+    values.iter().fold(vc(0), |found, value| {
+        found | keys_equal(state, value, &args[1])
+    })
+}
+
+pub fn collection_clear(state: &mut State, args: &[Value]) -> Value {
+    collection_set(state, &args[0], vec![]);
+    vc(0)
+}
+
+pub fn map_put(state: &mut State, args: &[Value]) -> Value {
+    let mut values = collection_get(state, &args[0]);
+    let previous = map_get(state, args);
+
+    // overwrite equal keys, otherwise the later entry wins in get
+    let existing = (0..values.len())
+        .step_by(2)
+        .find(|i| keys_equal(state, &values[*i], &args[1]).as_u64() == Some(1));
+
+    if let Some(ind) = existing {
+        values[ind + 1] = args[2].to_owned();
+    } else {
+        values.push(args[1].to_owned());
+        values.push(args[2].to_owned());
+    }
+    collection_set(state, &args[0], values);
+    previous
+}
+
+pub fn map_get(state: &mut State, args: &[Value]) -> Value {
+    let values = collection_get(state, &args[0]);
+
+    let mut result = vc(0);
+    for pair in values.chunks(2) {
+        if pair.len() == 2 {
+            let equal = keys_equal(state, &pair[0], &args[1]);
+            result = state.cond(&equal, &pair[1], &result);
+        }
+    }
+    result
+}
+
+pub fn map_contains_key(state: &mut State, args: &[Value]) -> Value {
+    let values = collection_get(state, &args[0]);
+    values.iter().step_by(2).fold(vc(0), |found, key| {
+        found | keys_equal(state, key, &args[1])
+    })
+}
+
+pub fn map_size(state: &mut State, args: &[Value]) -> Value {
+    vc(collection_get(state, &args[0]).len() as u64 / 2)
+}
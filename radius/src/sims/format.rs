@@ -11,23 +11,274 @@ const NONUINTS: [char; 15] = [
     'd', 'i', 'f', 'F', 'e', 'E', 'g', 'G', 'a', 'A', 'c', 's', 'n', '%', 'p',
 ];
 
+/// The kind of value a conversion takes from the variadic arguments
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgType {
+    Int,
+    Wide, // long long, 8 bytes even on 32 bit
+    Float,
+}
+
+/// The variadic arguments of a format function, either the arguments of
+/// the call followed by the stack or a `va_list` for the v* functions
+#[derive(Debug, Clone)]
+pub enum VarArgs {
+    /// Argument values then arguments on the stack at `stack`. If the ABI
+    /// passes floats in vector registers they come from `floats` then the stack
+    Direct {
+        args: Vec<Value>,
+        index: usize,
+        stack: Value,
+        floats: Option<Vec<Value>>,
+    },
+    /// SysV x86_64 `va_list`, general and fp registers are saved in `save_area`
+    SysV {
+        gp_offset: u64,
+        fp_offset: u64,
+        overflow: Value,
+        save_area: Value,
+    },
+    /// AArch64 `va_list`, the register offsets are negative from the tops
+    Aapcs64 {
+        stack: Value,
+        gr_top: Value,
+        vr_top: Value,
+        gr_offs: i64,
+        vr_offs: i64,
+    },
+    /// `va_list` that just points at the arguments, x86, ARM32 and darwin
+    Pointer(Value),
+}
+
+impl VarArgs {
+    /// Get the variadic args of a call, `args` are the remaining sim args
+    pub fn direct(state: &mut State, args: &[Value]) -> Self {
+        let ptr = state.memory.bits / 8;
+        let sp = state.registers.get_with_alias("SP");
+        let pc = state.registers.get_pc().as_u64().unwrap_or_default();
+        let regs = state.r2api.get_cc(pc).unwrap_or_default().args.len();
+        let bin = state.info.bin.clone();
+
+        // darwin arm64 passes every variadic arg on the stack
+        if bin.os == "darwin" && bin.arch == "arm" && bin.bits == 64 {
+            return VarArgs::Direct {
+                args: vec![],
+                index: 0,
+                stack: sp,
+                floats: None,
+            };
+        }
+
+        // sysv x86_64 and aarch64 pass floats in the vector registers
+        let fp_regs = if bin.arch == "x86" && bin.bits == 64 && bin.os != "windows" {
+            Some("xmm")
+        } else if bin.arch == "arm" && bin.bits == 64 {
+            Some("d")
+        } else {
+            None
+        };
+        let floats = fp_regs.map(|fp_regs| {
+            (0..8)
+                .map(|i| format!("{}{}", fp_regs, i))
+                .filter(|reg| state.registers.get_register(reg).is_some())
+                .map(|reg| state.registers.get(&reg).slice(63, 0))
+                .collect()
+        });
+
+        let stack = if regs == 0 {
+            // the 8 stack slots were already read as args, continue after them
+            sp.add(&vc(ptr * 9))
+        } else if state.registers.aliases.contains_key("LR") {
+            sp
+        } else if state.info.bin.os == "windows" && ptr == 8 {
+            sp.add(&vc(8 + 32)) // skip the shadow space
+        } else {
+            sp.add(&vc(ptr)) // skip the return address
+        };
+
+        VarArgs::Direct {
+            args: args.to_owned(),
+            index: 0,
+            stack,
+            floats,
+        }
+    }
+
+    /// Read the `va_list` argument `ap` with the layout of the target ABI
+    pub fn va_list(state: &mut State, ap: &Value) -> Self {
+        let bin = state.info.bin.clone();
+        if bin.arch == "x86" && bin.bits == 64 && bin.os != "windows" {
+            let gp_offset = state.memory_read_value(ap, 4);
+            let fp_offset = state.memory_read_value(&ap.add(&vc(4)), 4);
+            VarArgs::SysV {
+                gp_offset: state.solver.evalcon_to_u64(&gp_offset).unwrap_or(48),
+                fp_offset: state.solver.evalcon_to_u64(&fp_offset).unwrap_or(176),
+                overflow: state.memory_read_value(&ap.add(&vc(8)), 8),
+                save_area: state.memory_read_value(&ap.add(&vc(16)), 8),
+            }
+        } else if bin.arch == "arm" && bin.bits == 64 && bin.os != "darwin" {
+            let gr_offs = state.memory_read_value(&ap.add(&vc(24)), 4);
+            let vr_offs = state.memory_read_value(&ap.add(&vc(28)), 4);
+            let gr_offs = state.solver.evalcon_to_u64(&gr_offs).unwrap_or(0) as i32;
+            let vr_offs = state.solver.evalcon_to_u64(&vr_offs).unwrap_or(0) as i32;
+            VarArgs::Aapcs64 {
+                stack: state.memory_read_value(ap, 8),
+                gr_top: state.memory_read_value(&ap.add(&vc(8)), 8),
+                vr_top: state.memory_read_value(&ap.add(&vc(16)), 8),
+                gr_offs: gr_offs as i64,
+                vr_offs: vr_offs as i64,
+            }
+        } else {
+            // arm32 va_list is a struct with one pointer, passed by value
+            VarArgs::Pointer(ap.to_owned())
+        }
+    }
+
+    /// Get the next argument of the given type
+    pub fn next(&mut self, state: &mut State, arg_type: &ArgType) -> Value {
+        let ptr = state.memory.bits / 8;
+        match self {
+            VarArgs::Direct {
+                args,
+                index,
+                stack,
+                floats,
+            } => {
+                if let Some(floats) = floats.as_mut().filter(|_| *arg_type == ArgType::Float) {
+                    if !floats.is_empty() {
+                        return floats.remove(0);
+                    }
+                    // the rest are on the stack with any extra ints
+                    let value = state.memory_read_value(stack, ptr as usize);
+                    *stack = stack.add(&vc(ptr));
+                    return value;
+                }
+
+                let wide = *arg_type != ArgType::Int && ptr == 4;
+                let mut value = VarArgs::next_direct(state, args, index, stack);
+                if wide {
+                    let high = VarArgs::next_direct(state, args, index, stack);
+                    value = value.slice(31, 0).or(&(high.slice(31, 0).uext(&vc(32)) << vc(32)));
+                }
+                value
+            }
+            VarArgs::SysV {
+                gp_offset,
+                fp_offset,
+                overflow,
+                save_area,
+            } => {
+                let (offset, max, step) = if *arg_type == ArgType::Float {
+                    (fp_offset, 176, 16)
+                } else {
+                    (gp_offset, 48, 8)
+                };
+
+                if *offset < max {
+                    let value = state.memory_read_value(&save_area.add(&vc(*offset)), 8);
+                    *offset += step;
+                    value
+                } else {
+                    let value = state.memory_read_value(overflow, 8);
+                    *overflow = overflow.add(&vc(8));
+                    value
+                }
+            }
+            VarArgs::Aapcs64 {
+                stack,
+                gr_top,
+                vr_top,
+                gr_offs,
+                vr_offs,
+            } => {
+                let (top, offs, step) = if *arg_type == ArgType::Float {
+                    (vr_top, vr_offs, 16)
+                } else {
+                    (gr_top, gr_offs, 8)
+                };
+
+                if *offs < 0 {
+                    let value = state.memory_read_value(&top.add(&vc(*offs as u64)), 8);
+                    *offs += step;
+                    value
+                } else {
+                    let value = state.memory_read_value(stack, 8);
+                    *stack = stack.add(&vc(8));
+                    value
+                }
+            }
+            VarArgs::Pointer(ap) => {
+                let size = if *arg_type == ArgType::Int { ptr } else { 8 };
+                if size == 8 && ptr == 4 && state.info.bin.arch == "arm" {
+                    *ap = ap.add(&vc(7)).and(&vc(!7)); // 8 byte aligned
+                }
+                let value = state.memory_read_value(ap, size as usize);
+                *ap = ap.add(&vc(size));
+                value
+            }
+        }
+    }
+
+    fn next_direct(state: &mut State, args: &[Value], index: &mut usize, stack: &mut Value) -> Value {
+        let value = if let Some(arg) = args.get(*index) {
+            arg.to_owned()
+        } else {
+            let ptr = state.memory.bits / 8;
+            let value = state.memory_read_value(stack, ptr as usize);
+            *stack = stack.add(&vc(ptr));
+            value
+        };
+        *index += 1;
+        value
+    }
+}
+
+// get the type of the next conversion, eg. "lld" is Wide and "f" is Float
+fn next_arg_type(formatstr: &[Value]) -> ArgType {
+    let mut longs = 0;
+    for c in formatstr {
+        match c.as_u64().map(|c| c as u8 as char) {
+            Some('l') => longs += 1,
+            Some('q') | Some('j') => longs += 2,
+            Some(f) if "fFeEgGaA".contains(f) => return ArgType::Float,
+            Some(f) if FORMATS.contains(&f) => break,
+            None => break,
+            _ => {}
+        }
+    }
+
+    if longs > 1 {
+        ArgType::Wide
+    } else {
+        ArgType::Int
+    }
+}
+
 // the value returned is the formatted string
 pub fn format(state: &mut State, args: &[Value]) -> Vec<Value> {
-    let length = state.memory_strlen(&args[0], &vc(MAXLEN as u64));
-    let mut formatstr = state.memory_read(&args[0], &length);
+    let mut var_args = VarArgs::direct(state, &args[1..]);
+    format_va(state, &args[0], &mut var_args)
+}
+
+/// Format the string at `fmt` with the arguments from `args`
+pub fn format_va(state: &mut State, fmt: &Value, args: &mut VarArgs) -> Vec<Value> {
+    let length = state.memory_strlen(fmt, &vc(MAXLEN as u64));
+    let mut formatstr = state.memory_read(fmt, &length);
     let mut result = Vec::with_capacity(MAXLEN);
 
     let mut count = 0;
-    let mut ind = 0; // argument index
     while !formatstr.is_empty() {
         let c = formatstr.remove(0);
         if !formatstr.is_empty() && state.check(&c.eq(&vc('%' as u64))) {
-            if formatstr[0].as_u64() != Some('%' as u64) {
-                ind += 1; // jank fix cuz %% shouldnt increment
-            }
+            // %% doesnt take an argument
+            let arg = if formatstr[0].as_u64() != Some('%' as u64) {
+                args.next(state, &next_arg_type(&formatstr))
+            } else {
+                vc(0)
+            };
             // if it can be % it *must* be %
             state.assert(&c.eq(&vc('%' as u64)));
-            let formatted = format_one(state, &mut formatstr, &args[ind], count);
+            let formatted = format_one(state, &mut formatstr, &arg, count);
             count += formatted.len();
             result.extend(formatted);
         } else {
@@ -179,7 +430,8 @@ fn format_int(state: &mut State, _c: &Value, arg: &Value, pre: &str) -> Vec<Valu
 }
 
 fn format_float(state: &mut State, _c: &Value, arg: &Value) -> Vec<Value> {
-    let f = f32::from_bits(state.solver.evalcon_to_u64(arg).unwrap_or_default() as u32);
+    // floats are promoted to double for varargs
+    let f = f64::from_bits(state.solver.evalcon_to_u64(arg).unwrap_or_default());
     format!("{:e}", f).chars().map(|c| vc(c as u64)).collect() // jank
 }
 
@@ -197,28 +449,53 @@ fn format_string(state: &mut State, _c: &Value, arg: &Value, pre: &str) -> Vec<V
 }
 
 pub fn scan(state: &mut State, args: &[Value]) -> Value {
-    let flength = state.memory_strlen(&args[1], &vc(MAXLEN as u64));
-    let mut formatstr = state.memory_read(&args[1], &(flength + vc(1)));
-    let mut data = args[0].to_owned();
+    let mut var_args = VarArgs::direct(state, &args[2..]);
+    scan_va(state, &args[0], &args[1], &mut var_args)
+}
+
+/// Scan the string at `data` with the format `fmt` into the pointers in `args`
+pub fn scan_va(state: &mut State, data: &Value, fmt: &Value, args: &mut VarArgs) -> Value {
+    scan_input(state, data, fmt, args).0
+}
+
+/// Scan like `scan_va`, returns the number of items assigned and the
+/// address after the last byte of `data` used by the format
+pub fn scan_input(
+    state: &mut State,
+    data: &Value,
+    fmt: &Value,
+    args: &mut VarArgs,
+) -> (Value, Value) {
+    let flength = state.memory_strlen(fmt, &vc(MAXLEN as u64));
+    let mut formatstr = state.memory_read(fmt, &(flength + vc(1)));
+    let mut data = data.to_owned();
 
     let mut count = 0;
-    let mut ind = 1; // argument index
     while !formatstr.is_empty() {
         let c = formatstr.remove(0);
         if !formatstr.is_empty() && state.check(&c.eq(&vc('%' as u64))) {
-            if formatstr[0].as_u64() != Some('%' as u64) {
-                ind += 1; // jank fix cuz %% shouldnt increment
-            }
+            // %% doesnt take an argument, the rest are all pointers
+            let literal = formatstr[0].as_u64() == Some('%' as u64);
+            let arg = if !literal {
+                args.next(state, &ArgType::Int)
+            } else {
+                vc(0)
+            };
             // if it can be % it *must* be %
             state.assert(&c.eq(&vc('%' as u64)));
-            scan_one(state, &mut formatstr, &args[ind], &mut data);
-            count += 1;
-        } else {
+            if literal {
+                formatstr.remove(0);
+                data = data + vc(1);
+            } else {
+                scan_one(state, &mut formatstr, &arg, &mut data);
+                count += 1;
+            }
+        } else if c.as_u64() != Some(0) {
             data = data + vc(1);
         }
     }
 
-    vc(count as u64)
+    (vc(count as u64), data)
 }
 
 pub fn scan_one(state: &mut State, formatstr: &mut Vec<Value>, arg: &Value, data: &mut Value) {
@@ -239,28 +516,49 @@ pub fn scan_one(state: &mut State, formatstr: &mut Vec<Value>, arg: &Value, data
                 state.evaluate_string_bv(&preformat_bv).unwrap_or_default()
             };
             let maybe_uint = formats.iter().any(|f| UINTS.contains(f));
-            let next = formatstr.remove(0); // get delimiter maybe?
-            let delim = state.memory_search(data, &next, &vc(MAXLEN as u64), false);
+            let cl = tolower(state, std::slice::from_ref(&c));
+            if cl.as_u64() != Some('c' as u64) {
+                skip_space(state, data);
+            }
+
+            // the last field ends at whitespace, the rest at the next format char
+            let next = formatstr.remove(0);
+            let last = next.as_u64() == Some(0);
+            let delim = if last {
+                field_end(state, data)
+            } else {
+                state.memory_search(data, &next, &vc(MAXLEN as u64), false)
+            };
             state.memory_write_value(&delim, &vc(0), 1); // write a null there
 
+            let mut end = delim.clone();
             if maybe_uint {
                 // ensure that it *is* a uint format
                 must_be_formats(state, &c, &UINTS);
                 scan_uint(state, &c, arg, data);
             } else {
                 must_be_formats(state, &c, &NONUINTS);
-                let cl = tolower(state, &[c]);
                 let cc = state.solver.evalcon_to_u64(&cl).unwrap_or(0) as u8;
 
                 match cc as char {
-                    'c' => state.memory_write_value(arg, data, 1),
+                    'c' => {
+                        state.memory_write_value(arg, data, 1);
+                        end = data.add(&vc(1));
+                    }
                     'p' => scan_uint(state, &vc(cc as u64), arg, data),
                     'd' | 'i' => scan_int(state, arg, data),
                     'f' | 'e' | 'g' | 'a' => scan_float(state, arg, data),
-                    's' => scan_string(state, arg, data, &preformat_str),
+                    's' => end = data.add(&scan_string(state, arg, data, &preformat_str)),
                     _ => {}
                 }
             }
+
+            // a delimiter from the format is used too
+            *data = if last {
+                end
+            } else {
+                state.cond(&end.eq(&delim), &end.add(&vc(1)), &end)
+            };
             break;
         } else {
             preformat.push(c);
@@ -268,32 +566,65 @@ pub fn scan_one(state: &mut State, formatstr: &mut Vec<Value>, arg: &Value, data
     }
 }
 
-pub fn scan_uint(state: &mut State, c: &Value, arg: &Value, data: &mut Value) {
+pub fn scan_uint(state: &mut State, c: &Value, arg: &Value, data: &Value) {
     let base = format_to_base(state, c);
     let value = atoi_helper(state, data, &base, 32); // TODO need to fix size for p, llx etc
     state.memory_write_value(arg, &value, 4);
-    *data = data.add(&state.memory_strlen(data, &vc(MAXLEN as u64))) + vc(1);
 }
 
-pub fn scan_int(state: &mut State, arg: &Value, data: &mut Value) {
+pub fn scan_int(state: &mut State, arg: &Value, data: &Value) {
     let value = atoi_helper(state, data, &vc(10), 32); // TODO need to fix size for p, llx etc
     state.memory_write_value(arg, &value, 4);
-    *data = data.add(&state.memory_strlen(data, &vc(MAXLEN as u64))) + vc(1);
 }
 
-pub fn scan_float(state: &mut State, arg: &Value, data: &mut Value) {
+pub fn scan_float(state: &mut State, arg: &Value, data: &Value) {
     let addr = state.solver.evalcon_to_u64(data).unwrap_or_default();
     let fs = state.memory_read_cstring(addr);
     let f = fs.parse::<f32>().unwrap_or_default();
     state.memory_write_value(arg, &vc(f32::to_bits(f) as u64), 4);
-    *data = data.add(&state.memory_strlen(data, &vc(MAXLEN as u64))) + vc(1);
 }
 
-pub fn scan_string(state: &mut State, arg: &Value, data: &mut Value, pre: &str) {
+/// Copy the string at `data` to `arg` up to the width in `pre`,
+/// returns the length of the string
+pub fn scan_string(state: &mut State, arg: &Value, data: &Value, pre: &str) -> Value {
     let limit = pre.parse::<u64>().unwrap_or(MAXLEN as u64);
-    let length = state.memory_strlen(data, &vc(limit)) + vc(1);
+    let length = state.memory_strlen(data, &vc(limit));
     state.memory_move(arg, data, &length);
-    *data = data.add(&length);
+    state.memory_write_value(&arg.add(&length), &vc(0), 1);
+    length
+}
+
+// skip leading whitespace, only concrete whitespace can be skipped
+fn skip_space(state: &mut State, data: &mut Value) {
+    while let Some(c) = state.memory_read_value(data, 1).as_u64() {
+        if !(c as u8).is_ascii_whitespace() {
+            break;
+        }
+        *data = data.add(&vc(1));
+    }
+}
+
+// get the address of the first whitespace or null at `data`
+fn field_end(state: &mut State, data: &Value) -> Value {
+    let mut ends = vec![];
+    for i in 0..MAXLEN as u64 {
+        let c = state.memory_read_value(&data.add(&vc(i)), 1);
+        let end = c.eq(&vc(0)) | c.eq(&vc(0x20)) | (c.ugte(&vc(9)) & c.ulte(&vc(0xd)));
+        if end.as_u64() == Some(0) {
+            continue;
+        }
+        let concrete = end.as_u64() == Some(1);
+        ends.push((end, i));
+        if concrete {
+            break;
+        }
+    }
+
+    let mut result = data.add(&vc(MAXLEN as u64));
+    for (end, i) in ends.into_iter().rev() {
+        result = state.cond(&end, &data.add(&vc(i)), &result);
+    }
+    result
 }
 
 fn bv_pow(bv: &Value, exp: u32) -> Value {
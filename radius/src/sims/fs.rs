@@ -1,15 +1,37 @@
-use crate::value::Value;
+use crate::value::{cond_value, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::path::Path;
 // use std::io;
-use std::time::SystemTime;
 
-#[derive(Debug, Clone)]
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFCHR: u32 = 0o020000;
+const FILE_PERM: u32 = 0o644;
+const DIR_PERM: u32 = 0o755;
+const MAX_FDS: usize = 1024; // the default RLIMIT_NOFILE
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum FileMode {
     Read,
     Write,
     Append,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeType {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MountMode {
+    /// Host files can be read but not modified
+    ReadOnly,
+    /// Host files are copied in on first use and writes stay simulated
+    CopyOnWrite,
+}
+
 /// Stat structure from kernel_stat64
 #[derive(Default, Debug, Clone)]
 pub struct Stat {
@@ -23,6 +45,7 @@ pub struct Stat {
     pub __pad0: u32,
     pub st_rdev: u64,
     pub st_size: i64,
+    pub length: Option<Value>, // symbolic st_size, at most st_size
     pub st_blksize: i32,
     pub st_blocks: i64,
 
@@ -44,11 +67,42 @@ pub struct SimFile {
     pub mode: FileMode,
     pub content: Vec<Value>,
     pub metadata: Option<fs::Metadata>,
+    pub closed: bool,
+    pub length: Option<Value>, // symbolic length, at most content.len()
 }
 
+/// A file or directory in the simulated file tree
+#[derive(Debug, Clone)]
+pub struct SimNode {
+    pub node_type: NodeType,
+    pub content: Vec<Value>,
+    pub perm: u32,
+    pub ino: u64,
+    pub writable: bool,
+    pub modified: bool, // created or changed during the run
+    pub length: Option<Value>,
+}
+
+/// A host directory that is visible in the simulated file tree
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub host: String,
+    pub path: String,
+    pub mode: MountMode,
+}
+
+/// The simulated filesystem. `files` is the table of open files indexed
+/// by fd and `nodes` is the file tree indexed by absolute path. Nothing
+/// from the host is visible unless it is under a `Mount`
 #[derive(Debug, Clone)]
 pub struct SimFilesytem {
     pub files: Vec<SimFile>,
+    pub nodes: BTreeMap<String, SimNode>,
+    pub mounts: Vec<Mount>,
+    pub removed: HashSet<String>,     // removed paths that were mounted
+    pub pipes: HashMap<usize, usize>, // write end -> read end
+    pub cwd: String,
+    pub next_ino: u64,
 }
 
 impl Default for SimFilesytem {
@@ -60,46 +114,200 @@ impl Default for SimFilesytem {
 impl SimFilesytem {
     pub fn new() -> Self {
         let files = SimFilesytem::get_stdio();
-        SimFilesytem { files }
+        let mut filesystem = SimFilesytem {
+            files,
+            nodes: BTreeMap::new(),
+            mounts: vec![],
+            removed: HashSet::new(),
+            pipes: HashMap::new(),
+            cwd: "/".to_owned(),
+            next_ino: 2,
+        };
+
+        for dir in ["/", "/tmp", "/dev", "/proc", "/etc"] {
+            filesystem.mkdir_all(dir);
+        }
+        filesystem
     }
 
-    pub fn open(&mut self, path: &str, mode: FileMode) -> Option<usize> {
-        for file in &self.files {
-            if file.path == path {
-                return Some(file.fd);
+    /// Make `path` absolute and remove `.`, `..` and repeated slashes
+    pub fn normalize(&self, path: &str) -> String {
+        let full = if path.starts_with('/') {
+            path.to_owned()
+        } else {
+            format!("{}/{}", self.cwd, path)
+        };
+
+        let mut parts: Vec<&str> = vec![];
+        for part in full.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                _ => parts.push(part),
             }
         }
+        format!("/{}", parts.join("/"))
+    }
 
-        let fd = self.files.len();
-        let data = fs::read(path);
+    fn parent(path: &str) -> String {
+        match path.rfind('/') {
+            Some(0) | None => "/".to_owned(),
+            Some(i) => path[..i].to_owned(),
+        }
+    }
 
-        if data.is_ok() {
-            let metadata = fs::metadata(path).unwrap();
+    /// Make the host directory `host` visible at `path`
+    pub fn mount(&mut self, host: &str, path: &str, mode: MountMode) {
+        let path = self.normalize(path);
+        self.mkdir_all(&path);
+        if let Some(node) = self.nodes.get_mut(&path) {
+            node.writable = mode == MountMode::CopyOnWrite;
+        }
+        self.mounts.push(Mount {
+            host: host.to_owned(),
+            path,
+            mode,
+        });
+    }
 
-            let mut content = Vec::with_capacity(512);
-            for d in data.unwrap() {
-                content.push(Value::Concrete(d as u64, 0));
-            }
+    // get the host path and mode of a path under a mount, longest mount wins
+    fn host_path(&self, path: &str) -> Option<(String, MountMode)> {
+        self.mounts
+            .iter()
+            .filter(|m| {
+                m.path == "/" || path == m.path || path.starts_with(&format!("{}/", m.path))
+            })
+            .max_by_key(|m| m.path.len())
+            .map(|m| {
+                let rest = path[m.path.len()..].trim_start_matches('/');
+                let host = Path::new(&m.host).join(rest);
+                (host.to_string_lossy().to_string(), m.mode.clone())
+            })
+    }
 
-            let file = SimFile {
-                path: path.to_owned(),
-                fd,
-                position: 0,
-                mode,
+    fn add_node(&mut self, path: &str, node_type: NodeType, content: Vec<Value>, writable: bool) {
+        let perm = if node_type == NodeType::Directory {
+            DIR_PERM
+        } else {
+            FILE_PERM
+        };
+
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.removed.remove(path);
+        self.nodes.insert(
+            path.to_owned(),
+            SimNode {
+                node_type,
                 content,
-                metadata: Some(metadata),
-            };
+                perm,
+                ino,
+                writable,
+                modified: false,
+                length: None,
+            },
+        );
+    }
+
+    /// Get the node at `path`, copying it in from a mount if needed
+    pub fn lookup(&mut self, path: &str) -> Option<&mut SimNode> {
+        let path = self.normalize(path);
+        if !self.nodes.contains_key(&path) && !self.removed.contains(&path) {
+            let (host, mode) = self.host_path(&path)?;
+            let writable = mode == MountMode::CopyOnWrite;
+            let metadata = fs::metadata(&host).ok()?;
+
+            let parent = SimFilesytem::parent(&path);
+            if !self.nodes.contains_key(&parent) {
+                self.lookup(&parent)?;
+            }
+
+            if metadata.is_dir() {
+                self.add_node(&path, NodeType::Directory, vec![], writable);
+            } else {
+                let content = fs::read(&host)
+                    .ok()?
+                    .iter()
+                    .map(|b| Value::Concrete(*b as u64, 0))
+                    .collect();
+                self.add_node(&path, NodeType::File, content, writable);
+            }
+        }
+        self.nodes.get_mut(&path)
+    }
+
+    // put the file in the lowest closed fd or at the end
+    fn alloc_fd(&mut self, file: SimFile) -> usize {
+        let fd = self
+            .files
+            .iter()
+            .position(|f| f.closed)
+            .unwrap_or(self.files.len());
 
+        let file = SimFile { fd, ..file };
+        self.pipes.remove(&fd);
+        if fd == self.files.len() {
             self.files.push(file);
-            Some(fd)
         } else {
-            None
+            self.files[fd] = file;
+        }
+        fd
+    }
+
+    pub fn open(&mut self, path: &str, mode: FileMode) -> Option<usize> {
+        let path = self.normalize(path);
+        let node = self.lookup(&path)?;
+        if mode != FileMode::Read && (!node.writable || node.node_type == NodeType::Directory) {
+            return None;
+        }
+
+        let content = node.content.clone();
+        let length = node.length.clone();
+        let position = if mode == FileMode::Append {
+            content.len()
+        } else {
+            0
+        };
+
+        Some(self.alloc_fd(SimFile {
+            path,
+            fd: 0,
+            position,
+            mode,
+            content,
+            metadata: None,
+            closed: false,
+            length,
+        }))
+    }
+
+    /// Create an empty file at `path` (or truncate it) and open it
+    pub fn create(&mut self, path: &str, mode: FileMode) -> Option<usize> {
+        let path = self.normalize(path);
+        let parent = SimFilesytem::parent(&path);
+        if !self.lookup(&parent).map(|p| p.writable).unwrap_or(false) {
+            return None;
+        }
+
+        if let Some(node) = self.lookup(&path) {
+            if !node.writable || node.node_type == NodeType::Directory {
+                return None;
+            }
+            node.content.clear();
+            node.length = None;
+            node.modified = true;
+        } else {
+            self.add_node(&path, NodeType::File, vec![], true);
+            self.set_modified(&path);
         }
+        self.open(&path, mode)
     }
 
     pub fn read(&mut self, fd: usize, length: usize) -> Vec<Value> {
         if let Some(file) = &mut self.files.get_mut(fd) {
-            let start = file.position;
+            let start = file.position.min(file.content.len());
             let end = if file.content.len() - start < length {
                 file.content.len()
             } else {
@@ -113,145 +321,468 @@ impl SimFilesytem {
         }
     }
 
+    /// Push the byte `c` back so it is the next byte read from `fd`
+    pub fn unread(&mut self, fd: usize, c: Value) {
+        if let Some(file) = self.files.get_mut(fd) {
+            if file.position > 0 {
+                file.position -= 1;
+                if let Some(byte) = file.content.get_mut(file.position) {
+                    *byte = c;
+                }
+            } else {
+                file.content.insert(0, c);
+                file.length = file.length.as_ref().map(|l| l.add(&Value::Concrete(1, 0)));
+            }
+        }
+    }
+
     pub fn fill(&mut self, fd: usize, data: &[Value]) {
         self.files[fd].content.extend(data.to_owned());
     }
 
+    /// Give the open file `fd` a symbolic length, reads past it return EOF
+    pub fn set_length(&mut self, fd: usize, length: Value) {
+        self.files[fd].length = Some(length);
+    }
+
+    /// Give the file at `path` a symbolic length
+    pub fn set_path_length(&mut self, path: &str, length: Value) -> bool {
+        if let Some(node) = self.lookup(path) {
+            node.length = Some(length);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn dump(&mut self, fd: usize) -> Vec<Value> {
         self.files[fd].content.clone()
     }
 
+    /// Get the contents of the file at `path`
+    pub fn dump_path(&mut self, path: &str) -> Option<Vec<Value>> {
+        if let Some(fd) = self.getfd(path) {
+            Some(self.dump(fd))
+        } else {
+            self.lookup(path).map(|node| node.content.clone())
+        }
+    }
+
     pub fn write(&mut self, fd: usize, data: Vec<Value>) -> Option<Value> {
+        self.files.get(fd).filter(|f| !f.closed)?;
+        if let Some(read_fd) = self.pipes.get(&fd) {
+            if let Some(file) = self.files.get_mut(*read_fd).filter(|f| !f.closed) {
+                file.content.extend(data.clone());
+            }
+        }
+
         if let Some(file) = &mut self.files.get_mut(fd) {
             let length = data.len();
-            file.position += length;
-            file.content.extend(data);
+            if file.mode == FileMode::Append {
+                file.position = file.content.len();
+            }
+
+            // overwrite from the position and extend past the end
+            let start = file.position.min(file.content.len());
+            let end = (start + length).min(file.content.len());
+            file.content.splice(start..end, data);
+            file.position = start + length;
+
+            // a write past a symbolic length extends it
+            if let Some(len) = &file.length {
+                let pos = Value::Concrete(file.position as u64, 0);
+                file.length = Some(match len.ult(&pos) {
+                    Value::Concrete(c, _) if c != 0 => pos,
+                    Value::Concrete(_, _) => len.to_owned(),
+                    Value::Symbolic(c, t) => {
+                        Value::Symbolic(cond_value(&c, pos, len.to_owned()), t)
+                    }
+                });
+            }
+
+            // keep the tree in sync so later opens see the writes
+            if let Some(node) = self.nodes.get_mut(&file.path) {
+                node.content = file.content.clone();
+                node.length = file.length.clone();
+                node.modified = true;
+            }
             Some(Value::Concrete(length as u64, 0))
         } else {
             None
         }
     }
 
-    pub fn seek(&mut self, fd: usize, pos: usize, whence: usize) -> Option<Value> {
-        if let Some(file) = &mut self.files.get_mut(fd) {
-            if whence == 0 {
-                file.position = pos;
-            } else if whence == 1 {
-                file.position += pos;
-            } else if whence == 2 {
-                file.position = file.content.len() + pos;
-            }
-            Some(Value::Concrete(file.position as u64, 0))
-        } else {
-            None
+    /// Move the position of `fd`, offsets before the start are an error.
+    /// With a symbolic length SEEK_END returns the symbolic offset while
+    /// the concrete position stays relative to the end of the content
+    pub fn seek(&mut self, fd: usize, pos: i64, whence: usize) -> Option<Value> {
+        let file = self.files.get_mut(fd).filter(|f| !f.closed)?;
+        let base = match whence {
+            0 => 0,
+            1 => file.position as i64,
+            2 => file.content.len() as i64,
+            _ => return None,
+        };
+        let position = base.checked_add(pos).filter(|p| *p >= 0)?;
+        file.position = position as usize;
+        match (&file.length, whence) {
+            (Some(length), 2) => Some(length.add(&Value::Concrete(pos as u64, 0))),
+            _ => Some(Value::Concrete(file.position as u64, 0)),
         }
     }
 
     pub fn getfd(&mut self, path: &str) -> Option<usize> {
-        self.files.iter().find(|f| f.path == path).map(|f| f.fd)
+        let full = self.normalize(path);
+        self.files
+            .iter()
+            .find(|f| !f.closed && (f.path == path || f.path == full))
+            .map(|f| f.fd)
     }
 
     pub fn getpath(&mut self, fd: usize) -> Option<String> {
         self.files
             .iter()
-            .find(|f| f.fd == fd)
+            .find(|f| f.fd == fd && !f.closed)
             .map(|f| f.path.clone())
     }
 
-    pub fn access(&mut self, path: &str) -> Value {
-        if self.files.iter().any(|f| f.path == path) {
-            return Value::Concrete(0, 0);
-        }
+    /// Check permissions, `mode` is R_OK 4, W_OK 2, X_OK 1 or F_OK 0
+    pub fn access(&mut self, path: &str, mode: u32) -> Value {
+        if let Some(node) = self.lookup(path) {
+            let perm = if node.writable {
+                node.perm
+            } else {
+                node.perm & !0o222
+            };
 
-        let metadata = fs::metadata(path);
-        if metadata.is_ok() {
-            Value::Concrete(0, 0)
-        } else {
-            Value::Concrete(-1i64 as u64, 0)
+            if (perm >> 6) & mode == mode {
+                return Value::Concrete(0, 0);
+            }
         }
+        Value::Concrete(-1i64 as u64, 0)
     }
 
     pub fn stat(&mut self, path: &str) -> Option<Stat> {
-        let metadata = fs::metadata(path);
-        if let Ok(meta) = metadata {
+        let node = self.lookup(path)?;
+        let (kind, size, length) = if node.node_type == NodeType::Directory {
+            (S_IFDIR, 0x1000, None)
+        } else {
+            (S_IFREG, node.content.len() as i64, node.length.clone())
+        };
+
+        // timestamps are all 0 so runs are reproducible
+        Some(Stat {
+            st_dev: 0x803,
+            st_ino: node.ino,
+            st_mode: kind | node.perm,
+            st_nlink: 1,
+            st_size: size,
+            length,
+            st_blksize: 0x1000,
+            st_blocks: (size + 511) / 512,
+            ..Stat::default()
+        })
+    }
+
+    /// Get the stat of an open fd, stdio and pipes are character devices
+    pub fn fstat(&mut self, fd: usize) -> Option<Stat> {
+        let path = self.getpath(fd)?;
+        if self.nodes.contains_key(&path) {
+            let length = self.files[fd].length.clone();
+            let stat = self.stat(&path)?;
             Some(Stat {
-                st_dev: 16777234,
-                st_ino: 3334575,
-                st_mode: 33188,
-                st_nlink: 0,
-                st_uid: 0,
-                st_gid: 0,
-                st_rdev: 0,
-                __pad0: 0,
-
-                st_size: meta.len() as i64,
-                st_blksize: 0x1000,
-                st_blocks: 8,
-
-                st_atime: meta
-                    .accessed()
-                    .unwrap()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                st_atimensec: 0,
-                st_mtime: meta
-                    .modified()
-                    .unwrap()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                st_mtimensec: 0,
-                st_ctime: meta
-                    .created()
-                    .unwrap()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                st_ctimensec: 0,
-
-                __glibc_reserved: [0, 0],
+                length: length.or(stat.length.clone()),
+                ..stat
             })
         } else {
-            None
+            Some(Stat {
+                st_ino: fd as u64 + 1,
+                st_mode: S_IFCHR | 0o620,
+                st_nlink: 1,
+                st_blksize: 0x400,
+                ..Stat::default()
+            })
         }
     }
 
-    pub fn touch(&mut self, path: &str, mode: FileMode) {
-        let fd = self.files.len();
-
-        let file = SimFile {
+    pub fn touch(&mut self, path: &str, mode: FileMode) -> usize {
+        self.alloc_fd(SimFile {
             path: path.to_owned(),
-            fd,
+            fd: 0,
             position: 0,
             mode,
             content: vec![],
             metadata: None,
-        };
-
-        self.files.push(file);
+            closed: false,
+            length: None,
+        })
     }
 
     pub fn close(&mut self, fd: usize) {
-        self.seek(fd, 0, 0); // uhh just go to 0 for now
+        if let Some(file) = self.files.get_mut(fd) {
+            file.closed = true;
+        }
+        self.pipes.remove(&fd);
+        self.pipes.retain(|_, read_fd| *read_fd != fd);
+    }
+
+    /// Create a pipe, returns the read and write fds
+    pub fn pipe(&mut self) -> (usize, usize) {
+        let read_fd = self.touch("PIPE", FileMode::Read);
+        let write_fd = self.touch("PIPE", FileMode::Write);
+        self.files[read_fd].path = format!("PIPE{}", read_fd);
+        self.files[write_fd].path = format!("PIPE{}", write_fd);
+        self.pipes.insert(write_fd, read_fd);
+        (read_fd, write_fd)
+    }
+
+    /// Duplicate `fd` to `new_fd` or the lowest available fd.
+    /// The new fd gets a copy of the file, they are not shared
+    pub fn dup(&mut self, fd: usize, new_fd: Option<usize>) -> Option<usize> {
+        let file = self.files.get(fd).filter(|f| !f.closed)?.clone();
+        let new_fd = if let Some(new_fd) = new_fd {
+            if new_fd >= MAX_FDS {
+                return None;
+            }
+
+            // fill the table up to new_fd with closed files
+            while self.files.len() < new_fd {
+                let fd = self.files.len();
+                self.files.push(SimFile {
+                    fd,
+                    content: vec![],
+                    closed: true,
+                    length: None,
+                    ..file.clone()
+                });
+            }
+            new_fd
+        } else {
+            self.files
+                .iter()
+                .position(|f| f.closed)
+                .unwrap_or(self.files.len())
+        };
+
+        if let Some(read_fd) = self.pipes.get(&fd) {
+            let read_fd = *read_fd;
+            self.pipes.insert(new_fd, read_fd);
+        } else {
+            self.pipes.remove(&new_fd);
+        }
+
+        let file = SimFile { fd: new_fd, ..file };
+        if new_fd == self.files.len() {
+            self.files.push(file);
+        } else {
+            self.files[new_fd] = file;
+        }
+        Some(new_fd)
     }
 
+    /// Add a file with the given contents to the tree, any missing
+    /// parent directories are created
     pub fn add_file(&mut self, path: &str, data: &[Value]) {
-        self.files.push(SimFile {
-            path: path.to_owned(),
-            fd: self.files.len(),
-            position: 0,
-            mode: FileMode::Read,
-            content: data.to_owned(),
-            metadata: None,
-        });
+        let path = self.normalize(path);
+        self.mkdir_all(&SimFilesytem::parent(&path));
+        self.add_node(&path, NodeType::File, data.to_owned(), true);
     }
 
     pub fn remove_file(&mut self, path: &str) {
-        let f = self.files.iter().find(|f| f.path == path).map(|f| f.fd);
-        if let Some(fd) = f {
-            self.files.remove(fd);
+        self.unlink(path);
+    }
+
+    /// Create a directory, its parent must exist
+    pub fn mkdir(&mut self, path: &str) -> bool {
+        let path = self.normalize(path);
+        let parent = SimFilesytem::parent(&path);
+        if self.lookup(&path).is_some() {
+            return false;
+        } else if path != "/" && !self.lookup(&parent).map(|p| p.writable).unwrap_or(false) {
+            return false;
+        }
+        self.add_node(&path, NodeType::Directory, vec![], true);
+        self.set_modified(&path);
+        true
+    }
+
+    /// Create a directory and any missing parents
+    pub fn mkdir_all(&mut self, path: &str) {
+        let path = self.normalize(path);
+        if path != "/" {
+            self.mkdir_all(&SimFilesytem::parent(&path));
+        }
+        if self.lookup(&path).is_none() {
+            self.add_node(&path, NodeType::Directory, vec![], true);
+        }
+    }
+
+    fn remove(&mut self, path: &str, node_type: NodeType) -> bool {
+        let path = self.normalize(path);
+        let parent = SimFilesytem::parent(&path);
+        let writable = self.lookup(&parent).map(|p| p.writable).unwrap_or(false);
+
+        match self.lookup(&path) {
+            Some(node) if node.node_type == node_type && writable => {}
+            _ => return false,
+        }
+        if node_type == NodeType::Directory && !self.list(&path).unwrap_or_default().is_empty() {
+            return false;
         }
+
+        self.nodes.remove(&path);
+        if self.host_path(&path).is_some() {
+            self.removed.insert(path);
+        }
+        true
+    }
+
+    /// Remove the file at `path`
+    pub fn unlink(&mut self, path: &str) -> bool {
+        self.remove(path, NodeType::File)
+    }
+
+    /// Remove the empty directory at `path`
+    pub fn rmdir(&mut self, path: &str) -> bool {
+        self.remove(path, NodeType::Directory)
+    }
+
+    /// Move the file or directory `old` to `new`
+    pub fn rename(&mut self, old: &str, new: &str) -> bool {
+        let old = self.normalize(old);
+        let new = self.normalize(new);
+        let node = if let Some(node) = self.lookup(&old) {
+            node.clone()
+        } else {
+            return false;
+        };
+
+        let parent = SimFilesytem::parent(&new);
+        if !node.writable || !self.lookup(&parent).map(|p| p.writable).unwrap_or(false) {
+            return false;
+        } else if old == new {
+            return true;
+        } else if new.starts_with(&format!("{}/", old)) {
+            return false; // can't move a directory into itself
+        }
+
+        // the new node is added first so the children can be moved into it
+        let children = if node.node_type == NodeType::Directory {
+            self.list(&old).unwrap_or_default()
+        } else {
+            vec![]
+        };
+        self.removed.remove(&new);
+        self.nodes.insert(
+            new.clone(),
+            SimNode {
+                modified: true,
+                ..node
+            },
+        );
+        for (name, _) in children {
+            if !self.rename(&format!("{}/{}", old, name), &format!("{}/{}", new, name)) {
+                return false;
+            }
+        }
+
+        self.nodes.remove(&old);
+        if self.host_path(&old).is_some() {
+            self.removed.insert(old);
+        }
+        true
+    }
+
+    /// Set the permission bits of `path`
+    pub fn chmod(&mut self, path: &str, perm: u32) -> bool {
+        if let Some(node) = self.lookup(path).filter(|n| n.writable) {
+            node.perm = perm & 0o7777;
+            node.modified = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Change the current directory
+    pub fn chdir(&mut self, path: &str) -> bool {
+        let path = self.normalize(path);
+        match self.lookup(&path) {
+            Some(node) if node.node_type == NodeType::Directory => {
+                self.cwd = path;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// List the names and types of the entries in the directory `path`
+    pub fn list(&mut self, path: &str) -> Option<Vec<(String, NodeType)>> {
+        let path = self.normalize(path);
+        if self.lookup(&path)?.node_type != NodeType::Directory {
+            return None;
+        }
+
+        let prefix = if path == "/" {
+            "/".to_owned()
+        } else {
+            format!("{}/", path)
+        };
+
+        // copy in mounted entries that have not been used yet
+        if let Some((host, _)) = self.host_path(&path) {
+            let names: Vec<String> = fs::read_dir(host)
+                .map(|dir| {
+                    dir.filter_map(|e| e.ok())
+                        .map(|e| e.file_name().to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            for name in names {
+                self.lookup(&format!("{}{}", prefix, name));
+            }
+        }
+
+        let entries = self
+            .nodes
+            .range(prefix.clone()..)
+            .take_while(|(p, _)| p.starts_with(&prefix))
+            .filter(|(p, _)| p.len() > prefix.len() && !p[prefix.len()..].contains('/'))
+            .map(|(p, n)| (p[prefix.len()..].to_owned(), n.node_type.clone()))
+            .collect();
+
+        Some(entries)
+    }
+
+    /// Get the next entry of an open directory, its name, type and inode
+    pub fn readdir(&mut self, fd: usize) -> Option<(String, NodeType, u64)> {
+        let path = self.getpath(fd)?;
+        let entries = self.list(&path)?;
+        let index = self.files[fd].position;
+        let (name, node_type) = entries.get(index)?.to_owned();
+        self.files[fd].position += 1;
+
+        let ino = self
+            .lookup(&format!("{}/{}", path, name))
+            .map(|n| n.ino)
+            .unwrap_or(0);
+        Some((name, node_type, ino))
+    }
+
+    fn set_modified(&mut self, path: &str) {
+        if let Some(node) = self.nodes.get_mut(path) {
+            node.modified = true;
+        }
+    }
+
+    /// Get the paths and nodes that were created or changed during the run
+    pub fn modified(&self) -> Vec<(String, SimNode)> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.modified)
+            .map(|(path, node)| (path.to_owned(), node.to_owned()))
+            .collect()
     }
 
     pub fn get_stdio() -> Vec<SimFile> {
@@ -263,6 +794,8 @@ impl SimFilesytem {
                 mode: FileMode::Read,
                 content: Vec::with_capacity(256),
                 metadata: None,
+                closed: false,
+                length: None,
             },
             SimFile {
                 path: "STDOUT".to_owned(),
@@ -271,6 +804,8 @@ impl SimFilesytem {
                 mode: FileMode::Write,
                 content: Vec::with_capacity(256),
                 metadata: None,
+                closed: false,
+                length: None,
             },
             SimFile {
                 path: "STDERR".to_owned(),
@@ -279,6 +814,8 @@ impl SimFilesytem {
                 mode: FileMode::Write,
                 content: Vec::with_capacity(256),
                 metadata: None,
+                closed: false,
+                length: None,
             },
         ]
     }
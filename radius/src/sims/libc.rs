@@ -1,4 +1,6 @@
-use crate::sims::format;
+use crate::memory::{get_file_layout, FileLayout};
+use crate::sims::format::{self, VarArgs};
+use crate::sims::fs::FileMode;
 use crate::sims::syscall;
 use crate::state::State;
 use crate::value::{vc, Value};
@@ -30,102 +32,276 @@ pub fn putchar(state: &mut State, args: &[Value]) -> Value {
     c
 }
 
-fn readline(state: &mut State, args: &[Value]) -> Value {
-    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap_or(0) as usize;
-    let mut p = args[1].to_owned();
+// read from `fd` until `delim` or `max` bytes, returns the bytes, the
+// number read and whether the end of the file was hit. symbolic bytes
+// are never the delimiter, bytes past a symbolic eof are 0
+fn read_until(
+    state: &mut State,
+    fd: usize,
+    delim: Option<u64>,
+    max: usize,
+) -> (Vec<Value>, Value, Value) {
+    let mut data = Vec::with_capacity(256);
+    let mut count = vc(0);
+    let mut hit_eof = vc(0);
+
+    while data.len() < max {
+        let eof = syscall::at_eof(state, fd);
+        let c = if let Some(c) = state.filesystem.read(fd, 1).get(0) {
+            c.to_owned()
+        } else {
+            hit_eof = vc(1);
+            break;
+        };
 
-    loop {
-        let c = state
-            .filesystem
-            .read(fd, 1)
-            .get(0)
-            .unwrap_or(&vc(-1i64 as u64))
-            .to_owned();
-
-        if c.as_u64() != Some(-1i64 as u64) {
-            // uhhh idk we cant do symbolic file pos yet so
-            // this is where we are at
-            if c.as_u64() == Some('\n' as u64) {
-                break;
+        if eof.as_u64() == Some(1) {
+            hit_eof = vc(1);
+            break;
+        }
+
+        // uhhh idk we cant do symbolic file pos yet so
+        // this is where we are at
+        if let Some(d) = delim {
+            if c.is_symbolic() && eof.is_symbolic() {
+                // symbolic length, the line ends at the delim or eof
+                state.assert(&(eof.to_owned() | !c.eq(&vc(d))));
             } else if c.is_symbolic() {
-                state.assert(&!c.eq(&vc('\n' as u64)));
+                state.assert(&!c.eq(&vc(d)));
             }
-            state.memory_write_value(&p, &c, 1);
-            p = p + vc(1);
-        } else {
+        }
+
+        count = count.add(&state.cond(&eof, &vc(0), &vc(1)));
+        hit_eof = hit_eof.or(&eof);
+        data.push(state.cond(&eof, &vc(0), &c));
+        if delim.is_some() && c.as_u64() == delim {
             break;
         }
     }
-    state.memory_write_value(&p, &vc(0), 1);
-    args[1].to_owned()
+    (data, count, hit_eof)
+}
+
+fn readline(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap_or(0) as usize;
+    let (mut data, count, eof) = read_until(state, fd, Some('\n' as u64), MAX_LEN as usize);
+    let stream = std_stream(state, fd);
+    let eof_flag = file_layout(state).eof;
+    set_stream_flag(state, &stream, eof_flag, &eof);
+
+    // the newline is not kept
+    if data.last().and_then(|c| c.as_u64()) == Some('\n' as u64) {
+        data.pop();
+    }
+    data.push(vc(0));
+    state.memory_write(&args[1], &data, &vc(data.len() as u64));
+    state.cond(&count.eq(&vc(0)), &vc(0), &args[1])
 }
 
 pub fn getchar(state: &mut State, _args: &[Value]) -> Value {
-    state
-        .filesystem
-        .read(0, 1)
-        .get(0)
-        .unwrap_or(&vc(-1i64 as u64))
-        .to_owned()
+    let stream = std_stream(state, 0);
+    getc_helper(state, 0, &stream)
 }
 
-pub fn fprintf(state: &mut State, args: &[Value]) -> Value {
-    let fd = fileno(state, &args[0..1]);
-    let fdn = state.solver.evalcon_to_u64(&fd).unwrap_or(1);
-    let formatted = format::format(state, args);
-    let ret = vc(formatted.len() as u64);
-    state.filesystem.write(fdn as usize, formatted);
-    ret
+pub fn fgetc(state: &mut State, args: &[Value]) -> Value {
+    let fd = stream_fd(state, &args[0]);
+    getc_helper(state, fd, &args[0])
 }
 
-pub fn sprintf(state: &mut State, args: &[Value]) -> Value {
-    let mut formatted = format::format(state, &args[1..]);
-    formatted.push(vc(0));
+fn getc_helper(state: &mut State, fd: usize, stream: &Value) -> Value {
+    let c = syscall::read_byte(state, fd);
+    let eof = file_layout(state).eof;
+    set_stream_flag(state, stream, eof, &c.eq(&vc(-1i64 as u64)));
+    c
+}
+
+// write formatted output to an fd, returns the length
+fn write_formatted(state: &mut State, fd: &Value, formatted: Vec<Value>) -> Value {
+    let fdn = state.solver.evalcon_to_u64(fd).unwrap_or(1);
     let ret = vc(formatted.len() as u64);
-    state.memory_write(&args[0], &formatted, &vc(formatted.len() as u64));
+    state.filesystem.write(fdn as usize, formatted);
     ret
 }
 
-pub fn snprintf(state: &mut State, args: &[Value]) -> Value {
-    let mut formatted = format::format(state, &args[2..]);
-    formatted.push(vc(0));
+// write formatted output to a buffer of at most `size` bytes
+fn print_buffer(state: &mut State, buf: &Value, size: Option<&Value>, formatted: Vec<Value>) -> Value {
     let ret = vc(formatted.len() as u64);
-    let length = state.cond(&args[1].ult(&ret), &args[1], &ret);
-    state.memory_write(&args[0], &formatted, &length);
+    let length = if let Some(size) = size {
+        if size.as_u64() == Some(0) {
+            return ret;
+        }
+        // truncate to size - 1 so there is room for the null
+        state.cond(&size.ulte(&ret), &size.sub(&vc(1)), &ret)
+    } else {
+        ret.to_owned()
+    };
+
+    state.memory_write(buf, &formatted, &length);
+    state.memory_write_value(&buf.add(&length), &vc(0), 1);
     ret
 }
 
 pub fn printf(state: &mut State, args: &[Value]) -> Value {
     let formatted = format::format(state, args);
-    let ret = vc(formatted.len() as u64);
-    state.filesystem.write(1, formatted);
-    ret
+    write_formatted(state, &vc(1), formatted)
+}
+
+pub fn vprintf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[1]);
+    let formatted = format::format_va(state, &args[0], &mut var_args);
+    write_formatted(state, &vc(1), formatted)
+}
+
+pub fn printf_chk(state: &mut State, args: &[Value]) -> Value {
+    printf(state, &args[1..])
+}
+
+pub fn vprintf_chk(state: &mut State, args: &[Value]) -> Value {
+    vprintf(state, &args[1..])
+}
+
+pub fn fprintf(state: &mut State, args: &[Value]) -> Value {
+    let fd = fileno(state, &args[0..1]);
+    let formatted = format::format(state, &args[1..]);
+    write_formatted(state, &fd, formatted)
+}
+
+pub fn vfprintf(state: &mut State, args: &[Value]) -> Value {
+    let fd = fileno(state, &args[0..1]);
+    let mut var_args = VarArgs::va_list(state, &args[2]);
+    let formatted = format::format_va(state, &args[1], &mut var_args);
+    write_formatted(state, &fd, formatted)
+}
+
+pub fn fprintf_chk(state: &mut State, args: &[Value]) -> Value {
+    fprintf(state, &[&args[0..1], &args[2..]].concat())
+}
+
+pub fn vfprintf_chk(state: &mut State, args: &[Value]) -> Value {
+    vfprintf(state, &[&args[0..1], &args[2..]].concat())
+}
+
+pub fn dprintf(state: &mut State, args: &[Value]) -> Value {
+    let formatted = format::format(state, &args[1..]);
+    write_formatted(state, &args[0], formatted)
+}
+
+pub fn vdprintf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[2]);
+    let formatted = format::format_va(state, &args[1], &mut var_args);
+    write_formatted(state, &args[0], formatted)
+}
+
+pub fn sprintf(state: &mut State, args: &[Value]) -> Value {
+    let formatted = format::format(state, &args[1..]);
+    print_buffer(state, &args[0], None, formatted)
+}
+
+pub fn vsprintf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[2]);
+    let formatted = format::format_va(state, &args[1], &mut var_args);
+    print_buffer(state, &args[0], None, formatted)
+}
+
+pub fn sprintf_chk(state: &mut State, args: &[Value]) -> Value {
+    // s, flag, slen, fmt, ...
+    let formatted = format::format(state, &args[3..]);
+    print_buffer(state, &args[0], Some(&args[2]), formatted)
+}
+
+pub fn vsprintf_chk(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[4]);
+    let formatted = format::format_va(state, &args[3], &mut var_args);
+    print_buffer(state, &args[0], Some(&args[2]), formatted)
+}
+
+pub fn snprintf(state: &mut State, args: &[Value]) -> Value {
+    let formatted = format::format(state, &args[2..]);
+    print_buffer(state, &args[0], Some(&args[1]), formatted)
+}
+
+pub fn vsnprintf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[3]);
+    let formatted = format::format_va(state, &args[2], &mut var_args);
+    print_buffer(state, &args[0], Some(&args[1]), formatted)
+}
+
+pub fn snprintf_chk(state: &mut State, args: &[Value]) -> Value {
+    // s, maxlen, flag, slen, fmt, ...
+    let formatted = format::format(state, &args[4..]);
+    print_buffer(state, &args[0], Some(&args[1]), formatted)
+}
+
+pub fn vsnprintf_chk(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[5]);
+    let formatted = format::format_va(state, &args[4], &mut var_args);
+    print_buffer(state, &args[0], Some(&args[1]), formatted)
+}
+
+// allocate the output of asprintf and store it in strp
+fn alloc_formatted(state: &mut State, strp: &Value, formatted: Vec<Value>) -> Value {
+    let buf = state.memory_alloc(&vc(formatted.len() as u64 + 1));
+    let ptrlen = (state.memory.bits / 8) as usize;
+    state.memory_write_value(strp, &buf, ptrlen);
+    print_buffer(state, &buf, None, formatted)
+}
+
+pub fn asprintf(state: &mut State, args: &[Value]) -> Value {
+    let formatted = format::format(state, &args[1..]);
+    alloc_formatted(state, &args[0], formatted)
+}
+
+pub fn vasprintf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[2]);
+    let formatted = format::format_va(state, &args[1], &mut var_args);
+    alloc_formatted(state, &args[0], formatted)
 }
 
 pub fn scanf(state: &mut State, args: &[Value]) -> Value {
-    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap_or(0);
-    let fmt = state.memory_read_cstring(addr);
-    let length = if fmt.starts_with("%") && fmt.ends_with("s") && fmt.len() > 2 {
-        vc(fmt[1..fmt.len()-1].parse::<u64>().unwrap_or(MAX_LEN))
-    } else {
-        vc(MAX_LEN)
-    };
+    let mut var_args = VarArgs::direct(state, &args[1..]);
+    stdin_scan(state, &args[0], &mut var_args)
+}
 
-    if length.as_u64().unwrap() == MAX_LEN {
-        let buf = state.memory_alloc(&length.add(&vc(1)));
-        read(state, &[vc(0), buf.clone(), length]);
-        let result = format::scan(state, &[&[buf.clone()], args].concat());
-        state.memory_free(&buf);
-        result
-    } else {
-        read(state, &[vc(0), args[1].clone(), length.clone()])
+pub fn vscanf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[1]);
+    stdin_scan(state, &args[0], &mut var_args)
+}
+
+fn stdin_scan(state: &mut State, fmt: &Value, args: &mut VarArgs) -> Value {
+    let result = scan_stream(state, 0, fmt, args);
+    let stream = std_stream(state, 0);
+    let eof = file_layout(state).eof;
+    set_stream_flag(state, &stream, eof, &result.eq(&vc(-1i64 as u64)));
+    result
+}
+
+// scan from the stream `fd` with the format `fmt`, the bytes the format
+// didn't use are left in the stream for the next read
+fn scan_stream(state: &mut State, fd: usize, fmt: &Value, args: &mut VarArgs) -> Value {
+    let length = vc(MAX_LEN);
+    let buf = state.memory_alloc(&length.add(&vc(1)));
+    let count = read(state, &[vc(fd as u64), buf.clone(), length]);
+    state.memory_write_value(&buf.add(&count), &vc(0), 1);
+    let (result, end) = format::scan_input(state, &buf, fmt, args);
+    state.memory_free(&buf);
+
+    // the position is concrete so a symbolic count keeps the whole read
+    let used = state.cond(&end.ult(&buf.add(&count)), &end.sub(&buf), &count);
+    if let Some(unused) = count.sub(&used).as_u64() {
+        state.filesystem.seek(fd, -(unused as i64), 1);
     }
+
+    // nothing left to read is EOF
+    state.cond(&count.eq(&vc(0)), &vc(-1i64 as u64), &result)
 }
 
 pub fn sscanf(state: &mut State, args: &[Value]) -> Value {
     format::scan(state, args)
 }
 
+pub fn vsscanf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[2]);
+    format::scan_va(state, &args[0], &args[1], &mut var_args)
+}
+
 pub fn memmove(state: &mut State, args: &[Value]) -> Value {
     state.memory_move(&args[0], &args[1], &args[2].slice(31, 0));
     args[0].to_owned()
@@ -197,11 +373,17 @@ pub fn gets(state: &mut State, args: &[Value]) -> Value {
 }
 
 pub fn fgets(state: &mut State, args: &[Value]) -> Value {
-    let fd = fileno(state, &args[2..3]);
-    let buf = args[0].to_owned();
-    let len = args[1].to_owned() - vc(1);
-    syscall::read(state, &[fd, buf, len]);
-    args[0].to_owned()
+    let fd = stream_fd(state, &args[2]);
+    let max = state.solver.evalcon_to_u64(&args[1]).unwrap_or(1).max(1) - 1;
+    let (mut data, count, eof) = read_until(state, fd, Some('\n' as u64), max as usize);
+    let eof_flag = file_layout(state).eof;
+    set_stream_flag(state, &args[2], eof_flag, &eof);
+
+    data.push(vc(0));
+    state.memory_write(&args[0], &data, &vc(data.len() as u64));
+
+    // returns NULL if nothing was read
+    state.cond(&count.eq(&vc(0)), &vc(0), &args[0])
 }
 
 pub fn fputs(state: &mut State, args: &[Value]) -> Value {
@@ -220,15 +402,14 @@ pub fn perror(state: &mut State, args: &[Value]) -> Value {
 pub fn fputc(state: &mut State, args: &[Value]) -> Value {
     let fd = fileno(state, &args[1..2]);
     let fdn = state.solver.evalcon_to_u64(&fd).unwrap_or(1) as usize;
-    state.filesystem.write(fdn, vec![args[0].to_owned()]);
-    vc(0)
+    let c = args[0].slice(7, 0);
+    state.filesystem.write(fdn, vec![c.to_owned()]);
+    c.uext(&vc(8))
 }
 
 pub fn feof(state: &mut State, args: &[Value]) -> Value {
-    let fd = fileno(state, &args[1..2]);
-    let fdn = state.solver.evalcon_to_u64(&fd).unwrap_or(1) as usize;
-    let f = &state.filesystem.files[fdn];
-    vc((f.position != f.content.len() - 1) as u64)
+    let eof = file_layout(state).eof;
+    stream_flag(state, &args[0], eof)
 }
 
 pub fn strcpy(state: &mut State, args: &[Value]) -> Value {
@@ -439,28 +620,100 @@ pub fn c_syscall(state: &mut State, args: &[Value]) -> Value {
     syscall::syscall("indirect_syscall", state, args)
 }
 
-// This is not going to be a real version of this func
-// because otherwise all execution would have to take place
-// within this sim which would be weird and bad
+/// Run init, the .init_array functions, main and then exit. This is
+/// simulated at its call site and each function is called with
+/// `call_guest`, returning back to `start_main_step`
 pub fn __libc_start_main(state: &mut State, args: &[Value]) -> Value {
+    // envp is where set_argv_env put it, otherwise it follows argv
+    let envp = if let Some(env) = state.context.get("env") {
+        env[0].to_owned()
+    } else {
+        let ptrlen = state.memory.bits / 8;
+        args[2].add(&args[1].add(&vc(1)).mul(&vc(ptrlen)))
+    };
+    let mut queue = start_main_queue(state, args, envp);
+    queue.push(vc(0));
+    start_main_step(state, &queue)
+}
+
+// args are argc, argv, envp, the functions left to call
+// and the return value of the last one
+fn start_main_step(state: &mut State, args: &[Value]) -> Value {
+    let (ret, queue) = args.split_last().unwrap();
+    if let Some((func, rest)) = queue[3..].split_first() {
+        let data = [&queue[..3], rest].concat();
+        state.call_guest(func, &queue[..3], start_main_step, data);
+        vc(0)
+    } else {
+        // main returned
+        exit(state, std::slice::from_ref(ret))
+    }
+}
+
+fn start_main_queue(state: &mut State, args: &[Value], envp: Value) -> Vec<Value> {
     let main = args[0].to_owned();
     let argc = args[1].to_owned();
     let argv = args[2].to_owned();
+    let init = args[3].to_owned();
 
-    // TODO go to init then main
-    // but we need a nice arch neutral way to push ret
-    // so until then
+    let ptrlen = state.memory.bits / 8;
+    let mut queue = vec![argc, argv, envp];
 
-    // go to main
-    state.registers.set_with_alias("PC", main);
-    state.registers.set_with_alias("A0", argc);
-    state.registers.set_with_alias("A1", argv);
+    // old glibc passes __libc_csu_init which runs the init_array
+    // newer versions pass null and ld.so runs it instead
+    if state.solver.evalcon_to_u64(&init).unwrap_or(0) != 0 {
+        queue.push(init);
+    } else {
+        let sections = state.r2api.get_segments().unwrap_or_default();
+        if let Some(sect) = sections.iter().find(|s| s.name.ends_with(".init_array")) {
+            for i in 0..sect.vsize / ptrlen {
+                let addr = vc(sect.vaddr + i * ptrlen);
+                let func = state.memory_read_value(&addr, ptrlen as usize);
+                // skip the 0 and -1 sentinels
+                if let Value::Concrete(0, _) | Value::Concrete(u64::MAX, _) = func {
+                    continue;
+                }
+                queue.push(func);
+            }
+        }
+    }
+    queue.push(main);
+    queue
+}
 
-    // TODO set env
-    state.registers.set_with_alias("A2", vc(0));
+/// Set the return address for a call, in the link register if
+/// the arch has one or pushed on the stack otherwise
+pub fn push_return(state: &mut State, ret_addr: u64) {
+    if state.registers.aliases.contains_key("LR") {
+        state.registers.set_with_alias("LR", vc(ret_addr));
+    } else {
+        let ptrlen = state.memory.bits / 8;
+        let sp = state.registers.get_with_alias("SP").sub(&vc(ptrlen));
+        state.memory_write_value(&sp, &vc(ret_addr), ptrlen as usize);
+        state.registers.set_with_alias("SP", sp);
+    }
+}
 
-    // uh in case we are overwriting A0
-    args[1].to_owned()
+// get the sign of the int returned by a comparison callback, symbolic
+// results are constrained to the first possible sign
+fn compare_sign(state: &mut State, ret: &Value) -> i64 {
+    let result = ret.slice(31, 0);
+    if let Some(r) = result.as_u64() {
+        return (r as u32 as i32).signum() as i64;
+    }
+
+    let signs = [
+        (0, result.eq(&vc(0))),
+        (-1, result.slt(&vc(0))),
+        (1, result.sgt(&vc(0))),
+    ];
+    for (sign, cond) in &signs {
+        if state.check(cond) {
+            state.assert(cond);
+            return *sign;
+        }
+    }
+    0
 }
 
 /*
@@ -501,7 +754,9 @@ type = struct _IO_FILE {
                          }
 */
 
-// beginning of bad FILE function support
+// FILE support, streams are allocated with the platform layout and
+// resolved to simulated fds through their fileno field. reads and writes
+// go straight to the simulated file so buffering is never observable
 
 // _fileno offset from above linux x86_64
 pub const LINUX_FILENO_OFFSET: u64 = 112;
@@ -509,53 +764,176 @@ pub const LINUX_FILENO_OFFSET: u64 = 112;
 // _file offset from macos aarch64
 pub const MACOS_FILENO_OFFSET: u64 = 18;
 
-pub fn fileno(state: &mut State, args: &[Value]) -> Value {
-    let fd_addr = if state.info.bin.os == "darwin" {
-        args[0].add(&vc(MACOS_FILENO_OFFSET))
-    } else {
-        args[0].add(&vc(LINUX_FILENO_OFFSET))
+fn file_layout(state: &State) -> FileLayout {
+    get_file_layout(&state.info.bin.os, state.memory.bits)
+}
+
+/// Allocate a FILE for `fd`, returns the address of the struct
+pub fn new_file(state: &mut State, fd: &Value, mode: FileMode) -> Value {
+    let layout = file_layout(state);
+    let file = state.memory_alloc(&vc(layout.size));
+    let flags = match mode {
+        FileMode::Read => layout.magic | layout.no_writes,
+        _ => layout.magic | layout.no_reads,
     };
-    state.memory_read_value(&(fd_addr), 4)
+
+    state.memory_write_value(&file.add(&vc(layout.flags)), &vc(flags), layout.flags_size);
+    state.memory_write_value(&file.add(&vc(layout.fileno)), fd, layout.fileno_size);
+    file
 }
 
-pub fn fopen(state: &mut State, args: &[Value]) -> Value {
-    // we are reaching levels of jank code previously undreamt
-    let fd = syscall::open(state, args);
-    let file_struct = state.memory.alloc(&vc(216));
+// get the stream for a standard fd, if the binary has one
+fn std_stream(state: &State, fd: usize) -> Value {
+    state
+        .context
+        .get("std_streams")
+        .and_then(|s| s.get(fd))
+        .cloned()
+        .unwrap_or_default()
+}
 
-    let fd_addr = if state.info.bin.os == "darwin" {
-        vc(file_struct).add(&vc(MACOS_FILENO_OFFSET))
+// get the concrete fd of a stream
+fn stream_fd(state: &mut State, stream: &Value) -> usize {
+    let fd = fileno(state, &[stream.to_owned()]);
+    state.solver.evalcon_to_u64(&fd).unwrap_or(0) as usize
+}
+
+// set `flag` in the stream flags if `cond` is true
+fn set_stream_flag(state: &mut State, stream: &Value, flag: u64, cond: &Value) {
+    if stream.as_u64() == Some(0) {
+        return;
+    }
+    let layout = file_layout(state);
+    let addr = stream.add(&vc(layout.flags));
+    let flags = state.memory_read_value(&addr, layout.flags_size);
+    let new_flags = state.cond(cond, &flags.or(&vc(flag)), &flags);
+    state.memory_write_value(&addr, &new_flags, layout.flags_size);
+}
+
+// clear the eof and error flags
+fn clear_stream_flags(state: &mut State, stream: &Value) {
+    if stream.as_u64() == Some(0) {
+        return;
+    }
+    let layout = file_layout(state);
+    let addr = stream.add(&vc(layout.flags));
+    let flags = state.memory_read_value(&addr, layout.flags_size);
+    let new_flags = flags.and(&vc(!(layout.eof | layout.err)));
+    state.memory_write_value(&addr, &new_flags, layout.flags_size);
+}
+
+fn stream_flag(state: &mut State, stream: &Value, flag: u64) -> Value {
+    let layout = file_layout(state);
+    let addr = stream.add(&vc(layout.flags));
+    let flags = state.memory_read_value(&addr, layout.flags_size);
+    let set = !flags.and(&vc(flag)).eq(&vc(0));
+    state.cond(&set, &vc(1), &vc(0))
+}
+
+// parse an fopen mode string into the file mode, create and truncate
+fn parse_mode(mode: &str) -> (FileMode, bool, bool) {
+    let file_mode = if mode.starts_with('a') {
+        FileMode::Append
+    } else if mode.starts_with('w') || mode.contains('+') {
+        FileMode::Write
     } else {
-        vc(file_struct).add(&vc(LINUX_FILENO_OFFSET))
+        FileMode::Read
     };
-    state.memory_write_value(&fd_addr, &fd, 4);
-    vc(file_struct)
+    (file_mode, !mode.starts_with('r'), mode.starts_with('w'))
+}
+
+pub fn fileno(state: &mut State, args: &[Value]) -> Value {
+    let layout = file_layout(state);
+    let fd_addr = args[0].add(&vc(layout.fileno));
+    state.memory_read_value(&fd_addr, layout.fileno_size)
+}
+
+pub fn fopen(state: &mut State, args: &[Value]) -> Value {
+    let path_addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let mode_addr = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    let path = state.memory_read_cstring(path_addr);
+    let mode = state.memory_read_cstring(mode_addr);
+    let (file_mode, create, trunc) = parse_mode(&mode);
+
+    let fd = syscall::open_path(state, &path, file_mode.clone(), create, trunc);
+    if fd == vc(-1i64 as u64) {
+        return vc(0);
+    }
+    new_file(state, &fd, file_mode)
+}
+
+pub fn fdopen(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap_or(0) as usize;
+    let mode_addr = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    let mode = state.memory_read_cstring(mode_addr);
+    if state.filesystem.getpath(fd).is_none() {
+        return vc(0);
+    }
+    new_file(state, &vc(fd as u64), parse_mode(&mode).0)
+}
+
+pub fn freopen(state: &mut State, args: &[Value]) -> Value {
+    let path_addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let mode_addr = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    let path = state.memory_read_cstring(path_addr);
+    let mode = state.memory_read_cstring(mode_addr);
+    let (file_mode, create, trunc) = parse_mode(&mode);
+
+    // reuse the stream with the new fd
+    let old_fd = stream_fd(state, &args[2]);
+    state.filesystem.close(old_fd);
+    let fd = syscall::open_path(state, &path, file_mode, create, trunc);
+    if fd == vc(-1i64 as u64) {
+        return vc(0);
+    }
+    let layout = file_layout(state);
+    let fd_addr = args[2].add(&vc(layout.fileno));
+    state.memory_write_value(&fd_addr, &fd, layout.fileno_size);
+    clear_stream_flags(state, &args[2]);
+    args[2].to_owned()
 }
 
 pub fn fclose(state: &mut State, args: &[Value]) -> Value {
     let fd = fileno(state, &[args[0].to_owned()]);
-    syscall::close(state, &[fd])
+    let ret = syscall::close(state, &[fd]);
+    state.memory_free(&args[0]);
+    ret
 }
 
 pub fn fread(state: &mut State, args: &[Value]) -> Value {
     let fd = fileno(state, &[args[3].to_owned()]);
-    syscall::read(
-        state,
-        &[fd, args[0].to_owned(), args[1].mul(&args[2]).to_owned()],
-    )
+    let length = args[1].mul(&args[2]);
+    let count = syscall::read(state, &[fd, args[0].to_owned(), length.to_owned()]);
+
+    // a short read means the end of the file was hit
+    let eof = file_layout(state).eof;
+    set_stream_flag(state, &args[3], eof, &count.ult(&length));
+    let items = count.div(&args[1]);
+    state.cond(&args[1].eq(&vc(0)), &vc(0), &items)
 }
 
 pub fn fwrite(state: &mut State, args: &[Value]) -> Value {
     let fd = fileno(state, &[args[3].to_owned()]);
-    syscall::write(
-        state,
-        &[fd, args[0].to_owned(), args[1].mul(&args[2]).to_owned()],
-    )
+    let length = args[1].mul(&args[2]);
+    let written = syscall::write(state, &[fd, args[0].to_owned(), length.to_owned()]);
+    let written = state.cond(&written.slt(&vc(0)), &vc(0), &written);
+
+    // a short write sets the error flag and only counts complete items
+    let err = file_layout(state).err;
+    set_stream_flag(state, &args[3], err, &written.ult(&length));
+    let items = written.div(&args[1]);
+    state.cond(&args[1].eq(&vc(0)), &vc(0), &items)
 }
 
 pub fn fseek(state: &mut State, args: &[Value]) -> Value {
     let fd = fileno(state, &[args[0].to_owned()]);
-    syscall::lseek(state, &[fd, args[1].to_owned(), args[2].to_owned()])
+    let pos = syscall::lseek(state, &[fd, args[1].to_owned(), args[2].to_owned()]);
+    if pos == vc(-1i64 as u64) {
+        pos
+    } else {
+        clear_stream_flags(state, &args[0]);
+        vc(0)
+    }
 }
 
 pub fn ftell(state: &mut State, args: &[Value]) -> Value {
@@ -565,8 +943,117 @@ pub fn ftell(state: &mut State, args: &[Value]) -> Value {
 }
 
 pub fn rewind(state: &mut State, args: &[Value]) -> Value {
-    let fd = fileno(state, &[args[0].to_owned()]);
-    syscall::lseek(state, &[fd, vc(0), vc(0)])
+    fseek(state, &[args[0].to_owned(), vc(0), vc(0)]);
+    vc(0)
+}
+
+pub fn ferror(state: &mut State, args: &[Value]) -> Value {
+    let err = file_layout(state).err;
+    stream_flag(state, &args[0], err)
+}
+
+pub fn clearerr(state: &mut State, args: &[Value]) -> Value {
+    clear_stream_flags(state, &args[0]);
+    vc(0)
+}
+
+pub fn ungetc(state: &mut State, args: &[Value]) -> Value {
+    if args[0].slice(31, 0).as_u64() == Some(0xffffffff) {
+        return vc(-1i64 as u64);
+    }
+    let fd = stream_fd(state, &args[1]);
+    let c = args[0].slice(7, 0);
+    state.filesystem.unread(fd, c.to_owned());
+    clear_stream_flags(state, &args[1]);
+    c.uext(&vc(8))
+}
+
+pub fn getdelim(state: &mut State, args: &[Value]) -> Value {
+    let fd = stream_fd(state, &args[3]);
+    let delim = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0x0a);
+    let (mut data, count, eof) = read_until(state, fd, Some(delim & 0xff), MAX_LEN as usize);
+    let eof_flag = file_layout(state).eof;
+    set_stream_flag(state, &args[3], eof_flag, &eof);
+
+    // allocate a new buffer if there is none or it is too small
+    let ptrlen = (state.memory.bits / 8) as usize;
+    let mut buf = state.memory_read_value(&args[0], ptrlen);
+    let size = state.memory_read_value(&args[1], ptrlen);
+    let needed = data.len() as u64 + 1;
+    if buf.as_u64() == Some(0) || state.check(&size.ult(&vc(needed))) {
+        let new_size = needed.max(120);
+        buf = state.memory_alloc(&vc(new_size));
+        state.memory_write_value(&args[0], &buf, ptrlen);
+        state.memory_write_value(&args[1], &vc(new_size), ptrlen);
+    }
+
+    data.push(vc(0));
+    state.memory_write(&buf, &data, &vc(data.len() as u64));
+    state.cond(&count.eq(&vc(0)), &vc(-1i64 as u64), &count)
+}
+
+pub fn getline(state: &mut State, args: &[Value]) -> Value {
+    getdelim(
+        state,
+        &[args[0].to_owned(), args[1].to_owned(), vc(0x0a), args[2].to_owned()],
+    )
+}
+
+pub fn fscanf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::direct(state, &args[2..]);
+    stream_scan(state, &args[0], &args[1], &mut var_args)
+}
+
+pub fn vfscanf(state: &mut State, args: &[Value]) -> Value {
+    let mut var_args = VarArgs::va_list(state, &args[2]);
+    stream_scan(state, &args[0], &args[1], &mut var_args)
+}
+
+fn stream_scan(state: &mut State, stream: &Value, fmt: &Value, args: &mut VarArgs) -> Value {
+    let fd = stream_fd(state, stream);
+    let result = scan_stream(state, fd, fmt, args);
+    let eof = file_layout(state).eof;
+    set_stream_flag(state, stream, eof, &result.eq(&vc(-1i64 as u64)));
+    result
+}
+
+// wchar_t is 4 bytes everywhere but windows
+fn wchar_size(state: &State) -> usize {
+    if state.info.bin.os == "windows" {
+        2
+    } else {
+        4
+    }
+}
+
+pub fn fgetwc(state: &mut State, args: &[Value]) -> Value {
+    let fd = stream_fd(state, &args[0]);
+    let c = syscall::read_byte(state, fd);
+    let eof = file_layout(state).eof;
+    let is_eof = c.eq(&vc(-1i64 as u64));
+    set_stream_flag(state, &args[0], eof, &is_eof);
+    state.cond(&is_eof, &vc(0xffffffff), &c)
+}
+
+pub fn fgetws(state: &mut State, args: &[Value]) -> Value {
+    let fd = stream_fd(state, &args[2]);
+    let max = state.solver.evalcon_to_u64(&args[1]).unwrap_or(1).max(1) - 1;
+    let (mut data, count, eof) = read_until(state, fd, Some(0x0a), max as usize);
+    let eof_flag = file_layout(state).eof;
+    set_stream_flag(state, &args[2], eof_flag, &eof);
+
+    // single byte characters are widened, no multibyte decoding
+    let size = wchar_size(state);
+    data.push(vc(0));
+    for (i, c) in data.iter().enumerate() {
+        let addr = args[0].add(&vc((i * size) as u64));
+        state.memory_write_value(&addr, &c.uext(&vc(8)), size);
+    }
+    state.cond(&count.eq(&vc(0)), &vc(0), &args[0])
+}
+
+pub fn setvbuf(_state: &mut State, _args: &[Value]) -> Value {
+    vc(0)
 }
 
 // is whitespace
@@ -927,6 +1414,89 @@ pub fn access(state: &mut State, args: &[Value]) -> Value {
     syscall::access(state, args)
 }
 
+pub fn creat(state: &mut State, args: &[Value]) -> Value {
+    syscall::creat(state, args)
+}
+
+pub fn mkdir(state: &mut State, args: &[Value]) -> Value {
+    syscall::mkdir(state, args)
+}
+
+pub fn rmdir(state: &mut State, args: &[Value]) -> Value {
+    syscall::rmdir(state, args)
+}
+
+pub fn unlink(state: &mut State, args: &[Value]) -> Value {
+    syscall::unlink(state, args)
+}
+
+pub fn remove(state: &mut State, args: &[Value]) -> Value {
+    let ret = syscall::unlink(state, args);
+    if ret == vc(0) {
+        ret
+    } else {
+        syscall::rmdir(state, args)
+    }
+}
+
+pub fn rename(state: &mut State, args: &[Value]) -> Value {
+    syscall::rename(state, args)
+}
+
+pub fn chdir(state: &mut State, args: &[Value]) -> Value {
+    syscall::chdir(state, args)
+}
+
+pub fn chmod(state: &mut State, args: &[Value]) -> Value {
+    syscall::chmod(state, args)
+}
+
+pub fn getcwd(state: &mut State, args: &[Value]) -> Value {
+    syscall::getcwd(state, args)
+}
+
+// DIR is just the fd followed by space for one dirent
+const DIR_SIZE: u64 = 8 + 1048;
+
+pub fn opendir(state: &mut State, args: &[Value]) -> Value {
+    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let path = state.memory_read_cstring(addr);
+    if state.filesystem.list(&path).is_none() {
+        return vc(0);
+    }
+    let fd = syscall::open_path(state, &path, FileMode::Read, false, false);
+    fdopendir(state, &[fd])
+}
+
+pub fn fdopendir(state: &mut State, args: &[Value]) -> Value {
+    let dir = state.memory_alloc(&vc(DIR_SIZE));
+    state.memory_write_value(&dir, &args[0], 4);
+    dir
+}
+
+pub fn readdir(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.memory_read_value(&args[0], 4);
+    let fd = state.solver.evalcon_to_u64(&fd).unwrap() as usize;
+    let position = state.filesystem.files.get(fd).map(|f| f.position);
+
+    if let Some((name, node_type, ino)) = state.filesystem.readdir(fd) {
+        let entry = args[0].add(&vc(8));
+        let addr = state.solver.evalcon_to_u64(&entry).unwrap();
+        let off = position.unwrap_or(0) as u64 + 1;
+        syscall::write_dirent(state, addr, &(name, node_type, ino, off));
+        entry
+    } else {
+        vc(0)
+    }
+}
+
+pub fn closedir(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.memory_read_value(&args[0], 4);
+    syscall::close(state, &[fd]);
+    state.memory_free(&args[0]);
+    vc(0)
+}
+
 pub fn stat(state: &mut State, args: &[Value]) -> Value {
     syscall::stat(state, args)
 }
@@ -943,6 +1513,672 @@ pub fn ptrace(state: &mut State, args: &[Value]) -> Value {
     syscall::ptrace(state, args)
 }
 
+pub fn atexit(state: &mut State, args: &[Value]) -> Value {
+    __cxa_atexit(state, &[args[0].to_owned(), vc(0)])
+}
+
+// handlers are kept as function and argument pairs
+pub fn __cxa_atexit(state: &mut State, args: &[Value]) -> Value {
+    let handlers = state.context.entry("atexit".to_string()).or_default();
+    handlers.push(args[0].to_owned());
+    handlers.push(args[1].to_owned());
+    vc(0)
+}
+
 pub fn exit(state: &mut State, args: &[Value]) -> Value {
-    syscall::exit(state, args)
+    exit_handlers(state, args)
+}
+
+// call the next atexit handler in reverse order, each one
+// returns back here with the exit status in `args`
+fn exit_handlers(state: &mut State, args: &[Value]) -> Value {
+    let handler = state.context.get_mut("atexit").and_then(|h| {
+        let arg = h.pop()?;
+        Some((h.pop()?, arg))
+    });
+
+    if let Some((func, arg)) = handler {
+        state.call_guest(&func, &[arg], exit_handlers, vec![args[0].to_owned()]);
+        vc(0)
+    } else {
+        syscall::exit(state, &args[0..1])
+    }
+}
+
+// abort skips the handlers and dies like it got SIGABRT
+pub fn abort(state: &mut State, _args: &[Value]) -> Value {
+    syscall::exit(state, &[vc(128 + 6)])
+}
+
+// signals that are ignored by default instead of terminating
+const IGNORED_SIGNALS: [u64; 4] = [17, 18, 23, 28];
+
+pub fn signal(state: &mut State, args: &[Value]) -> Value {
+    let sig = state.solver.evalcon_to_u64(&args[0]).unwrap_or(0);
+    let key = format!("signal_{}", sig);
+    let old = state.context.get(&key).map(|h| h[0].to_owned());
+    state.context.insert(key, vec![args[1].to_owned()]);
+    old.unwrap_or_else(|| vc(0))
+}
+
+pub fn raise(state: &mut State, args: &[Value]) -> Value {
+    let sig = state.solver.evalcon_to_u64(&args[0]).unwrap_or(0);
+    let handler = state
+        .context
+        .get(&format!("signal_{}", sig))
+        .map(|h| h[0].to_owned())
+        .unwrap_or_else(|| vc(0));
+
+    // SIG_DFL is 0 and SIG_IGN is 1
+    match handler.as_u64() {
+        Some(0) if !IGNORED_SIGNALS.contains(&sig) => syscall::exit(state, &[vc(128 + sig)]),
+        Some(0) | Some(1) => vc(0),
+        _ => {
+            state.call_guest(&handler, &[vc(sig)], raise_return, vec![]);
+            vc(0)
+        }
+    }
+}
+
+fn raise_return(_state: &mut State, _args: &[Value]) -> Value {
+    vc(0)
+}
+
+// the registers a jmp_buf keeps besides the pc and sp, the callee saved ones
+fn jmp_buf_regs(state: &State) -> Vec<&'static str> {
+    let bin = &state.info.bin;
+    let regs: &[&'static str] = match (bin.arch.as_str(), bin.bits) {
+        ("x86", 64) if bin.os == "windows" => {
+            &["rbx", "rbp", "rdi", "rsi", "r12", "r13", "r14", "r15"]
+        }
+        ("x86", 64) => &["rbx", "rbp", "r12", "r13", "r14", "r15"],
+        ("x86", _) => &["ebx", "esi", "edi", "ebp"],
+        ("arm", 64) => &[
+            "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27", "x28", "x29",
+        ],
+        ("arm", _) => &["r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11"],
+        ("mips", _) => &["s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "fp", "gp"],
+        ("riscv", _) => &[
+            "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11",
+        ],
+        _ => &[],
+    };
+    regs.iter()
+        .copied()
+        .filter(|reg| state.registers.get_register(reg).is_some())
+        .collect()
+}
+
+/// Save the return address, the stack pointer as it will be when setjmp
+/// returns and the callee saved registers into the jmp_buf at `env`.
+/// The jmp_buf is laid out as pc, sp, the registers and the backtrace depth
+pub fn setjmp(state: &mut State, args: &[Value]) -> Value {
+    let ptrlen = state.memory.bits / 8;
+    let sp = state.registers.get_with_alias("SP");
+
+    let (ret_addr, ret_sp) = if state.registers.aliases.contains_key("LR") {
+        (state.registers.get_with_alias("LR"), sp)
+    } else {
+        let ret_addr = state.memory_read_value(&sp, ptrlen as usize);
+        (ret_addr, sp.add(&vc(ptrlen)))
+    };
+
+    let mut saved = vec![ret_addr, ret_sp];
+    for reg in jmp_buf_regs(state) {
+        saved.push(state.registers.get(reg));
+    }
+    saved.push(vc(state.backtrace.len().saturating_sub(1) as u64));
+
+    for (i, value) in saved.iter().enumerate() {
+        let addr = args[0].add(&vc(i as u64 * ptrlen));
+        state.memory_write_value(&addr, value, ptrlen as usize);
+    }
+    vc(0)
+}
+
+/// Restore the registers saved by setjmp and return `val` from it
+pub fn longjmp(state: &mut State, args: &[Value]) -> Value {
+    let ptrlen = state.memory.bits / 8;
+    let regs = jmp_buf_regs(state);
+    let saved: Vec<Value> = (0..regs.len() as u64 + 3)
+        .map(|i| state.memory_read_value(&args[0].add(&vc(i * ptrlen)), ptrlen as usize))
+        .collect();
+
+    if saved[0].as_u64() == Some(0) {
+        // jumping to a buffer that was never set is undefined
+        state.set_inactive();
+        return vc(0);
+    }
+
+    state.registers.set_pc(saved[0].to_owned());
+    state.registers.set_with_alias("SP", saved[1].to_owned());
+    for (reg, value) in regs.iter().zip(&saved[2..]) {
+        state.registers.set(reg, value.to_owned());
+    }
+    let depth = saved[regs.len() + 2].as_u64();
+    state.backtrace.truncate(depth.unwrap_or(u64::MAX) as usize);
+
+    let val = args[1].slice(31, 0);
+    state.cond(&val.eq(&vc(0)), &vc(1), &val)
+}
+
+/// Sort `nmemb` elements of `size` at `base` with the guest comparison function.
+/// This is a binary insertion sort, each comparison returns back to `qsort_step`
+pub fn qsort(state: &mut State, args: &[Value]) -> Value {
+    let nmemb = state.solver.evalcon_to_u64(&args[1]).unwrap_or(0);
+    let size = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0);
+    let frame = [args[0].to_owned(), vc(nmemb), vc(size), args[3].to_owned()];
+    qsort_sort(state, &frame, 1, 0, 1)
+}
+
+// args are base, nmemb, size, compar, i, lo, hi and the comparison result
+fn qsort_step(state: &mut State, args: &[Value]) -> Value {
+    let i = args[4].as_u64().unwrap();
+    let mut lo = args[5].as_u64().unwrap();
+    let mut hi = args[6].as_u64().unwrap();
+
+    let mid = (lo + hi) / 2;
+    if compare_sign(state, &args[7]) < 0 {
+        hi = mid;
+    } else {
+        lo = mid + 1;
+    }
+    qsort_sort(state, &args[..4], i, lo, hi)
+}
+
+// continue the sort searching for where element `i` goes in `lo..hi`
+fn qsort_sort(state: &mut State, frame: &[Value], mut i: u64, mut lo: u64, mut hi: u64) -> Value {
+    let base = frame[0].to_owned();
+    let nmemb = frame[1].as_u64().unwrap();
+    let size = frame[2].as_u64().unwrap();
+
+    while i < nmemb {
+        if lo < hi {
+            let mid = (lo + hi) / 2;
+            let elem = base.add(&vc(i * size));
+            let other = base.add(&vc(mid * size));
+            let data = [frame, &[vc(i), vc(lo), vc(hi)]].concat();
+            state.call_guest(&frame[3], &[elem, other], qsort_step, data);
+            return vc(0);
+        }
+
+        // insert element i at lo and shift the rest up
+        if lo < i {
+            let elem = state.memory_read(&base.add(&vc(i * size)), &vc(size));
+            let start = base.add(&vc(lo * size));
+            state.memory_move(&start.add(&vc(size)), &start, &vc((i - lo) * size));
+            state.memory_write(&start, &elem, &vc(size));
+        }
+
+        i += 1;
+        lo = 0;
+        hi = i;
+    }
+    vc(0)
+}
+
+/// Binary search for `key` in the sorted array with the guest comparison function
+pub fn bsearch(state: &mut State, args: &[Value]) -> Value {
+    let nmemb = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0);
+    let size = state.solver.evalcon_to_u64(&args[3]).unwrap_or(0);
+    let frame = [args[0].to_owned(), args[1].to_owned(), vc(size), args[4].to_owned()];
+    bsearch_search(state, &frame, 0, nmemb)
+}
+
+// args are key, base, size, compar, lo, hi and the comparison result
+fn bsearch_step(state: &mut State, args: &[Value]) -> Value {
+    let mut lo = args[4].as_u64().unwrap();
+    let mut hi = args[5].as_u64().unwrap();
+    let size = args[2].as_u64().unwrap();
+
+    let mid = (lo + hi) / 2;
+    match compare_sign(state, &args[6]) {
+        0 => return args[1].add(&vc(mid * size)),
+        sign if sign < 0 => hi = mid,
+        _ => lo = mid + 1,
+    }
+    bsearch_search(state, &args[..4], lo, hi)
+}
+
+fn bsearch_search(state: &mut State, frame: &[Value], lo: u64, hi: u64) -> Value {
+    if lo < hi {
+        let mid = (lo + hi) / 2;
+        let size = frame[2].as_u64().unwrap();
+        let elem = frame[1].add(&vc(mid * size));
+        let data = [frame, &[vc(lo), vc(hi)]].concat();
+        state.call_guest(&frame[3], &[frame[0].to_owned(), elem], bsearch_step, data);
+    }
+    vc(0)
+}
+
+// threads run to completion when they are created, the return
+// value is kept in the context until they are joined
+pub fn pthread_create(state: &mut State, args: &[Value]) -> Value {
+    let threads = state.context.entry("pthread".to_string()).or_default();
+    threads.push(vc(0));
+    let thread = vc(threads.len() as u64);
+
+    let ptrlen = state.memory.bits / 8;
+    state.memory_write_value(&args[0], &thread, ptrlen as usize);
+    state.call_guest(&args[2], &args[3..4], pthread_return, vec![thread]);
+    vc(0)
+}
+
+fn pthread_return(state: &mut State, args: &[Value]) -> Value {
+    let index = args[0].as_u64().unwrap() as usize - 1;
+    state.context.get_mut("pthread").unwrap()[index] = args[1].to_owned();
+    vc(0)
+}
+
+pub fn pthread_join(state: &mut State, args: &[Value]) -> Value {
+    let thread = state.solver.evalcon_to_u64(&args[0]).unwrap_or(0) as usize;
+    let ret = state
+        .context
+        .get("pthread")
+        .and_then(|t| t.get(thread.wrapping_sub(1)))
+        .cloned();
+
+    if let Some(ret) = ret {
+        if args[1].as_u64() != Some(0) {
+            state.memory_write_ptr(&args[1], &ret);
+        }
+        vc(0)
+    } else {
+        vc(3) // ESRCH
+    }
+}
+
+// get the length of the initial part of `s` made of bytes in `chars`,
+// or bytes not in `chars` if `reject` is set, like strspn and strcspn
+fn span_helper(state: &mut State, s: &Value, chars: &Value, reject: bool) -> Value {
+    let set_length = state.memory_strlen(chars, &vc(MAX_LEN));
+    let set_max = state.solver.max_value(&set_length);
+    let set = state.memory_read(chars, &vc(set_max));
+
+    // the byte at the max length is always the end of the string
+    let length = state.memory_strlen(s, &vc(MAX_LEN));
+    let max = state.solver.max_value(&length);
+    let data = state.memory_read(s, &vc(max));
+
+    let mut span = vc(max);
+    for (i, c) in data.iter().enumerate().rev() {
+        let mut found = vc(0);
+        for (j, x) in set.iter().enumerate() {
+            found = found | (c.eq(x) & vc(j as u64).ult(&set_length));
+        }
+        let stop = if reject { found } else { !found };
+        span = state.cond(&(c.eq(&vc(0)) | stop), &vc(i as u64), &span);
+    }
+    span
+}
+
+pub fn strspn(state: &mut State, args: &[Value]) -> Value {
+    span_helper(state, &args[0], &args[1], false)
+}
+
+pub fn strcspn(state: &mut State, args: &[Value]) -> Value {
+    span_helper(state, &args[0], &args[1], true)
+}
+
+pub fn strpbrk(state: &mut State, args: &[Value]) -> Value {
+    let span = span_helper(state, &args[0], &args[1], true);
+    let addr = args[0].add(&span);
+    let c = state.memory_read_value(&addr, 1);
+    state.cond(&c.eq(&vc(0)), &vc(0), &addr)
+}
+
+// find the next token in `s` or `saved` if `s` is null,
+// returns the token and where the next search starts
+fn strtok_helper(state: &mut State, s: &Value, delim: &Value, saved: &Value) -> (Value, Value) {
+    let start = state.cond(&s.eq(&vc(0)), saved, s);
+    let skip = span_helper(state, &start, delim, false);
+    let start = start.add(&skip);
+    let first = state.memory_read_value(&start, 1);
+
+    let length = span_helper(state, &start, delim, true);
+    let end = start.add(&length);
+    let last = state.memory_read_value(&end, 1);
+    state.memory_write_value(&end, &vc(0), 1);
+
+    let next = state.cond(&last.eq(&vc(0)), &end, &end.add(&vc(1)));
+    let token = state.cond(&first.eq(&vc(0)), &vc(0), &start);
+    (token, next)
+}
+
+pub fn strtok(state: &mut State, args: &[Value]) -> Value {
+    let saved = state
+        .context
+        .get("strtok")
+        .map(|s| s[0].to_owned())
+        .unwrap_or_else(|| vc(0));
+
+    let (token, next) = strtok_helper(state, &args[0], &args[1], &saved);
+    state.context.insert("strtok".to_string(), vec![next]);
+    token
+}
+
+pub fn strtok_r(state: &mut State, args: &[Value]) -> Value {
+    let saved = state.memory_read_ptr(&args[2]);
+    let (token, next) = strtok_helper(state, &args[0], &args[1], &saved);
+    state.memory_write_ptr(&args[2], &next);
+    token
+}
+
+pub fn strsep(state: &mut State, args: &[Value]) -> Value {
+    let token = state.memory_read_ptr(&args[0]);
+    if token.as_u64() == Some(0) {
+        return token;
+    }
+
+    let length = span_helper(state, &token, &args[1], true);
+    let end = token.add(&length);
+    let last = state.memory_read_value(&end, 1);
+    state.memory_write_value(&end, &vc(0), 1);
+
+    let next = state.cond(&last.eq(&vc(0)), &vc(0), &end.add(&vc(1)));
+    state.memory_write_ptr(&args[0], &next);
+    token
+}
+
+// compare `length` bytes of two strings ignoring case, like memory.compare
+fn casecmp_helper(state: &mut State, s1: &Value, s2: &Value, length: &Value) -> Value {
+    let max = state.solver.max_value(length);
+    let data1 = state.memory_read(s1, &vc(max));
+    let data2 = state.memory_read(s2, &vc(max));
+
+    let mut result = vc(0);
+    for (ind, (c1, c2)) in data1.iter().zip(&data2).enumerate() {
+        let d1 = tolower(state, &[c1.to_owned()]).uext(&vc(8));
+        let d2 = tolower(state, &[c2.to_owned()]).uext(&vc(8));
+
+        let len_cond = vc(ind as u64).ult(length);
+        result = state.cond(&(result.eq(&vc(0)) & len_cond), &d1.sub(&d2), &result);
+
+        if result.as_u64().is_some_and(|res| res != 0) {
+            break;
+        }
+    }
+    result
+}
+
+pub fn strcasecmp(state: &mut State, args: &[Value]) -> Value {
+    let len1 = state.memory_strlen(&args[0], &vc(MAX_LEN));
+    let len2 = state.memory_strlen(&args[1], &vc(MAX_LEN));
+    let length = state.cond(&(len1.ult(&len2)), &len1, &len2) + vc(1);
+
+    casecmp_helper(state, &args[0], &args[1], &length)
+}
+
+pub fn strncasecmp(state: &mut State, args: &[Value]) -> Value {
+    let len1 = state.memory_strlen(&args[0], &args[2]);
+    let len2 = state.memory_strlen(&args[1], &args[2]);
+    let mut length = state.cond(&(len1.ult(&len2)), &len1, &len2);
+    length = state.cond(&(length.ult(&args[2])), &length.add(&vc(1)), &args[2]);
+
+    casecmp_helper(state, &args[0], &args[1], &length)
+}
+
+// read up to `max` wide chars at `addr`, stopping at a null if `nulls` is set
+fn read_wide(state: &mut State, addr: &Value, max: u64, nulls: bool) -> Vec<Value> {
+    let size = wchar_size(state) as u64;
+    let mut chars = vec![];
+    for i in 0..max.min(MAX_LEN) {
+        let c = state.memory_read_value(&addr.add(&vc(i * size)), size as usize);
+        let end = nulls && c.as_u64() == Some(0);
+        chars.push(c);
+        if end {
+            break;
+        }
+    }
+    chars
+}
+
+fn wcslen_helper(state: &mut State, addr: &Value, max: u64) -> Value {
+    let chars = read_wide(state, addr, max, true);
+    let mut length = vc(chars.len() as u64);
+    for (i, c) in chars.iter().enumerate().rev() {
+        length = state.cond(&c.eq(&vc(0)), &vc(i as u64), &length);
+    }
+    length
+}
+
+// compare wide chars like wmemcmp, or wcscmp if `nulls` is set
+fn wide_compare(state: &mut State, s1: &[Value], s2: &[Value], nulls: bool) -> Value {
+    let mut result = vc(0);
+    for i in (0..s1.len().max(s2.len())).rev() {
+        let c1 = s1.get(i).cloned().unwrap_or_else(|| vc(0));
+        let c2 = s2.get(i).cloned().unwrap_or_else(|| vc(0));
+        let diff = state.cond(&c1.slt(&c2), &vc(-1i64 as u64), &vc(1));
+        let same = if nulls {
+            state.cond(&c1.eq(&vc(0)), &vc(0), &result)
+        } else {
+            result
+        };
+        result = state.cond(&c1.eq(&c2), &same, &diff);
+    }
+    result
+}
+
+pub fn wcslen(state: &mut State, args: &[Value]) -> Value {
+    wcslen_helper(state, &args[0], MAX_LEN)
+}
+
+pub fn wcsnlen(state: &mut State, args: &[Value]) -> Value {
+    let max = state.solver.max_value(&args[1]);
+    wcslen_helper(state, &args[0], max)
+}
+
+pub fn wcscpy(state: &mut State, args: &[Value]) -> Value {
+    let size = wchar_size(state) as u64;
+    let length = wcslen_helper(state, &args[1], MAX_LEN).add(&vc(1));
+    state.memory_move(&args[0], &args[1], &length.mul(&vc(size)));
+    args[0].to_owned()
+}
+
+pub fn wcsncpy(state: &mut State, args: &[Value]) -> Value {
+    let size = wchar_size(state) as u64;
+    let length = wcslen_helper(state, &args[1], MAX_LEN).add(&vc(1));
+    let length = state.cond(&length.ult(&args[2]), &length, &args[2]);
+    state.memory_move(&args[0], &args[1], &length.mul(&vc(size)));
+    args[0].to_owned()
+}
+
+pub fn wcscat(state: &mut State, args: &[Value]) -> Value {
+    let size = wchar_size(state) as u64;
+    let length1 = wcslen_helper(state, &args[0], MAX_LEN);
+    let length2 = wcslen_helper(state, &args[1], MAX_LEN).add(&vc(1));
+    let dst = args[0].add(&length1.mul(&vc(size)));
+    state.memory_move(&dst, &args[1], &length2.mul(&vc(size)));
+    args[0].to_owned()
+}
+
+pub fn wcsdup(state: &mut State, args: &[Value]) -> Value {
+    let size = wchar_size(state) as u64;
+    let length = wcslen_helper(state, &args[0], MAX_LEN).add(&vc(1)).mul(&vc(size));
+    let new_addr = state.memory_alloc(&length);
+    state.memory_move(&new_addr, &args[0], &length);
+    new_addr
+}
+
+pub fn wcscmp(state: &mut State, args: &[Value]) -> Value {
+    let s1 = read_wide(state, &args[0], MAX_LEN, true);
+    let s2 = read_wide(state, &args[1], MAX_LEN, true);
+    wide_compare(state, &s1, &s2, true)
+}
+
+pub fn wcsncmp(state: &mut State, args: &[Value]) -> Value {
+    let max = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0);
+    let s1 = read_wide(state, &args[0], max, true);
+    let s2 = read_wide(state, &args[1], max, true);
+    wide_compare(state, &s1, &s2, true)
+}
+
+pub fn wmemcmp(state: &mut State, args: &[Value]) -> Value {
+    let max = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0);
+    let s1 = read_wide(state, &args[0], max, false);
+    let s2 = read_wide(state, &args[1], max, false);
+    wide_compare(state, &s1, &s2, false)
+}
+
+fn wcschr_helper(state: &mut State, args: &[Value], reverse: bool) -> Value {
+    let size = wchar_size(state) as u64;
+    let chars = read_wide(state, &args[0], MAX_LEN, true);
+    let c = args[1].slice(8 * size - 1, 0);
+
+    let mut result = vc(0);
+    if reverse {
+        let mut ended = vc(0);
+        for (i, x) in chars.iter().enumerate() {
+            let addr = args[0].add(&vc(i as u64 * size));
+            let found = state.cond(&x.eq(&c), &addr, &result);
+            result = state.cond(&ended, &result, &found);
+            ended = ended | x.eq(&vc(0));
+        }
+    } else {
+        for (i, x) in chars.iter().enumerate().rev() {
+            let addr = args[0].add(&vc(i as u64 * size));
+            let end = state.cond(&x.eq(&vc(0)), &vc(0), &result);
+            result = state.cond(&x.eq(&c), &addr, &end);
+        }
+    }
+    result
+}
+
+pub fn wcschr(state: &mut State, args: &[Value]) -> Value {
+    wcschr_helper(state, args, false)
+}
+
+pub fn wcsrchr(state: &mut State, args: &[Value]) -> Value {
+    wcschr_helper(state, args, true)
+}
+
+pub fn wmemchr(state: &mut State, args: &[Value]) -> Value {
+    let size = wchar_size(state) as u64;
+    let max = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0);
+    let chars = read_wide(state, &args[0], max, false);
+    let c = args[1].slice(8 * size - 1, 0);
+
+    let mut result = vc(0);
+    for (i, x) in chars.iter().enumerate().rev() {
+        let addr = args[0].add(&vc(i as u64 * size));
+        result = state.cond(&x.eq(&c), &addr, &result);
+    }
+    result
+}
+
+pub fn wmemcpy(state: &mut State, args: &[Value]) -> Value {
+    let size = wchar_size(state) as u64;
+    state.memory_move(&args[0], &args[1], &args[2].mul(&vc(size)));
+    args[0].to_owned()
+}
+
+pub fn wmemset(state: &mut State, args: &[Value]) -> Value {
+    let size = wchar_size(state);
+    let max = state.solver.max_value(&args[2]) & 0xffffffff;
+    let c = state.unpack(&args[1], size);
+    let mut data = vec![];
+    for _ in 0..max {
+        data.extend(c.iter().cloned());
+    }
+
+    state.memory_write(&args[0], &data, &args[2].mul(&vc(size as u64)));
+    args[0].to_owned()
+}
+
+pub fn time(state: &mut State, args: &[Value]) -> Value {
+    syscall::time(state, args)
+}
+
+pub fn gettimeofday(state: &mut State, args: &[Value]) -> Value {
+    syscall::gettimeofday(state, args)
+}
+
+pub fn clock_gettime(state: &mut State, args: &[Value]) -> Value {
+    syscall::clock_gettime(state, args)
+}
+
+pub fn clock(state: &mut State, _args: &[Value]) -> Value {
+    let bits = state.memory.bits as u32;
+    syscall::nondet(state, "clock", bits)
+}
+
+// days since the epoch of a date, from howard hinnant's date algorithms
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Get the `struct tm` fields for the UTC time `t`, from tm_sec to tm_yday
+pub fn civil_time(t: i64) -> [i64; 8] {
+    let days = t.div_euclid(86400);
+    let secs = t.rem_euclid(86400);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 };
+
+    [
+        secs % 60,
+        secs / 60 % 60,
+        secs / 3600,
+        day,
+        month - 1,
+        year - 1900,
+        (days + 4).rem_euclid(7), // 1970-01-01 was a thursday
+        days - days_from_civil(year, 1, 1),
+    ]
+}
+
+// tm is 9 ints then tm_gmtoff and tm_zone, there are no timezones so
+// localtime is the same as gmtime
+fn write_tm(state: &mut State, timep: &Value, tm: &Value) -> Value {
+    let ptrlen = state.memory.bits / 8;
+    let time = state.memory_read_value(timep, ptrlen as usize);
+    let secs = state.solver.evalcon_to_u64(&time).unwrap_or(0);
+    state.assert(&time.eq(&vc(secs)));
+
+    let secs = if ptrlen == 4 { secs as u32 as i32 as i64 } else { secs as i64 };
+    for (i, field) in civil_time(secs).iter().enumerate() {
+        state.memory_write_value(&tm.add(&vc(4 * i as u64)), &vc(*field as u64), 4);
+    }
+    state.memory_write_value(&tm.add(&vc(32)), &vc(0), 4);
+
+    let gmtoff = tm.add(&vc(36)).add(&vc(ptrlen - 1)).and(&vc(!(ptrlen - 1)));
+    let static_tm = static_tm(state);
+    let zone = static_tm.add(&vc(64));
+    state.memory_write_value(&gmtoff, &vc(0), ptrlen as usize);
+    state.memory_write_ptr(&gmtoff.add(&vc(ptrlen)), &zone);
+    tm.to_owned()
+}
+
+// get the static tm buffer shared by every gmtime call, the
+// zone name "GMT" is stored after it
+fn static_tm(state: &mut State) -> Value {
+    if let Some(buf) = state.context.get("tm") {
+        return buf[0].to_owned();
+    }
+
+    let buf = state.memory_alloc(&vc(72));
+    state.memory_write_string(buf.as_u64().unwrap() + 64, "GMT");
+    state.context.insert("tm".to_string(), vec![buf.clone()]);
+    buf
+}
+
+pub fn gmtime_r(state: &mut State, args: &[Value]) -> Value {
+    write_tm(state, &args[0], &args[1])
+}
+
+pub fn gmtime(state: &mut State, args: &[Value]) -> Value {
+    let tm = static_tm(state);
+    write_tm(state, &args[0], &tm)
 }
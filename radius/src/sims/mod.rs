@@ -1,6 +1,9 @@
 use crate::state::State;
 use crate::value::Value;
 
+use std::rc::Rc;
+
+pub mod dalvik;
 pub mod format;
 pub mod fs;
 pub mod libc;
@@ -8,21 +11,73 @@ pub mod syscall;
 
 pub type SimMethod = fn(&mut State, &[Value]) -> Value;
 
+/// The implementation of a sim, a `SimMethod` or any closure
+pub type SimFunction = Rc<dyn Fn(&mut State, &[Value]) -> Value>;
+
 #[derive(Clone)]
 pub struct Sim {
     pub symbol: String,
-    pub function: SimMethod,
+    pub function: SimFunction,
     pub arguments: usize,
 }
 
-pub fn make_sim(symbol: &str, function: SimMethod, arguments: usize) -> Sim {
+/// Get the function name from a signature match flag
+/// like `sign.bytes.sym.strlen_0` or `flirt.strlen`
+pub fn signature_symbol(flag: &str) -> &str {
+    let mut name = flag;
+    for prefix in &[
+        "sign.", "flirt.", "bytes.", "graph.", "offset.", "refs.", "types.", "bbhash.", "sym.",
+        "imp.",
+    ] {
+        name = name.strip_prefix(prefix).unwrap_or(name);
+    }
+
+    // zignature matches are suffixed with the match count
+    if flag.starts_with("sign.") {
+        if let Some((base, count)) = name.rsplit_once('_') {
+            if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) {
+                name = base;
+            }
+        }
+    }
+    name
+}
+
+/// Make a `Sim` from a `SimMethod` or a closure
+pub fn make_sim<F>(symbol: &str, function: F, arguments: usize) -> Sim
+where
+    F: Fn(&mut State, &[Value]) -> Value + 'static,
+{
     Sim {
         symbol: String::from(symbol),
-        function,
+        function: Rc::new(function),
         arguments,
     }
 }
 
+/// A call from a sim into the guest function `func`, made with
+/// `State::call_guest`. when `func` returns the processor calls
+/// `resume` with `data` followed by the return value of `func`
+#[derive(Clone)]
+pub struct SimCall {
+    pub func: Value,
+    pub args: Vec<Value>,
+    pub resume: SimMethod,
+    pub data: Vec<Value>,
+}
+
+/// A sim at `pc` waiting on a guest function that returns to it
+/// with the stack pointer `sp`
+#[derive(Clone)]
+pub struct SimFrame {
+    pub pc: u64,
+    pub sp: u64,
+    pub entry_sp: Value,
+    pub link: Option<Value>,
+    pub resume: SimMethod,
+    pub data: Vec<Value>,
+}
+
 pub fn error(_state: &mut State, _args: &[Value]) -> Value {
     Value::Concrete(-1i64 as u64, 0)
 }
@@ -47,7 +102,7 @@ pub fn ret(state: &mut State, _args: &[Value]) -> Value {
 /*
 add fortify versions
 memcpy, mempcpy, memmove, memset, strcpy, stpcpy, stncpy, 
-strcat, strncat, sprintf, vsprintf, snprintf, vsnprintf, gets.
+strcat, strncat, gets.
 */
 
 // get a vec of all available Sims
@@ -61,34 +116,73 @@ pub fn get_sims() -> Vec<Sim> {
         make_sim("getchar", libc::getchar, 0),
         make_sim("fgets", libc::fgets, 1),
         make_sim("__fgets_chk", libc::fgets, 1),
-        make_sim("fgetc", libc::fgets, 1),
+        make_sim("fgetc", libc::fgetc, 1),
+        make_sim("getc", libc::fgetc, 1),
+        make_sim("_IO_getc", libc::fgetc, 1),
         make_sim("fputs", libc::fputs, 2),
         make_sim("fputc", libc::fputc, 2),
+        make_sim("putc", libc::fputc, 2),
+        make_sim("_IO_putc", libc::fputc, 2),
         make_sim("feof", libc::feof, 1),
         make_sim("fprintf", libc::fprintf, 2),
         make_sim("printf", libc::printf, 1),
+        make_sim("__printf_chk", libc::printf_chk, 2),
+        make_sim("__fprintf_chk", libc::fprintf_chk, 3),
+        make_sim("dprintf", libc::dprintf, 2),
         make_sim("sprintf", libc::sprintf, 2),
-        make_sim("__sprintf_chk", libc::sprintf, 2),
+        make_sim("__sprintf_chk", libc::sprintf_chk, 4),
         make_sim("snprintf", libc::snprintf, 3),
-        make_sim("__snprintf_chk", libc::snprintf, 3),
-        make_sim("vprintf", libc::printf, 1),   // fix
-        make_sim("vsprintf", libc::sprintf, 2), // fix
-        make_sim("__vsprintf_chk", libc::sprintf, 2),
+        make_sim("__snprintf_chk", libc::snprintf_chk, 5),
+        make_sim("asprintf", libc::asprintf, 2),
+        make_sim("vprintf", libc::vprintf, 2),
+        make_sim("__vprintf_chk", libc::vprintf_chk, 3),
+        make_sim("vfprintf", libc::vfprintf, 3),
+        make_sim("__vfprintf_chk", libc::vfprintf_chk, 4),
+        make_sim("vdprintf", libc::vdprintf, 3),
+        make_sim("vsprintf", libc::vsprintf, 3),
+        make_sim("__vsprintf_chk", libc::vsprintf_chk, 5),
+        make_sim("vsnprintf", libc::vsnprintf, 4),
+        make_sim("__vsnprintf_chk", libc::vsnprintf_chk, 6),
+        make_sim("vasprintf", libc::vasprintf, 3),
         make_sim("perror", libc::perror, 1),
         make_sim("fflush", libc::fflush, 0),
         make_sim("scanf", libc::scanf, 1),
         make_sim("sscanf", libc::sscanf, 1),
         make_sim("__isoc99_scanf", libc::scanf, 1),
         make_sim("__isoc99_sscanf", libc::sscanf, 1),
+        make_sim("fscanf", libc::fscanf, 2),
+        make_sim("__isoc99_fscanf", libc::fscanf, 2),
+        make_sim("vscanf", libc::vscanf, 2),
+        make_sim("vsscanf", libc::vsscanf, 3),
+        make_sim("vfscanf", libc::vfscanf, 3),
+        make_sim("__isoc99_vscanf", libc::vscanf, 2),
+        make_sim("__isoc99_vsscanf", libc::vsscanf, 3),
+        make_sim("__isoc99_vfscanf", libc::vfscanf, 3),
         make_sim("fopen", libc::fopen, 2),
-        make_sim("freopen", libc::fopen, 3),
+        make_sim("fopen64", libc::fopen, 2),
+        make_sim("fdopen", libc::fdopen, 2),
+        make_sim("freopen", libc::freopen, 3),
+        make_sim("fileno", libc::fileno, 1),
         make_sim("fread", libc::fread, 4),
         make_sim("fwrite", libc::fwrite, 4),
-        make_sim("ferror", libc::zero, 1),
-        make_sim("fseek", libc::fseek, 2),
+        make_sim("ferror", libc::ferror, 1),
+        make_sim("clearerr", libc::clearerr, 1),
+        make_sim("fseek", libc::fseek, 3),
+        make_sim("fseeko", libc::fseek, 3),
         make_sim("ftell", libc::ftell, 1),
-        make_sim("fclose", libc::fgets, 1),
-        make_sim("rewind", libc::rewind, 2),
+        make_sim("ftello", libc::ftell, 1),
+        make_sim("fclose", libc::fclose, 1),
+        make_sim("rewind", libc::rewind, 1),
+        make_sim("ungetc", libc::ungetc, 2),
+        make_sim("getline", libc::getline, 3),
+        make_sim("getdelim", libc::getdelim, 4),
+        make_sim("__getdelim", libc::getdelim, 4),
+        make_sim("fgetwc", libc::fgetwc, 1),
+        make_sim("getwc", libc::fgetwc, 1),
+        make_sim("fgetws", libc::fgetws, 3),
+        make_sim("setvbuf", libc::setvbuf, 4),
+        make_sim("setbuffer", zero, 3),
+        make_sim("setlinebuf", zero, 1),
         make_sim("strlen", libc::strlen, 1),
         make_sim("strnlen", libc::strnlen, 2),
         make_sim("strstr", libc::strstr, 2),
@@ -114,6 +208,15 @@ pub fn get_sims() -> Vec<Sim> {
         make_sim("strcmp", libc::strcmp, 2),
         make_sim("strcoll", libc::strcmp, 2),
         make_sim("strncmp", libc::strncmp, 3),
+        make_sim("strcasecmp", libc::strcasecmp, 2),
+        make_sim("strncasecmp", libc::strncasecmp, 3),
+        make_sim("strspn", libc::strspn, 2),
+        make_sim("strcspn", libc::strcspn, 2),
+        make_sim("strpbrk", libc::strpbrk, 2),
+        make_sim("strtok", libc::strtok, 2),
+        make_sim("strtok_r", libc::strtok_r, 3),
+        make_sim("__strtok_r", libc::strtok_r, 3),
+        make_sim("strsep", libc::strsep, 2),
         make_sim("memmove", libc::memmove, 3),
         make_sim("__memmove_chk", libc::memmove_chk, 3),
         make_sim("memcpy", libc::memcpy, 3),
@@ -129,6 +232,27 @@ pub fn get_sims() -> Vec<Sim> {
         make_sim("memrchr", libc::memrchr, 3),
         make_sim("memcmp", libc::memcmp, 3),
         make_sim("bcmp", libc::memcmp, 3),
+        make_sim("__memcmpeq", libc::memcmp, 3),
+        make_sim("timingsafe_bcmp", libc::memcmp, 3),
+        make_sim("timingsafe_memcmp", libc::memcmp, 3),
+        make_sim("wcslen", libc::wcslen, 1),
+        make_sim("wcsnlen", libc::wcsnlen, 2),
+        make_sim("wcscpy", libc::wcscpy, 2),
+        make_sim("wcsncpy", libc::wcsncpy, 3),
+        make_sim("wcscat", libc::wcscat, 2),
+        make_sim("wcsdup", libc::wcsdup, 1),
+        make_sim("wcscmp", libc::wcscmp, 2),
+        make_sim("wcsncmp", libc::wcsncmp, 3),
+        make_sim("wcscoll", libc::wcscmp, 2),
+        make_sim("wcschr", libc::wcschr, 2),
+        make_sim("wcsrchr", libc::wcsrchr, 2),
+        make_sim("wmemchr", libc::wmemchr, 3),
+        make_sim("wmemcmp", libc::wmemcmp, 3),
+        make_sim("wmemcpy", libc::wmemcpy, 3),
+        make_sim("wmemmove", libc::wmemcpy, 3),
+        make_sim("wmemset", libc::wmemset, 3),
+        make_sim("qsort", libc::qsort, 4),
+        make_sim("bsearch", libc::bsearch, 5),
         make_sim("memmem", libc::memmem, 3),
         make_sim("bcopy", libc::bcopy, 3),
         make_sim("bzero", libc::bzero, 2),
@@ -161,7 +285,21 @@ pub fn get_sims() -> Vec<Sim> {
         make_sim("read", libc::read, 3),
         make_sim("write", libc::write, 3),
         make_sim("lseek", libc::lseek, 2),
-        make_sim("access", libc::access, 1),
+        make_sim("access", libc::access, 2),
+        make_sim("creat", libc::creat, 2),
+        make_sim("mkdir", libc::mkdir, 2),
+        make_sim("rmdir", libc::rmdir, 1),
+        make_sim("unlink", libc::unlink, 1),
+        make_sim("remove", libc::remove, 1),
+        make_sim("rename", libc::rename, 2),
+        make_sim("chdir", libc::chdir, 1),
+        make_sim("chmod", libc::chmod, 2),
+        make_sim("getcwd", libc::getcwd, 2),
+        make_sim("opendir", libc::opendir, 1),
+        make_sim("fdopendir", libc::fdopendir, 1),
+        make_sim("readdir", libc::readdir, 1),
+        make_sim("readdir64", libc::readdir, 1),
+        make_sim("closedir", libc::closedir, 1),
         make_sim("stat", libc::stat, 2),
         make_sim("fstat", libc::fstat, 2),
         make_sim("lstat", libc::lstat, 2),
@@ -177,8 +315,32 @@ pub fn get_sims() -> Vec<Sim> {
         make_sim("ptrace", libc::ptrace, 0),
         make_sim("syscall", libc::c_syscall, 0),
         make_sim("getenv", libc::getenv, 1),
-        make_sim("abort", libc::exit, 1),
+        make_sim("abort", libc::abort, 0),
         make_sim("exit", libc::exit, 1),
+        make_sim("atexit", libc::atexit, 1),
+        make_sim("__cxa_atexit", libc::__cxa_atexit, 3),
+        make_sim("pthread_create", libc::pthread_create, 4),
+        make_sim("pthread_join", libc::pthread_join, 2),
+        make_sim("signal", libc::signal, 2),
+        make_sim("bsd_signal", libc::signal, 2),
+        make_sim("sysv_signal", libc::signal, 2),
+        make_sim("raise", libc::raise, 1),
+        make_sim("setjmp", libc::setjmp, 1),
+        make_sim("_setjmp", libc::setjmp, 1),
+        make_sim("sigsetjmp", libc::setjmp, 2),
+        make_sim("__sigsetjmp", libc::setjmp, 2),
+        make_sim("longjmp", libc::longjmp, 2),
+        make_sim("_longjmp", libc::longjmp, 2),
+        make_sim("siglongjmp", libc::longjmp, 2),
+        make_sim("__longjmp_chk", libc::longjmp, 2),
+        make_sim("time", libc::time, 1),
+        make_sim("gettimeofday", libc::gettimeofday, 2),
+        make_sim("clock_gettime", libc::clock_gettime, 2),
+        make_sim("clock", libc::clock, 0),
+        make_sim("gmtime", libc::gmtime, 1),
+        make_sim("gmtime_r", libc::gmtime_r, 2),
+        make_sim("localtime", libc::gmtime, 1),
+        make_sim("localtime_r", libc::gmtime_r, 2),
         make_sim("xexit", libc::exit, 1),
         make_sim("ioctl", error, 1),
         make_sim("sysctl", zero, 1),
@@ -189,9 +351,121 @@ pub fn get_sims() -> Vec<Sim> {
         make_sim("gethostname", libc::gethostname, 0),
         make_sim("getpagesize", libc::getpagesize, 0),
         make_sim("__stack_chk_fail", ret, 0),
-        // this is covered by the hook in radius.rs
-        // make_sim("__libc_start_main", libc::__libc_start_main, 5),
+        // this is simulated at its call site by entry_state in radius.rs
+        // make_sim("__libc_start_main", libc::__libc_start_main, 7),
         make_sim("__cfi_slowpath", zero, 0),
         make_sim("__errno_location", libc::__errno_location, 0),
     ]
 }
+
+// get a vec of the java and android framework Sims for dalvik, these are
+// keyed by class, method and parameter types instead of import symbol
+pub fn get_dalvik_sims() -> Vec<Sim> {
+    let string = "Ljava/lang/String;";
+    let builder = "Ljava/lang/StringBuilder;";
+    let integer = "Ljava/lang/Integer;";
+    let arrays = "Ljava/util/Arrays;";
+
+    let mut sims = vec![
+        make_sim(&format!("{}-><init>()", string), dalvik::string_init, 1),
+        make_sim(&format!("{}-><init>([B)", string), dalvik::string_init_bytes, 2),
+        make_sim(&format!("{}->equals(Ljava/lang/Object;)", string), dalvik::string_equals, 2),
+        make_sim(
+            &format!("{}->compareTo(Ljava/lang/String;)", string),
+            dalvik::string_compare_to,
+            2,
+        ),
+        make_sim(&format!("{}->length()", string), dalvik::string_length, 1),
+        make_sim(&format!("{}->isEmpty()", string), dalvik::string_is_empty, 1),
+        make_sim(&format!("{}->charAt(I)", string), dalvik::string_char_at, 2),
+        make_sim(&format!("{}->concat(Ljava/lang/String;)", string), dalvik::string_concat, 2),
+        make_sim(
+            &format!("{}->contains(Ljava/lang/CharSequence;)", string),
+            dalvik::string_contains,
+            2,
+        ),
+        make_sim(
+            &format!("{}->startsWith(Ljava/lang/String;)", string),
+            dalvik::string_starts_with,
+            2,
+        ),
+        make_sim(&format!("{}->indexOf(I)", string), dalvik::string_index_of, 2),
+        make_sim(&format!("{}->hashCode()", string), dalvik::string_hash_code, 1),
+        make_sim(&format!("{}->getBytes()", string), dalvik::string_get_bytes, 1),
+        make_sim(&format!("{}->toCharArray()", string), dalvik::string_to_char_array, 1),
+        make_sim(&format!("{}->toString()", string), dalvik::this, 1),
+        make_sim(&format!("{}->intern()", string), dalvik::this, 1),
+        make_sim(&format!("{}->valueOf(I)", string), dalvik::string_value_of_int, 1),
+        make_sim(&format!("{}->valueOf(C)", string), dalvik::string_value_of_char, 1),
+        make_sim(&format!("{}->valueOf(Ljava/lang/Object;)", string), dalvik::this, 1),
+        make_sim(&format!("{}-><init>()", builder), dalvik::builder_init, 1),
+        make_sim(
+            &format!("{}-><init>(Ljava/lang/String;)", builder),
+            dalvik::builder_init_string,
+            2,
+        ),
+        make_sim(&format!("{}->append(Ljava/lang/String;)", builder), dalvik::builder_append, 2),
+        make_sim(
+            &format!("{}->append(Ljava/lang/CharSequence;)", builder),
+            dalvik::builder_append,
+            2,
+        ),
+        make_sim(&format!("{}->append(I)", builder), dalvik::builder_append_int, 2),
+        make_sim(&format!("{}->append(C)", builder), dalvik::builder_append_char, 2),
+        make_sim(&format!("{}->toString()", builder), dalvik::builder_to_string, 1),
+        make_sim(&format!("{}->length()", builder), dalvik::builder_length, 1),
+        make_sim(&format!("{}->parseInt(Ljava/lang/String;)", integer), dalvik::parse_int, 1),
+        make_sim(
+            &format!("{}->parseInt(Ljava/lang/String;I)", integer),
+            dalvik::parse_int_radix,
+            2,
+        ),
+        make_sim(&format!("{}->valueOf(Ljava/lang/String;)", integer), dalvik::parse_int, 1),
+        make_sim(&format!("{}->valueOf(I)", integer), dalvik::integer_value_of, 1),
+        make_sim(&format!("{}->intValue()", integer), dalvik::this, 1),
+        make_sim(&format!("{}->toString(I)", integer), dalvik::string_value_of_int, 1),
+        make_sim(&format!("{}->equals([B[B)", arrays), dalvik::arrays_equals_bytes, 2),
+        make_sim(&format!("{}->equals([C[C)", arrays), dalvik::arrays_equals_chars, 2),
+        make_sim(&format!("{}->equals([I[I)", arrays), dalvik::arrays_equals_ints, 2),
+        make_sim(&format!("{}->copyOf([BI)", arrays), dalvik::arrays_copy_of_bytes, 2),
+        make_sim(&format!("{}->copyOf([CI)", arrays), dalvik::arrays_copy_of_chars, 2),
+        make_sim(&format!("{}->copyOf([II)", arrays), dalvik::arrays_copy_of_ints, 2),
+        make_sim(&format!("{}->fill([BB)", arrays), dalvik::arrays_fill_bytes, 2),
+        make_sim(&format!("{}->fill([CC)", arrays), dalvik::arrays_fill_chars, 2),
+        make_sim(&format!("{}->fill([II)", arrays), dalvik::arrays_fill_ints, 2),
+    ];
+
+    // the interfaces and implementations share the same sims
+    for list in &["Ljava/util/ArrayList;", "Ljava/util/List;"] {
+        sims.extend(vec![
+            make_sim(&format!("{}-><init>()", list), dalvik::collection_init, 1),
+            make_sim(&format!("{}->add(Ljava/lang/Object;)", list), dalvik::list_add, 2),
+            make_sim(&format!("{}->get(I)", list), dalvik::list_get, 2),
+            make_sim(&format!("{}->size()", list), dalvik::list_size, 1),
+            make_sim(&format!("{}->isEmpty()", list), dalvik::list_is_empty, 1),
+            make_sim(&format!("{}->contains(Ljava/lang/Object;)", list), dalvik::list_contains, 2),
+            make_sim(&format!("{}->clear()", list), dalvik::collection_clear, 1),
+        ]);
+    }
+
+    for map in &["Ljava/util/HashMap;", "Ljava/util/Map;"] {
+        sims.extend(vec![
+            make_sim(&format!("{}-><init>()", map), dalvik::collection_init, 1),
+            make_sim(
+                &format!("{}->put(Ljava/lang/Object;Ljava/lang/Object;)", map),
+                dalvik::map_put,
+                3,
+            ),
+            make_sim(&format!("{}->get(Ljava/lang/Object;)", map), dalvik::map_get, 2),
+            make_sim(
+                &format!("{}->containsKey(Ljava/lang/Object;)", map),
+                dalvik::map_contains_key,
+                2,
+            ),
+            make_sim(&format!("{}->size()", map), dalvik::map_size, 1),
+            make_sim(&format!("{}->clear()", map), dalvik::collection_clear, 1),
+        ]);
+    }
+
+    sims
+}
@@ -1,35 +1,104 @@
-use crate::sims::fs::FileMode;
+use crate::sims::fs::{FileMode, NodeType, Stat};
 use crate::state::{State, StateStatus};
-use crate::value::Value;
+use crate::value::{vc, Value};
 
 const MAX_LEN: u64 = 8192;
 
+const AT_FDCWD: i32 = -100;
+const AT_REMOVEDIR: u64 = 0x200;
+const AT_EMPTY_PATH: u64 = 0x1000;
+const DT_DIR: u64 = 4;
+const DT_REG: u64 = 8;
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+const TCGETS: u64 = 0x5401;
+const TIOCGWINSZ: u64 = 0x5413;
+const RLIMIT_STACK: u64 = 3;
+const STACK_LIMIT: u64 = 0x800000;
+const UTSNAME_LEN: u64 = 65;
+
+// xnu puts the syscall class in the top byte, 2 is unix
+const DARWIN_CLASS_MASK: u64 = 0xffffff;
+
 pub fn syscall(syscall_name: &str, state: &mut State, args: &[Value]) -> Value {
     match syscall_name {
         "indirect_syscall" => indirect(state, args), // fuq
 
         "open" => open(state, args),
         "openat" => openat(state, args),
+        "creat" => creat(state, args),
         "close" => close(state, args),
         "read" => read(state, args),
         "write" => write(state, args),
+        "pread64" | "pread" => pread(state, args),
+        "readv" => readv(state, args),
+        "writev" => writev(state, args),
         "access" => access(state, args),
-        "stat" => stat(state, args),
-        "fstat" => fstat(state, args),
-        "lstat" => lstat(state, args),
+        "faccessat" => faccessat(state, args),
+        "stat" | "stat64" => stat(state, args),
+        "fstat" | "fstat64" => fstat(state, args),
+        "lstat" | "lstat64" => lstat(state, args),
+        "newfstatat" | "fstatat64" => newfstatat(state, args),
         "lseek" => lseek(state, args),
+        "mkdir" => mkdir(state, args),
+        "mkdirat" => mkdirat(state, args),
+        "rmdir" => rmdir(state, args),
+        "unlink" => unlink(state, args),
+        "unlinkat" => unlinkat(state, args),
+        "rename" => rename(state, args),
+        "renameat" | "renameat2" => renameat(state, args),
+        "chdir" => chdir(state, args),
+        "fchdir" => fchdir(state, args),
+        "chmod" => chmod(state, args),
+        "fchmod" => fchmod(state, args),
+        "getdents64" | "getdirentries64" => getdents64(state, args),
+        "pipe" | "pipe2" => pipe(state, args),
+        "dup" => dup(state, args),
+        "dup2" | "dup3" => dup2(state, args),
+        "fcntl" | "fcntl64" => fcntl(state, args),
+        "ioctl" => ioctl(state, args),
+        "getcwd" => getcwd(state, args),
+        "readlink" => readlink(state, args),
+        "readlinkat" => readlinkat(state, args),
         "mprotect" => mmap(state, args),
-        "mmap" => mmap(state, args),
+        "mmap" | "mmap2" => mmap(state, args),
         "munmap" => munmap(state, args),
+        "madvise" => success(state, args),
         "brk" => brk(state, args),
         "sbrk" => sbrk(state, args),
         "getpid" => getpid(state, args),
-        "getuid" => getuid(state, args),
-        "geteuid" => getuid(state, args),
-        "getgid" => getuid(state, args),
-        "getegid" => getuid(state, args),
+        "gettid" => getpid(state, args),
+        "set_tid_address" => getpid(state, args),
+        "getuid" | "getuid32" => getuid(state, args),
+        "geteuid" | "geteuid32" => getuid(state, args),
+        "getgid" | "getgid32" => getuid(state, args),
+        "getegid" | "getegid32" => getuid(state, args),
+        "uname" => uname(state, args),
+        "getrandom" => getrandom(state, args),
+        "getentropy" => getentropy(state, args),
+        "issetugid" => success(state, args),
+        "csops" => success(state, args),
+        "thread_selfid" => getpid(state, args),
+        "getppid" => getpid(state, args),
+        "time" => time(state, args),
+        "gettimeofday" => gettimeofday(state, args),
+        "clock_gettime" => clock_gettime(state, args),
+        "nanosleep" => success(state, args),
+        "sched_yield" => success(state, args),
+        "rt_sigaction" | "sigaction" => rt_sigaction(state, args),
+        "rt_sigprocmask" | "sigprocmask" => rt_sigprocmask(state, args),
+        "sigaltstack" => success(state, args),
+        "kill" | "tgkill" => success(state, args),
+        "arch_prctl" => arch_prctl(state, args),
+        "set_thread_area" => success(state, args),
+        "set_robust_list" => success(state, args),
+        "futex" => success(state, args),
+        "rseq" => error(state, args), // glibc falls back when it is unsupported
+        "prlimit64" => prlimit64(state, args),
+        "getrlimit" => getrlimit(state, args),
         "fork" => fork(state, args),
         "exit" => exit(state, args),
+        "exit_group" => exit(state, args),
         "ptrace" => ptrace(state, args),
         _ => error(state, args), // this is literally every syscall
                                  // the rest arent real
@@ -40,25 +109,466 @@ pub fn syscall(syscall_name: &str, state: &mut State, args: &[Value]) -> Value {
 // get actual syscall and recall ..
 pub fn indirect(state: &mut State, args: &[Value]) -> Value {
     let sn = state.solver.evalcon_to_u64(&args[0]).unwrap();
-    let sys_str = state.r2api.get_syscall_str(sn).unwrap();
+    let sn = if state.info.bin.os == "darwin" {
+        sn & DARWIN_CLASS_MASK
+    } else {
+        sn
+    };
+    let sys_str = get_syscall_name(state, sn).unwrap_or_default();
     syscall(sys_str.as_str(), state, &args[1..])
 }
 
+/// Get the syscall number, xnu uses x16 on arm64 and a class
+/// in the top byte of the number on x86_64
+pub fn get_syscall_num(state: &mut State) -> Value {
+    let bin = &state.info.bin;
+    if bin.os != "darwin" {
+        state.registers.get_with_alias("SN")
+    } else if bin.arch == "arm" && bin.bits == 64 {
+        state.registers.get("x16") & vc(DARWIN_CLASS_MASK)
+    } else {
+        state.registers.get_with_alias("SN") & vc(DARWIN_CLASS_MASK)
+    }
+}
+
+/// Get the name of syscall `num`, using the builtin tables when they apply
+pub fn get_syscall_name(state: &mut State, num: u64) -> Option<String> {
+    let bin = &state.info.bin;
+    let table = get_syscall_table(&bin.os, &bin.arch, bin.bits);
+
+    if let Some((_, name)) = table.iter().find(|(n, _)| *n == num) {
+        Some(name.to_string())
+    } else {
+        state.r2api.get_syscall_str(num).ok()
+    }
+}
+
+/// Get the syscall table for the os and arch
+pub fn get_syscall_table(os: &str, arch: &str, bits: u64) -> &'static [(u64, &'static str)] {
+    match os {
+        "linux" => get_linux_syscalls(arch, bits),
+        "darwin" => DARWIN,
+        _ => &[],
+    }
+}
+
+/// Get the linux syscall table for the arch. This only has the syscalls
+/// that are implemented, r2's tables are used for everything else
+pub fn get_linux_syscalls(arch: &str, bits: u64) -> &'static [(u64, &'static str)] {
+    match (arch, bits) {
+        ("x86", 64) => LINUX_X86_64,
+        ("x86", 32) => LINUX_X86,
+        ("arm", 64) | ("riscv", 64) => LINUX_GENERIC,
+        ("arm", 16) | ("arm", 32) => LINUX_ARM,
+        _ => &[],
+    }
+}
+
+const LINUX_X86_64: &[(u64, &str)] = &[
+    (0, "read"),
+    (1, "write"),
+    (2, "open"),
+    (3, "close"),
+    (4, "stat"),
+    (5, "fstat"),
+    (6, "lstat"),
+    (8, "lseek"),
+    (9, "mmap"),
+    (10, "mprotect"),
+    (11, "munmap"),
+    (12, "brk"),
+    (13, "rt_sigaction"),
+    (14, "rt_sigprocmask"),
+    (16, "ioctl"),
+    (17, "pread64"),
+    (19, "readv"),
+    (20, "writev"),
+    (21, "access"),
+    (22, "pipe"),
+    (24, "sched_yield"),
+    (28, "madvise"),
+    (32, "dup"),
+    (33, "dup2"),
+    (35, "nanosleep"),
+    (39, "getpid"),
+    (57, "fork"),
+    (60, "exit"),
+    (62, "kill"),
+    (63, "uname"),
+    (72, "fcntl"),
+    (79, "getcwd"),
+    (80, "chdir"),
+    (81, "fchdir"),
+    (82, "rename"),
+    (83, "mkdir"),
+    (84, "rmdir"),
+    (85, "creat"),
+    (87, "unlink"),
+    (89, "readlink"),
+    (90, "chmod"),
+    (91, "fchmod"),
+    (96, "gettimeofday"),
+    (97, "getrlimit"),
+    (101, "ptrace"),
+    (102, "getuid"),
+    (104, "getgid"),
+    (107, "geteuid"),
+    (108, "getegid"),
+    (131, "sigaltstack"),
+    (158, "arch_prctl"),
+    (186, "gettid"),
+    (201, "time"),
+    (202, "futex"),
+    (217, "getdents64"),
+    (218, "set_tid_address"),
+    (228, "clock_gettime"),
+    (231, "exit_group"),
+    (234, "tgkill"),
+    (257, "openat"),
+    (258, "mkdirat"),
+    (262, "newfstatat"),
+    (263, "unlinkat"),
+    (264, "renameat"),
+    (267, "readlinkat"),
+    (269, "faccessat"),
+    (273, "set_robust_list"),
+    (292, "dup3"),
+    (293, "pipe2"),
+    (302, "prlimit64"),
+    (316, "renameat2"),
+    (318, "getrandom"),
+    (334, "rseq"),
+];
+
+const LINUX_X86: &[(u64, &str)] = &[
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (8, "creat"),
+    (10, "unlink"),
+    (12, "chdir"),
+    (13, "time"),
+    (15, "chmod"),
+    (19, "lseek"),
+    (20, "getpid"),
+    (26, "ptrace"),
+    (33, "access"),
+    (37, "kill"),
+    (38, "rename"),
+    (39, "mkdir"),
+    (40, "rmdir"),
+    (41, "dup"),
+    (42, "pipe"),
+    (45, "brk"),
+    (54, "ioctl"),
+    (63, "dup2"),
+    (78, "gettimeofday"),
+    (85, "readlink"),
+    (91, "munmap"),
+    (94, "fchmod"),
+    (122, "uname"),
+    (125, "mprotect"),
+    (133, "fchdir"),
+    (145, "readv"),
+    (146, "writev"),
+    (158, "sched_yield"),
+    (162, "nanosleep"),
+    (174, "rt_sigaction"),
+    (175, "rt_sigprocmask"),
+    (183, "getcwd"),
+    (186, "sigaltstack"),
+    (191, "getrlimit"),
+    (192, "mmap2"),
+    (195, "stat64"),
+    (196, "lstat64"),
+    (197, "fstat64"),
+    (199, "getuid32"),
+    (200, "getgid32"),
+    (201, "geteuid32"),
+    (202, "getegid32"),
+    (219, "madvise"),
+    (220, "getdents64"),
+    (221, "fcntl64"),
+    (224, "gettid"),
+    (240, "futex"),
+    (243, "set_thread_area"),
+    (252, "exit_group"),
+    (258, "set_tid_address"),
+    (265, "clock_gettime"),
+    (270, "tgkill"),
+    (295, "openat"),
+    (296, "mkdirat"),
+    (300, "fstatat64"),
+    (301, "unlinkat"),
+    (302, "renameat"),
+    (307, "faccessat"),
+    (311, "set_robust_list"),
+    (330, "dup3"),
+    (331, "pipe2"),
+    (340, "prlimit64"),
+    (355, "getrandom"),
+];
+
+// arm64 and riscv64 use the asm-generic table
+const LINUX_GENERIC: &[(u64, &str)] = &[
+    (17, "getcwd"),
+    (23, "dup"),
+    (24, "dup3"),
+    (25, "fcntl"),
+    (29, "ioctl"),
+    (34, "mkdirat"),
+    (35, "unlinkat"),
+    (38, "renameat"),
+    (48, "faccessat"),
+    (49, "chdir"),
+    (50, "fchdir"),
+    (52, "fchmod"),
+    (56, "openat"),
+    (57, "close"),
+    (59, "pipe2"),
+    (61, "getdents64"),
+    (62, "lseek"),
+    (63, "read"),
+    (64, "write"),
+    (65, "readv"),
+    (66, "writev"),
+    (67, "pread64"),
+    (78, "readlinkat"),
+    (79, "newfstatat"),
+    (80, "fstat"),
+    (93, "exit"),
+    (94, "exit_group"),
+    (96, "set_tid_address"),
+    (98, "futex"),
+    (99, "set_robust_list"),
+    (101, "nanosleep"),
+    (113, "clock_gettime"),
+    (117, "ptrace"),
+    (124, "sched_yield"),
+    (129, "kill"),
+    (131, "tgkill"),
+    (132, "sigaltstack"),
+    (134, "rt_sigaction"),
+    (135, "rt_sigprocmask"),
+    (160, "uname"),
+    (163, "getrlimit"),
+    (169, "gettimeofday"),
+    (172, "getpid"),
+    (174, "getuid"),
+    (175, "geteuid"),
+    (176, "getgid"),
+    (177, "getegid"),
+    (178, "gettid"),
+    (214, "brk"),
+    (215, "munmap"),
+    (222, "mmap"),
+    (226, "mprotect"),
+    (233, "madvise"),
+    (261, "prlimit64"),
+    (276, "renameat2"),
+    (278, "getrandom"),
+    (293, "rseq"),
+];
+
+// bsd syscalls are the same for every xnu arch
+const DARWIN: &[(u64, &str)] = &[
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (10, "unlink"),
+    (12, "chdir"),
+    (13, "fchdir"),
+    (15, "chmod"),
+    (20, "getpid"),
+    (24, "getuid"),
+    (25, "geteuid"),
+    (26, "ptrace"),
+    (33, "access"),
+    (37, "kill"),
+    (39, "getppid"),
+    (41, "dup"),
+    (42, "pipe"),
+    (43, "getegid"),
+    (46, "sigaction"),
+    (47, "getgid"),
+    (48, "sigprocmask"),
+    (54, "ioctl"),
+    (58, "readlink"),
+    (73, "munmap"),
+    (74, "mprotect"),
+    (75, "madvise"),
+    (90, "dup2"),
+    (92, "fcntl"),
+    (116, "gettimeofday"),
+    (120, "readv"),
+    (121, "writev"),
+    (124, "fchmod"),
+    (128, "rename"),
+    (136, "mkdir"),
+    (137, "rmdir"),
+    (153, "pread"),
+    (169, "csops"),
+    (194, "getrlimit"),
+    (197, "mmap"),
+    (199, "lseek"),
+    (327, "issetugid"),
+    (338, "stat64"),
+    (339, "fstat64"),
+    (340, "lstat64"),
+    (344, "getdirentries64"),
+    (372, "thread_selfid"),
+    (463, "openat"),
+    (465, "renameat"),
+    (466, "faccessat"),
+    (469, "fstatat64"),
+    (472, "unlinkat"),
+    (475, "mkdirat"),
+    (500, "getentropy"),
+];
+
+const LINUX_ARM: &[(u64, &str)] = &[
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (8, "creat"),
+    (10, "unlink"),
+    (12, "chdir"),
+    (15, "chmod"),
+    (19, "lseek"),
+    (20, "getpid"),
+    (26, "ptrace"),
+    (33, "access"),
+    (37, "kill"),
+    (38, "rename"),
+    (39, "mkdir"),
+    (40, "rmdir"),
+    (41, "dup"),
+    (42, "pipe"),
+    (45, "brk"),
+    (54, "ioctl"),
+    (63, "dup2"),
+    (78, "gettimeofday"),
+    (85, "readlink"),
+    (91, "munmap"),
+    (94, "fchmod"),
+    (122, "uname"),
+    (125, "mprotect"),
+    (133, "fchdir"),
+    (145, "readv"),
+    (146, "writev"),
+    (158, "sched_yield"),
+    (162, "nanosleep"),
+    (174, "rt_sigaction"),
+    (175, "rt_sigprocmask"),
+    (183, "getcwd"),
+    (186, "sigaltstack"),
+    (191, "getrlimit"),
+    (192, "mmap2"),
+    (195, "stat64"),
+    (196, "lstat64"),
+    (197, "fstat64"),
+    (199, "getuid32"),
+    (200, "getgid32"),
+    (201, "geteuid32"),
+    (202, "getegid32"),
+    (217, "getdents64"),
+    (220, "madvise"),
+    (221, "fcntl64"),
+    (224, "gettid"),
+    (240, "futex"),
+    (248, "exit_group"),
+    (256, "set_tid_address"),
+    (263, "clock_gettime"),
+    (268, "tgkill"),
+    (322, "openat"),
+    (323, "mkdirat"),
+    (327, "fstatat64"),
+    (328, "unlinkat"),
+    (329, "renameat"),
+    (334, "faccessat"),
+    (338, "set_robust_list"),
+    (358, "dup3"),
+    (359, "pipe2"),
+    (369, "prlimit64"),
+    (384, "getrandom"),
+];
+
 pub fn open(state: &mut State, args: &[Value]) -> Value {
-    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
-    let len = state.memory_strlen(&args[0], &Value::Concrete(MAX_LEN, 0));
-    let length = state.solver.evalcon_to_u64(&len).unwrap();
-    let path = state.memory_read_string(addr, length as usize);
-    if let Some(fd) = state.filesystem.open(path.as_str(), FileMode::Read) {
+    let path = read_path(state, &args[0]);
+    let flags = state.solver.evalcon_to_u64(&args[1]).unwrap_or(0);
+
+    // the flag values are different on xnu
+    let (creat, trunc, append) = if state.info.bin.os == "darwin" {
+        (0x200, 0x400, 0x8)
+    } else {
+        (0x40, 0x200, 0x400)
+    };
+
+    let mode = if flags & append != 0 {
+        FileMode::Append
+    } else if flags & 3 != 0 {
+        FileMode::Write
+    } else {
+        FileMode::Read
+    };
+    open_path(state, &path, mode, flags & creat != 0, flags & trunc != 0)
+}
+
+/// Open the file at `path`, creating or truncating it if needed
+pub fn open_path(state: &mut State, path: &str, mode: FileMode, create: bool, trunc: bool) -> Value {
+    let exists = state.filesystem.lookup(path).is_some();
+    let fd = if (create && !exists) || (trunc && exists && mode != FileMode::Read) {
+        state.filesystem.create(path, mode)
+    } else {
+        state.filesystem.open(path, mode)
+    };
+
+    if let Some(fd) = fd {
         Value::Concrete(fd as u64, 0)
     } else {
         Value::Concrete(-1i64 as u64, 0)
     }
 }
 
-// ignore dir for now idk
+// resolve a path relative to the directory fd of an *at syscall
+fn at_path(state: &mut State, dirfd: &Value, path: &Value) -> String {
+    let path = read_path(state, path);
+    let dirfd = state.solver.evalcon_to_u64(dirfd).unwrap() as i32;
+    if path.starts_with('/') || dirfd == AT_FDCWD {
+        path
+    } else if let Some(dir) = state.filesystem.getpath(dirfd as usize) {
+        format!("{}/{}", dir, path)
+    } else {
+        path
+    }
+}
+
+// write a path into a new allocation so the plain syscalls can be reused
+fn path_value(state: &mut State, path: &str) -> Value {
+    let addr = state.memory.alloc(&vc(path.len() as u64 + 1));
+    state.memory_write_string(addr, path);
+    state.memory.write_value(addr + path.len() as u64, &vc(0), 1);
+    vc(addr)
+}
+
 pub fn openat(state: &mut State, args: &[Value]) -> Value {
-    open(state, &args[1..])
+    let path = at_path(state, &args[0], &args[1]);
+    let path = path_value(state, &path);
+    open(state, &[path, args[2].to_owned(), args[3].to_owned()])
+}
+
+pub fn creat(state: &mut State, args: &[Value]) -> Value {
+    let path = read_path(state, &args[0]);
+    open_path(state, &path, FileMode::Write, true, true)
 }
 
 pub fn close(state: &mut State, args: &[Value]) -> Value {
@@ -69,27 +579,72 @@ pub fn close(state: &mut State, args: &[Value]) -> Value {
 
 pub fn read(state: &mut State, args: &[Value]) -> Value {
     let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
-    let length = state.solver.max_value(&args[2]) & 0xffffffff;
+    // the file position is concrete so a symbolic length is concretized
+    let length = state.solver.evalcon_to_u64(&args[2]).unwrap() & 0xffffffff;
+    let count = read_count(state, fd as usize, length);
     let data = state.filesystem.read(fd as usize, length as usize);
     let len = data.len();
-    state.memory_write(&args[1], &data, &args[2]);
-    Value::Concrete(len as u64, args[2].get_taint())
+
+    if let Some(count) = count {
+        // only the bytes before the symbolic end of the file are written
+        state.memory_write(&args[1], &data, &count);
+        count.with_taint(args[2].get_taint())
+    } else {
+        state.memory_write(&args[1], &data, &args[2]);
+        Value::Concrete(len as u64, args[2].get_taint())
+    }
+}
+
+/// Get the number of bytes a read of `length` from `fd` will return
+/// if the file has a symbolic length
+pub fn read_count(state: &mut State, fd: usize, length: u64) -> Option<Value> {
+    let file = state.filesystem.files.get(fd)?;
+    let len = file.length.to_owned()?;
+    let pos = vc(file.position as u64);
+    let left = state.cond(&len.ugt(&pos), &len.sub(&pos), &vc(0));
+    Some(state.cond(&left.ult(&vc(length)), &left, &vc(length)))
+}
+
+/// Get a condition that is true if `fd` is at the end of the file
+pub fn at_eof(state: &mut State, fd: usize) -> Value {
+    if let Some(file) = state.filesystem.files.get(fd) {
+        let pos = vc(file.position as u64);
+        if let Some(len) = &file.length {
+            len.ulte(&pos)
+        } else {
+            vc((file.position >= file.content.len()) as u64)
+        }
+    } else {
+        vc(1)
+    }
+}
+
+/// Read one byte from `fd`, returns -1 at the end of the file
+pub fn read_byte(state: &mut State, fd: usize) -> Value {
+    let eof = at_eof(state, fd);
+    let data = state.filesystem.read(fd, 1);
+    if let Some(c) = data.get(0) {
+        state.cond(&eof, &vc(-1i64 as u64), &c.uext(&vc(8)))
+    } else {
+        vc(-1i64 as u64)
+    }
 }
 
 pub fn write(state: &mut State, args: &[Value]) -> Value {
     let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
     let data = state.memory_read(&args[1], &args[2]);
     let len = data.len();
-    state.filesystem.write(fd as usize, data);
-    Value::Concrete(len as u64, 0)
+    if state.filesystem.write(fd as usize, data).is_some() {
+        Value::Concrete(len as u64, 0)
+    } else {
+        error(state, args)
+    }
 }
 
 pub fn access(state: &mut State, args: &[Value]) -> Value {
-    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
-    let len = state.memory_strlen(&args[0], &Value::Concrete(MAX_LEN, 0));
-    let length = state.solver.evalcon_to_u64(&len).unwrap();
-    let path = state.memory_read_string(addr, length as usize);
-    state.filesystem.access(path.as_str())
+    let path = read_path(state, &args[0]);
+    let mode = state.solver.evalcon_to_u64(&args[1]).unwrap_or(0);
+    state.filesystem.access(path.as_str(), mode as u32 & 7)
 }
 
 /*
@@ -136,9 +691,16 @@ pub fn stat(state: &mut State, args: &[Value]) -> Value {
     let path_len = state.memory_strlen(&args[0], &Value::Concrete(4096, 0)); // idk
     let path = state.memory_read_string(path_addr, path_len.as_u64().unwrap() as usize);
     let statopt = state.filesystem.stat(&path);
-    let statbuf = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    write_stat(state, &args[1], statopt)
+}
+
+fn write_stat(state: &mut State, statbuf: &Value, statopt: Option<Stat>) -> Value {
+    let statbuf = state.solver.evalcon_to_u64(statbuf).unwrap();
 
-    if let Some(statdata) = statopt {
+    if let (Some(statdata), "darwin") = (&statopt, state.info.bin.os.as_str()) {
+        write_darwin_stat(state, statbuf, statdata);
+        Value::Concrete(0, 0)
+    } else if let Some(statdata) = statopt {
         // oof this is just one case, any different bits, arch, or os could be different
         // this definitely sucks.
         state
@@ -169,11 +731,10 @@ pub fn stat(state: &mut State, args: &[Value]) -> Value {
         state
             .memory
             .write_value(statbuf + 40, &Value::Concrete(statdata.st_rdev, 0), 8);
-        state.memory.write_value(
-            statbuf + 48,
-            &Value::Concrete(statdata.st_size as u64, 0),
-            8,
-        );
+        let size = statdata
+            .length
+            .unwrap_or(Value::Concrete(statdata.st_size as u64, 0));
+        state.memory.write_value(statbuf + 48, &size, 8);
         state.memory.write_value(
             statbuf + 56,
             &Value::Concrete(statdata.st_blksize as u64, 0),
@@ -221,10 +782,8 @@ pub fn stat(state: &mut State, args: &[Value]) -> Value {
 
 pub fn fstat(state: &mut State, args: &[Value]) -> Value {
     let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
-    let path = state.filesystem.getpath(fd as usize).unwrap_or_default();
-    let path_addr = state.memory.alloc(&Value::Concrete(path.len() as u64, 0));
-    state.memory_write_string(path_addr, &path);
-    stat(state, &[Value::Concrete(path_addr, 0), args[1].clone()])
+    let statopt = state.filesystem.fstat(fd as usize);
+    write_stat(state, &args[1], statopt)
 }
 
 // TODO handle symbolic links
@@ -236,7 +795,7 @@ pub fn lseek(state: &mut State, args: &[Value]) -> Value {
     let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
     let pos = state.solver.evalcon_to_u64(&args[1]).unwrap();
     let whence = state.solver.evalcon_to_u64(&args[2]).unwrap();
-    let result = state.filesystem.seek(fd as usize, pos as usize, whence as usize);
+    let result = state.filesystem.seek(fd as usize, pos as i64, whence as usize);
     result.unwrap_or(Value::Concrete(-1i64 as u64, 0))
 }
 
@@ -326,6 +885,548 @@ pub fn ptrace(_state: &mut State, _args: &[Value]) -> Value {
 }
 
 pub fn exit(state: &mut State, args: &[Value]) -> Value {
-    state.status = StateStatus::Inactive;
+    state.status = StateStatus::Exit;
+    state.exit_code = Some(args[0].to_owned());
+    args[0].to_owned()
+}
+
+// make a new symbolic value for nondeterministic syscall output
+pub fn nondet(state: &mut State, name: &str, bits: u32) -> Value {
+    let count = state.context.get(name).map(|v| v.len()).unwrap_or(0);
+    let value = state.symbolic_value(&format!("{}_{}", name, count), bits);
+    state
+        .context
+        .entry(name.to_string())
+        .or_default()
+        .push(value.clone());
+    value
+}
+
+fn read_path(state: &mut State, addr: &Value) -> String {
+    let len = state.memory_strlen(addr, &Value::Concrete(MAX_LEN, 0));
+    let length = state.solver.evalcon_to_u64(&len).unwrap();
+    let addr = state.solver.evalcon_to_u64(addr).unwrap();
+    state.memory_read_string(addr, length as usize)
+}
+
+pub fn pread(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap() as usize;
+    let offset = state.solver.evalcon_to_u64(&args[3]).unwrap() as i64;
+    if let Some(pos) = state.filesystem.seek(fd, 0, 1) {
+        if state.filesystem.seek(fd, offset, 0).is_none() {
+            return error(state, args);
+        }
+        let ret = read(state, args);
+        state.filesystem.seek(fd, pos.as_u64().unwrap() as i64, 0);
+        ret
+    } else {
+        error(state, args)
+    }
+}
+
+// read each iovec {base, len} in order
+fn iovecs(state: &mut State, args: &[Value]) -> Vec<(Value, Value)> {
+    let ptr = state.memory.bits / 8;
+    let iov = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    let count = state.solver.evalcon_to_u64(&args[2]).unwrap();
+
+    (0..count)
+        .map(|i| {
+            let addr = iov + 2 * ptr * i;
+            let base = state.memory.read_value(addr, ptr as usize);
+            let len = state.memory.read_value(addr + ptr, ptr as usize);
+            (base, len)
+        })
+        .collect()
+}
+
+pub fn readv(state: &mut State, args: &[Value]) -> Value {
+    let mut total = vc(0);
+    for (base, len) in iovecs(state, args) {
+        let n = read(state, &[args[0].clone(), base, len.clone()]);
+        total = total + n.clone();
+        if state.solver.evalcon_to_u64(&n) != state.solver.evalcon_to_u64(&len) {
+            break;
+        }
+    }
+    total
+}
+
+pub fn writev(state: &mut State, args: &[Value]) -> Value {
+    let mut total = vc(0);
+    for (base, len) in iovecs(state, args) {
+        total = total + write(state, &[args[0].clone(), base, len]);
+    }
+    total
+}
+
+pub fn faccessat(state: &mut State, args: &[Value]) -> Value {
+    let path = at_path(state, &args[0], &args[1]);
+    let mode = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0);
+    state.filesystem.access(&path, mode as u32 & 7)
+}
+
+pub fn newfstatat(state: &mut State, args: &[Value]) -> Value {
+    let flags = state.solver.evalcon_to_u64(&args[3]).unwrap();
+    if flags & AT_EMPTY_PATH != 0 {
+        fstat(state, &[args[0].clone(), args[2].clone()])
+    } else {
+        let path = at_path(state, &args[0], &args[1]);
+        let statopt = state.filesystem.stat(&path);
+        write_stat(state, &args[2], statopt)
+    }
+}
+
+pub fn getdents64(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap() as usize;
+    let addr = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    let size = state.solver.evalcon_to_u64(&args[2]).unwrap();
+
+    let path = state.filesystem.getpath(fd).unwrap_or_default();
+    if state.filesystem.list(&path).is_none() {
+        return error(state, args);
+    }
+
+    let mut offset = 0;
+    loop {
+        let position = state.filesystem.files[fd].position;
+        if let Some((name, node_type, ino)) = state.filesystem.readdir(fd) {
+            let reclen = dirent_len(state, &name);
+            if offset + reclen > size {
+                // doesnt fit, leave it for the next call
+                state.filesystem.files[fd].position = position;
+                break;
+            }
+            let entry = (name, node_type, ino, position as u64 + 1);
+            write_dirent(state, addr + offset, &entry);
+            offset += reclen;
+        } else {
+            break;
+        }
+    }
+    vc(offset)
+}
+
+fn dirent_len(state: &State, name: &str) -> u64 {
+    // linux_dirent64 and xnu dirent have different headers
+    let header = if state.info.bin.os == "darwin" { 21 } else { 19 };
+    (header + name.len() as u64 + 1 + 7) & !7
+}
+
+/// Write a dirent for (name, type, inode, offset) at `addr`, returns its length
+pub fn write_dirent(state: &mut State, addr: u64, entry: &(String, NodeType, u64, u64)) -> u64 {
+    let (name, node_type, ino, off) = entry;
+    let reclen = dirent_len(state, name);
+    let d_type = if *node_type == NodeType::Directory {
+        DT_DIR
+    } else {
+        DT_REG
+    };
+
+    state.memory.write_value(addr, &vc(0), reclen as usize);
+    state.memory.write_value(addr, &vc(*ino), 8);
+    state.memory.write_value(addr + 8, &vc(*off), 8);
+    state.memory.write_value(addr + 16, &vc(reclen), 2);
+    let name_addr = if state.info.bin.os == "darwin" {
+        state.memory.write_value(addr + 18, &vc(name.len() as u64), 2);
+        state.memory.write_value(addr + 20, &vc(d_type), 1);
+        addr + 21
+    } else {
+        state.memory.write_value(addr + 18, &vc(d_type), 1);
+        addr + 19
+    };
+    state.memory_write_string(name_addr, name);
+    reclen
+}
+
+pub fn mkdir(state: &mut State, args: &[Value]) -> Value {
+    let path = read_path(state, &args[0]);
+    if state.filesystem.mkdir(&path) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn mkdirat(state: &mut State, args: &[Value]) -> Value {
+    let path = at_path(state, &args[0], &args[1]);
+    let path = path_value(state, &path);
+    mkdir(state, &[path])
+}
+
+pub fn rmdir(state: &mut State, args: &[Value]) -> Value {
+    let path = read_path(state, &args[0]);
+    if state.filesystem.rmdir(&path) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn unlink(state: &mut State, args: &[Value]) -> Value {
+    let path = read_path(state, &args[0]);
+    if state.filesystem.unlink(&path) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn unlinkat(state: &mut State, args: &[Value]) -> Value {
+    let path = at_path(state, &args[0], &args[1]);
+    let flags = state.solver.evalcon_to_u64(&args[2]).unwrap_or(0);
+    let removed = if flags & AT_REMOVEDIR != 0 {
+        state.filesystem.rmdir(&path)
+    } else {
+        state.filesystem.unlink(&path)
+    };
+
+    if removed {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn rename(state: &mut State, args: &[Value]) -> Value {
+    let old = read_path(state, &args[0]);
+    let new = read_path(state, &args[1]);
+    if state.filesystem.rename(&old, &new) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn renameat(state: &mut State, args: &[Value]) -> Value {
+    let old = at_path(state, &args[0], &args[1]);
+    let new = at_path(state, &args[2], &args[3]);
+    if state.filesystem.rename(&old, &new) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn chdir(state: &mut State, args: &[Value]) -> Value {
+    let path = read_path(state, &args[0]);
+    if state.filesystem.chdir(&path) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn fchdir(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let path = state.filesystem.getpath(fd as usize).unwrap_or_default();
+    if state.filesystem.chdir(&path) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn chmod(state: &mut State, args: &[Value]) -> Value {
+    let path = read_path(state, &args[0]);
+    let perm = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    if state.filesystem.chmod(&path, perm as u32) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn fchmod(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let path = state.filesystem.getpath(fd as usize).unwrap_or_default();
+    let perm = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    if state.filesystem.chmod(&path, perm as u32) {
+        vc(0)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn pipe(state: &mut State, args: &[Value]) -> Value {
+    let (read_fd, write_fd) = state.filesystem.pipe();
+    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    state.memory.write_value(addr, &vc(read_fd as u64), 4);
+    state.memory.write_value(addr + 4, &vc(write_fd as u64), 4);
+    vc(0)
+}
+
+pub fn dup(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    if let Some(new_fd) = state.filesystem.dup(fd as usize, None) {
+        vc(new_fd as u64)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn dup2(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let new_fd = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    if let Some(new_fd) = state.filesystem.dup(fd as usize, Some(new_fd as usize)) {
+        vc(new_fd as u64)
+    } else {
+        error(state, args)
+    }
+}
+
+pub fn fcntl(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let cmd = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    match cmd {
+        0 | 1030 => dup(state, args), // F_DUPFD, F_DUPFD_CLOEXEC
+        _ if state.filesystem.getpath(fd as usize).is_some() => vc(0),
+        _ => error(state, args),
+    }
+}
+
+pub fn ioctl(state: &mut State, args: &[Value]) -> Value {
+    let fd = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let request = state.solver.evalcon_to_u64(&args[1]).unwrap() & 0xffffffff;
+    let addr = state.solver.evalcon_to_u64(&args[2]).unwrap();
+
+    if fd > 2 {
+        return error(state, args);
+    }
+
+    match request {
+        TCGETS => {
+            // struct termios, default flags for a terminal
+            state.memory.write_value(addr, &vc(0x500), 4);
+            state.memory.write_value(addr + 4, &vc(0x5), 4);
+            state.memory.write_value(addr + 8, &vc(0xbf), 4);
+            state.memory.write_value(addr + 12, &vc(0x8a3b), 4);
+            for i in 0..5 {
+                state.memory.write_value(addr + 16 + 4 * i, &vc(0), 4);
+            }
+            vc(0)
+        }
+        TIOCGWINSZ => {
+            state.memory.write_value(addr, &vc(24), 2);
+            state.memory.write_value(addr + 2, &vc(80), 2);
+            state.memory.write_value(addr + 4, &vc(0), 4);
+            vc(0)
+        }
+        _ => error(state, args),
+    }
+}
+
+pub fn getcwd(state: &mut State, args: &[Value]) -> Value {
+    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let cwd = state.filesystem.cwd.clone();
+    state.memory_write_string(addr, &cwd);
+    state.memory.write_value(addr + cwd.len() as u64, &vc(0), 1);
     args[0].to_owned()
 }
+
+pub fn readlink(state: &mut State, args: &[Value]) -> Value {
+    let path = read_path(state, &args[0]);
+    if path != "/proc/self/exe" {
+        return error(state, args);
+    }
+
+    let file = state.info.core.file.clone();
+    let addr = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    let size = state.solver.evalcon_to_u64(&args[2]).unwrap();
+    let len = (file.len() as u64).min(size);
+    let data: Vec<Value> = file.bytes().take(len as usize).map(|b| vc(b as u64)).collect();
+    state.memory_write(&vc(addr), &data, &vc(len));
+    vc(len)
+}
+
+pub fn readlinkat(state: &mut State, args: &[Value]) -> Value {
+    readlink(state, &args[1..])
+}
+
+pub fn uname(state: &mut State, args: &[Value]) -> Value {
+    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let machine = match (state.info.bin.arch.as_str(), state.info.bin.bits) {
+        ("x86", 64) => "x86_64",
+        ("x86", _) => "i686",
+        ("arm", 64) => "aarch64",
+        ("arm", _) => "armv7l",
+        ("mips", 64) => "mips64",
+        ("riscv", _) => "riscv64",
+        (arch, _) => arch,
+    }
+    .to_owned();
+
+    let fields = [
+        "Linux",
+        "radius",
+        "5.15.0",
+        "#1 SMP",
+        machine.as_str(),
+        "(none)",
+    ];
+    for (i, field) in fields.iter().enumerate() {
+        let field_addr = addr + UTSNAME_LEN * i as u64;
+        state
+            .memory
+            .write_value(field_addr, &vc(0), UTSNAME_LEN as usize);
+        state.memory_write_string(field_addr, field);
+    }
+    vc(0)
+}
+
+pub fn getrandom(state: &mut State, args: &[Value]) -> Value {
+    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let len = state.solver.evalcon_to_u64(&args[1]).unwrap().min(MAX_LEN);
+    for i in 0..len {
+        let byte = nondet(state, "getrandom", 8);
+        state.memory.write_value(addr + i, &byte, 1);
+    }
+    vc(len)
+}
+
+pub fn time(state: &mut State, args: &[Value]) -> Value {
+    let ptr = state.memory.bits / 8;
+    let secs = nondet(state, "time", 8 * ptr as u32);
+    let addr = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    if addr != 0 {
+        state.memory.write_value(addr, &secs, ptr as usize);
+    }
+    secs
+}
+
+// write a {secs, subsecs} timespec or timeval
+fn write_time(state: &mut State, addr: &Value) {
+    let ptr = state.memory.bits / 8;
+    let addr = state.solver.evalcon_to_u64(addr).unwrap();
+    if addr != 0 {
+        let secs = nondet(state, "time", 8 * ptr as u32);
+        let subsecs = nondet(state, "subsecs", 8 * ptr as u32);
+        state.memory.write_value(addr, &secs, ptr as usize);
+        state.memory.write_value(addr + ptr, &subsecs, ptr as usize);
+    }
+}
+
+pub fn gettimeofday(state: &mut State, args: &[Value]) -> Value {
+    write_time(state, &args[0]);
+    vc(0)
+}
+
+pub fn clock_gettime(state: &mut State, args: &[Value]) -> Value {
+    write_time(state, &args[1]);
+    vc(0)
+}
+
+pub fn rt_sigaction(state: &mut State, args: &[Value]) -> Value {
+    // zero the old action, nothing is ever installed
+    let old = state.solver.evalcon_to_u64(&args[2]).unwrap();
+    if old != 0 {
+        let ptr = state.memory.bits / 8;
+        state.memory.write_value(old, &vc(0), 3 * ptr as usize);
+    }
+    vc(0)
+}
+
+pub fn rt_sigprocmask(state: &mut State, args: &[Value]) -> Value {
+    let old = state.solver.evalcon_to_u64(&args[2]).unwrap();
+    if old != 0 {
+        state.memory.write_value(old, &vc(0), 8);
+    }
+    vc(0)
+}
+
+pub fn arch_prctl(state: &mut State, args: &[Value]) -> Value {
+    let code = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    match code {
+        ARCH_SET_FS => {
+            state.registers.set_with_alias("fs", args[1].to_owned());
+            state
+                .context
+                .insert("fs".to_string(), vec![args[1].to_owned()]);
+            vc(0)
+        }
+        ARCH_GET_FS => {
+            let addr = state.solver.evalcon_to_u64(&args[1]).unwrap();
+            let fs = state
+                .context
+                .get("fs")
+                .and_then(|fs| fs.first().cloned())
+                .unwrap_or_else(|| vc(0));
+            state.memory.write_value(addr, &fs, 8);
+            vc(0)
+        }
+        _ => error(state, args),
+    }
+}
+
+pub fn getrlimit(state: &mut State, args: &[Value]) -> Value {
+    let resource = state.solver.evalcon_to_u64(&args[0]).unwrap();
+    let addr = state.solver.evalcon_to_u64(&args[1]).unwrap();
+    let ptr = state.memory.bits / 8;
+
+    let cur = if resource == RLIMIT_STACK {
+        vc(STACK_LIMIT)
+    } else {
+        Value::Concrete(-1i64 as u64, 0)
+    };
+    state.memory.write_value(addr, &cur, ptr as usize);
+    state
+        .memory
+        .write_value(addr + ptr, &Value::Concrete(-1i64 as u64, 0), ptr as usize);
+    vc(0)
+}
+
+pub fn prlimit64(state: &mut State, args: &[Value]) -> Value {
+    let old = state.solver.evalcon_to_u64(&args[3]).unwrap();
+    if old != 0 {
+        let resource = state.solver.evalcon_to_u64(&args[1]).unwrap();
+        let cur = if resource == RLIMIT_STACK {
+            vc(STACK_LIMIT)
+        } else {
+            Value::Concrete(-1i64 as u64, 0)
+        };
+        state.memory.write_value(old, &cur, 8);
+        state
+            .memory
+            .write_value(old + 8, &Value::Concrete(-1i64 as u64, 0), 8);
+    }
+    vc(0)
+}
+
+// struct stat64 from xnu, same for x86_64 and arm64
+fn write_darwin_stat(state: &mut State, statbuf: u64, statdata: &Stat) {
+    let fields = [
+        (0, statdata.st_dev, 4),
+        (4, statdata.st_mode as u64, 2),
+        (6, statdata.st_nlink as u64, 2),
+        (8, statdata.st_ino, 8),
+        (16, statdata.st_uid as u64, 4),
+        (20, statdata.st_gid as u64, 4),
+        (24, statdata.st_rdev, 4),
+        (32, statdata.st_atime, 8),
+        (40, statdata.st_atimensec, 8),
+        (48, statdata.st_mtime, 8),
+        (56, statdata.st_mtimensec, 8),
+        (64, statdata.st_ctime, 8),
+        (72, statdata.st_ctimensec, 8),
+        (80, statdata.st_ctime, 8),
+        (88, statdata.st_ctimensec, 8),
+        (96, statdata.st_size as u64, 8),
+        (104, statdata.st_blocks as u64, 8),
+        (112, statdata.st_blksize as u64, 4),
+    ];
+
+    state.memory.write_value(statbuf, &vc(0), 144);
+    for (offset, value, length) in fields {
+        state
+            .memory
+            .write_value(statbuf + offset, &vc(value), length);
+    }
+    if let Some(length) = &statdata.length {
+        state.memory.write_value(statbuf + 96, length, 8);
+    }
+}
+
+pub fn getentropy(state: &mut State, args: &[Value]) -> Value {
+    getrandom(state, args);
+    vc(0)
+}
@@ -1,20 +1,19 @@
 use crate::memory::{Memory, READ_CACHE};
 use crate::r2_api::{Endian, Information, R2Api};
 use crate::registers::Registers;
-use crate::sims::fs::SimFilesytem;
+use crate::sims::fs::{NodeType, SimFilesytem};
+use crate::sims::{SimCall, SimFrame, SimMethod};
 use crate::solver::{BitVec, Solver};
+use crate::trace::{Trace, TraceEntry};
 use crate::value::{byte_values, vc, Value};
 
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
 use std::u8;
 
-// event hooks could be a performance issue at some point
-// prolly not now cuz there are 10000 slower things
-// but also i hate the code for them and want to remove it
-pub const DO_EVENT_HOOKS: bool = false;
-
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub enum EventTrigger {
     Before, // call hook before event occurs
@@ -23,6 +22,8 @@ pub enum EventTrigger {
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub enum Event {
+    Read(EventTrigger),            // read from concrete address
+    Write(EventTrigger),           // write to concrete address
     SymbolicRead(EventTrigger),    // read from symbolic address
     SymbolicWrite(EventTrigger),   // write to symbolic address
     SymbolicExec(EventTrigger),    // execute symbolic address
@@ -38,9 +39,46 @@ pub enum Event {
     SymbolicStrlen(EventTrigger),  // strlen of symbolic address
     Move(EventTrigger),            // move bytes from src to dst (memcpy, memmove)
     SymbolicMove(EventTrigger),    // symbolic move (memcpy, memmove)
+    RegisterRead(EventTrigger),    // instruction reads a register (After only)
+    RegisterWrite(EventTrigger),   // instruction writes a register
+    Fork(EventTrigger),            // state splits on a branch
+    Terminate(EventTrigger),       // state stops running, exit crash or inactive (Before only)
+    Syscall(EventTrigger),         // syscall or trap
+    SimCall(EventTrigger),         // simulated function
     All(EventTrigger),             // gotta hook em all, ra! - di! - us!
 }
 
+impl Event {
+    pub fn trigger(&self) -> &EventTrigger {
+        match self {
+            Event::Read(t)
+            | Event::Write(t)
+            | Event::SymbolicRead(t)
+            | Event::SymbolicWrite(t)
+            | Event::SymbolicExec(t)
+            | Event::Alloc(t)
+            | Event::SymbolicAlloc(t)
+            | Event::Free(t)
+            | Event::SymbolicFree(t)
+            | Event::Search(t)
+            | Event::SymbolicSearch(t)
+            | Event::Compare(t)
+            | Event::SymbolicCompare(t)
+            | Event::StringLength(t)
+            | Event::SymbolicStrlen(t)
+            | Event::Move(t)
+            | Event::SymbolicMove(t)
+            | Event::RegisterRead(t)
+            | Event::RegisterWrite(t)
+            | Event::Fork(t)
+            | Event::Terminate(t)
+            | Event::Syscall(t)
+            | Event::SimCall(t)
+            | Event::All(t) => t,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum EventContext {
     ReadContext(Value, Value),
@@ -52,10 +90,24 @@ pub enum EventContext {
     CompareContext(Value, Value, Value),
     StrlenContext(Value, Value),
     MoveContext(Value, Value, Value),
+    RegisterContext(String, Value),
+    ForkContext(Value, Vec<u64>),
+    TerminateContext(StateStatus),
+    SyscallContext(Value, Vec<Value>),
+    SimContext(String, Vec<Value>),
 }
 
 pub type EventHook = dyn Fn(&mut State, &EventContext);
 
+/// An access to a watched memory range or register
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchAccess {
+    pub pc: u64,        // address of the accessing instruction or sim
+    pub perm: char,     // 'r' or 'w'
+    pub old: Value,     // watched value before the access
+    pub new: Value,     // watched value after, same as old for reads
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ExecMode {
     If,     // in a symbolic if clause ?{,...,}
@@ -115,12 +167,16 @@ pub struct State {
     pub memory: Memory,
     pub filesystem: SimFilesytem,
     pub status: StateStatus,
+    pub exit_code: Option<Value>,
     pub context: HashMap<String, Vec<Value>>,
     pub taints: HashMap<String, u64>,
-    pub hooks: HashMap<Event, Rc<EventHook>>,
+    pub hooks: HashMap<Event, Vec<Rc<EventHook>>>,
     pub visits: HashMap<u64, usize>,
     pub pid: u64,
     pub backtrace: Vec<(u64, u64)>,
+    pub sim_call: Option<SimCall>,
+    pub sim_frames: Vec<SimFrame>,
+    pub trace: Option<Trace>,
     pub blank: bool,
     pub debug: bool,
     pub check: bool,
@@ -187,11 +243,15 @@ impl State {
             memory,
             filesystem: SimFilesytem::new(),
             status: StateStatus::Active,
+            exit_code: None,
             context: HashMap::new(),
             taints: HashMap::new(),
             hooks: HashMap::new(),
             visits: HashMap::with_capacity(512),
             backtrace: Vec::with_capacity(128),
+            sim_call: None,
+            sim_frames: vec![],
+            trace: None,
             pid: 1337, // sup3rh4x0r
             blank,
             debug,
@@ -238,6 +298,21 @@ impl State {
         for f in &mut filesystem.files {
             let content = f.content.clone();
             f.content = content.iter().map(|v| solver.translate_value(v)).collect();
+            f.length = f.length.as_ref().map(|l| solver.translate_value(l));
+        }
+        for node in filesystem.nodes.values_mut() {
+            let content = node.content.clone();
+            node.content = content.iter().map(|v| solver.translate_value(v)).collect();
+            node.length = node.length.as_ref().map(|l| solver.translate_value(l));
+        }
+
+        let exit_code = self.exit_code.as_ref().map(|c| solver.translate_value(c));
+
+        let mut sim_frames = self.sim_frames.clone();
+        for frame in &mut sim_frames {
+            frame.entry_sp = solver.translate_value(&frame.entry_sp);
+            frame.link = frame.link.as_ref().map(|l| solver.translate_value(l));
+            frame.data = frame.data.iter().map(|v| solver.translate_value(v)).collect();
         }
 
         let esil_state = EsilState {
@@ -263,11 +338,15 @@ impl State {
             memory,
             filesystem,
             status: self.status.clone(),
+            exit_code,
             context,
             taints: self.taints.clone(),
             hooks: self.hooks.clone(),
             visits: self.visits.clone(),
             backtrace: self.backtrace.clone(),
+            sim_call: None,
+            sim_frames,
+            trace: self.trace.clone(),
             pid: self.pid,
             blank: self.blank,
             debug: self.debug,
@@ -277,22 +356,33 @@ impl State {
         }
     }
 
+    /// Add an entry to the trace if this state is being traced
+    #[inline]
+    pub fn record(&mut self, entry: TraceEntry) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(entry);
+        }
+    }
+
+    /// Call `hook` whenever `event` occurs, `Event::All` hooks every event
     pub fn hook_event(&mut self, event: Event, hook: Rc<EventHook>) {
         self.has_event_hooks = true;
-        self.hooks.insert(event, hook);
+        self.hooks.entry(event).or_default().push(hook);
     }
 
     pub fn do_hooked(&mut self, event: &Event, event_context: &EventContext) {
-        if !self.hooks.contains_key(event) {
-            return;
+        let mut hooks = self.hooks.get(event).cloned().unwrap_or_default();
+        if let Some(all) = self.hooks.get(&Event::All(event.trigger().to_owned())) {
+            hooks.extend(all.iter().cloned());
+        }
+        for hook in hooks {
+            hook(self, event_context)
         }
-        let hook = self.hooks.get(event).unwrap().clone();
-        hook(self, event_context)
     }
 
     /// Allocate a block of memory `length` bytes in size
     pub fn memory_alloc(&mut self, length: &Value) -> Value {
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if length.is_symbolic() {
                 Event::SymbolicAlloc(EventTrigger::Before)
             } else {
@@ -303,7 +393,7 @@ impl State {
 
         let ret = self.memory.alloc_sym(length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if length.is_symbolic() {
                 Event::SymbolicAlloc(EventTrigger::After)
             } else {
@@ -317,7 +407,7 @@ impl State {
 
     /// Free a block of memory at `addr`
     pub fn memory_free(&mut self, addr: &Value) -> Value {
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if addr.is_symbolic() {
                 Event::SymbolicFree(EventTrigger::Before)
             } else {
@@ -332,7 +422,7 @@ impl State {
 
         let ret = self.memory.free_sym(addr, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if addr.is_symbolic() {
                 Event::SymbolicFree(EventTrigger::After)
             } else {
@@ -346,10 +436,14 @@ impl State {
 
     /// Read `length` bytes from `address`
     pub fn memory_read(&mut self, address: &Value, length: &Value) -> Vec<Value> {
-        if DO_EVENT_HOOKS && self.has_event_hooks && (address.is_symbolic() || length.is_symbolic())
-        {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() || length.is_symbolic() {
+                Event::SymbolicRead(EventTrigger::Before)
+            } else {
+                Event::Read(EventTrigger::Before)
+            };
             self.do_hooked(
-                &Event::SymbolicRead(EventTrigger::Before),
+                &event,
                 &EventContext::ReadContext(address.to_owned(), length.to_owned()),
             );
         }
@@ -360,10 +454,14 @@ impl State {
 
         let ret = self.memory.read_sym_len(address, length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks && (address.is_symbolic() || length.is_symbolic())
-        {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() || length.is_symbolic() {
+                Event::SymbolicRead(EventTrigger::After)
+            } else {
+                Event::Read(EventTrigger::After)
+            };
             self.do_hooked(
-                &Event::SymbolicRead(EventTrigger::After),
+                &event,
                 &EventContext::ReadContext(address.to_owned(), length.to_owned()),
             );
         }
@@ -373,10 +471,14 @@ impl State {
 
     /// Write `length` bytes to `address`
     pub fn memory_write(&mut self, address: &Value, values: &[Value], length: &Value) {
-        if DO_EVENT_HOOKS && self.has_event_hooks && (address.is_symbolic() || length.is_symbolic())
-        {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() || length.is_symbolic() {
+                Event::SymbolicWrite(EventTrigger::Before)
+            } else {
+                Event::Write(EventTrigger::Before)
+            };
             self.do_hooked(
-                &Event::SymbolicWrite(EventTrigger::Before),
+                &event,
                 &EventContext::WriteContext(address.to_owned(), length.to_owned()),
             );
         }
@@ -388,10 +490,14 @@ impl State {
             .memory
             .write_sym_len(address, values, length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks && (address.is_symbolic() || length.is_symbolic())
-        {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() || length.is_symbolic() {
+                Event::SymbolicWrite(EventTrigger::After)
+            } else {
+                Event::Write(EventTrigger::After)
+            };
             self.do_hooked(
-                &Event::SymbolicWrite(EventTrigger::After),
+                &event,
                 &EventContext::WriteContext(address.to_owned(), length.to_owned()),
             );
         }
@@ -402,9 +508,14 @@ impl State {
     /// Read `length` byte `value` from `address`
     #[inline]
     pub fn memory_read_value(&mut self, address: &Value, length: usize) -> Value {
-        if DO_EVENT_HOOKS && self.has_event_hooks && address.is_symbolic() {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() {
+                Event::SymbolicRead(EventTrigger::Before)
+            } else {
+                Event::Read(EventTrigger::Before)
+            };
             self.do_hooked(
-                &Event::SymbolicRead(EventTrigger::Before),
+                &event,
                 &EventContext::ReadContext(address.to_owned(), Value::Concrete(length as u64, 0)),
             );
         }
@@ -415,9 +526,14 @@ impl State {
 
         let ret = self.memory.read_sym(address, length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks && address.is_symbolic() {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() {
+                Event::SymbolicRead(EventTrigger::After)
+            } else {
+                Event::Read(EventTrigger::After)
+            };
             self.do_hooked(
-                &Event::SymbolicRead(EventTrigger::After),
+                &event,
                 &EventContext::ReadContext(address.to_owned(), Value::Concrete(length as u64, 0)),
             );
         }
@@ -428,10 +544,15 @@ impl State {
     /// Write `length` byte `value` to `address`
     #[inline]
     pub fn memory_write_value(&mut self, address: &Value, value: &Value, length: usize) {
-        if DO_EVENT_HOOKS && self.has_event_hooks && address.is_symbolic() {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() {
+                Event::SymbolicWrite(EventTrigger::Before)
+            } else {
+                Event::Write(EventTrigger::Before)
+            };
             self.do_hooked(
-                &Event::SymbolicRead(EventTrigger::Before),
-                &EventContext::ReadContext(address.to_owned(), Value::Concrete(length as u64, 0)),
+                &event,
+                &EventContext::WriteContext(address.to_owned(), Value::Concrete(length as u64, 0)),
             );
         }
 
@@ -443,9 +564,14 @@ impl State {
             .memory
             .write_sym(address, value, length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks && address.is_symbolic() {
+        if self.has_event_hooks {
+            let event = if address.is_symbolic() {
+                Event::SymbolicWrite(EventTrigger::After)
+            } else {
+                Event::Write(EventTrigger::After)
+            };
             self.do_hooked(
-                &Event::SymbolicWrite(EventTrigger::After),
+                &event,
                 &EventContext::WriteContext(address.to_owned(), Value::Concrete(length as u64, 0)),
             );
         }
@@ -462,7 +588,7 @@ impl State {
         length: &Value,
         reverse: bool,
     ) -> Value {
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if addr.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicSearch(EventTrigger::Before)
             } else {
@@ -482,7 +608,7 @@ impl State {
             .memory
             .search(addr, needle, length, reverse, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if addr.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicSearch(EventTrigger::After)
             } else {
@@ -500,7 +626,7 @@ impl State {
     /// Compare memory at `dst` and `src` address up to `length` bytes.
     /// This is akin to memcmp but will handle symbolic addrs and length
     pub fn memory_compare(&mut self, dst: &Value, src: &Value, length: &Value) -> Value {
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if dst.is_symbolic() || src.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicCompare(EventTrigger::Before)
             } else {
@@ -519,7 +645,7 @@ impl State {
 
         let ret = self.memory.compare(dst, src, length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if dst.is_symbolic() || src.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicCompare(EventTrigger::After)
             } else {
@@ -536,7 +662,7 @@ impl State {
 
     /// Get the length of the null terminated string at `addr`
     pub fn memory_strlen(&mut self, addr: &Value, length: &Value) -> Value {
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if addr.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicStrlen(EventTrigger::Before)
             } else {
@@ -555,7 +681,7 @@ impl State {
 
         let ret = self.memory.strlen(addr, length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if addr.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicStrlen(EventTrigger::After)
             } else {
@@ -572,7 +698,7 @@ impl State {
 
     /// Move `length` bytes from `src` to `dst`
     pub fn memory_move(&mut self, dst: &Value, src: &Value, length: &Value) {
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if dst.is_symbolic() || src.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicMove(EventTrigger::Before)
             } else {
@@ -591,7 +717,7 @@ impl State {
 
         self.memory.memmove(dst, src, length, &mut self.solver);
 
-        if DO_EVENT_HOOKS && self.has_event_hooks {
+        if self.has_event_hooks {
             let event = if dst.is_symbolic() || src.is_symbolic() || length.is_symbolic() {
                 Event::SymbolicMove(EventTrigger::After)
             } else {
@@ -652,6 +778,13 @@ impl State {
         values
     }
 
+    /// Set up the stdin, stdout and stderr FILE streams
+    pub fn add_std_streams(&mut self) {
+        let streams = self.memory.add_std_streams();
+        let streams = streams.iter().map(|s| vc(*s)).collect();
+        self.context.insert("std_streams".to_owned(), streams);
+    }
+
     pub fn fill_file(&mut self, fd: usize, data: &[Value]) {
         self.filesystem.fill(fd, data)
     }
@@ -662,11 +795,7 @@ impl State {
     }
 
     pub fn dump_path(&mut self, path: &str) -> Vec<Value> {
-        if let Some(fd) = self.filesystem.getfd(path) {
-            self.filesystem.dump(fd)
-        } else {
-            vec![]
-        }
+        self.filesystem.dump_path(path).unwrap_or_default()
     }
 
     pub fn dump_file(&mut self, fd: usize) -> Vec<Value> {
@@ -675,12 +804,52 @@ impl State {
 
     pub fn dump_file_bytes(&mut self, fd: usize) -> Vec<u8> {
         let values = self.filesystem.dump(fd);
-        let bytes = values
+        let length = self.filesystem.files[fd].length.clone();
+        self.evaluate_content(&values, &length)
+    }
+
+    // evaluate file contents, only up to the length if it is symbolic
+    fn evaluate_content(&mut self, values: &[Value], length: &Option<Value>) -> Vec<u8> {
+        let len = length
+            .as_ref()
+            .and_then(|l| self.solver.evalcon_to_u64(l))
+            .map(|l| (l as usize).min(values.len()))
+            .unwrap_or(values.len());
+
+        values[..len]
             .iter()
             .map(|v| self.solver.evalcon_to_u64(v).unwrap_or(0) as u8)
-            .collect();
+            .collect()
+    }
+
+    /// Create a symbolic length `name` for an input of at most `max` bytes
+    pub fn symbolic_length(&mut self, name: &str, max: usize) -> Value {
+        let length = self.symbolic_value(name, 64);
+        self.assert(&length.ulte(&vc(max as u64)));
+        self.context.insert(name.to_owned(), vec![length.clone()]);
+        length
+    }
+
+    /// Give the open file `fd` a symbolic length, reads past it return EOF.
+    /// The length is bounded by the current size of the file
+    pub fn set_file_length(&mut self, fd: usize, name: &str) -> Value {
+        let max = self.filesystem.files[fd].content.len();
+        let length = self.symbolic_length(name, max);
+        self.filesystem.set_length(fd, length.clone());
+        length
+    }
 
-        bytes
+    /// Give the file at `path` a symbolic length bounded by its size
+    pub fn set_path_length(&mut self, path: &str, name: &str) -> Option<Value> {
+        let max = self.filesystem.dump_path(path)?.len();
+        let length = self.symbolic_length(name, max);
+        self.filesystem.set_path_length(path, length.clone());
+        Some(length)
+    }
+
+    /// Get the minimum possible value of `value`, the state is not constrained
+    pub fn minimize(&self, value: &Value) -> u64 {
+        self.solver.min_value(value)
     }
 
     pub fn dump_file_string(&mut self, fd: usize) -> Option<String> {
@@ -712,7 +881,29 @@ impl State {
             self.r2api.write(addr, bytes.clone());
         }
 
-        // TODO: evaluate files and write to real FS? maybe a bad idea
+        // files are written with export_filesystem instead
+    }
+
+    /// Evaluate every file created or modified in the simulated filesystem
+    /// and write them to the host directory `dir`. Returns the number of
+    /// files written
+    pub fn export_filesystem(&mut self, dir: &str) -> std::io::Result<usize> {
+        let mut count = 0;
+        for (path, node) in self.filesystem.modified() {
+            let host = Path::new(dir).join(path.trim_start_matches('/'));
+            if node.node_type == NodeType::Directory {
+                fs::create_dir_all(&host)?;
+                continue;
+            }
+
+            if let Some(parent) = host.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let bytes = self.evaluate_content(&node.content, &node.length);
+            fs::write(&host, bytes)?;
+            count += 1;
+        }
+        Ok(count)
     }
 
     /// Merges `state` into self
@@ -781,6 +972,12 @@ impl State {
         for file in &state.filesystem.files {
             for cfile in &mut self.filesystem.files {
                 if file.path == cfile.path {
+                    cfile.length = merge_length(
+                        state,
+                        &asserted,
+                        (&file.length, file.content.len()),
+                        (&cfile.length, cfile.content.len()),
+                    );
                     let mlen = if file.content.len() > cfile.content.len() { 
                         file.content.len()
                     } else {
@@ -800,6 +997,33 @@ impl State {
             }
         }
 
+        // merge the file tree, nodes only in the other state are added
+        for (path, node) in &state.filesystem.nodes {
+            if let Some(cnode) = self.filesystem.nodes.get_mut(path) {
+                cnode.modified |= node.modified;
+                cnode.length = merge_length(
+                    state,
+                    &asserted,
+                    (&node.length, node.content.len()),
+                    (&cnode.length, cnode.content.len()),
+                );
+                let mlen = node.content.len().max(cnode.content.len());
+                for i in 0..mlen {
+                    let space = vc(0x20);
+                    let v = node.content.get(i).unwrap_or(&space);
+                    let cv = cnode.content.get(i).unwrap_or(&space);
+                    let merged = state.cond(&asserted, v, cv);
+                    if i < cnode.content.len() {
+                        cnode.content[i] = merged;
+                    } else {
+                        cnode.content.push(merged);
+                    }
+                }
+            } else {
+                self.filesystem.nodes.insert(path.to_owned(), node.to_owned());
+            }
+        }
+
         // merge solvers
         let assertions = &self.solver.assertions;
         let current = state.solver.and_all(assertions);
@@ -950,26 +1174,31 @@ impl State {
 
     /// Constrain bytes of file at `path` with pattern
     pub fn constrain_file(&mut self, path: &str, content: &str) {
-        if let Some(fd) = self.filesystem.getfd(path) {
-            self.constrain_fd(fd, content);
+        if let Some(fbytes) = self.filesystem.dump_path(path) {
+            let fbv = self.pack(&fbytes);
+            self.constrain_bytes(&fbv, content);
         }
     }
 
     // search for string in file
     pub fn search_file(&mut self, path: &str, content: &str) -> Value {
-        if let Some(fd) = self.filesystem.getfd(path) {
-            self.search_fd(fd, content)
+        if let Some(data) = self.filesystem.dump_path(path) {
+            self.search_data(&data, content)
         } else {
             vc(-1i64 as u64)
         }
     }
 
-    // TODO this is hacky as fuck, make it better
     pub fn search_fd(&mut self, fd: usize, content: &str) -> Value {
         let data = self.dump_file(fd);
+        self.search_data(&data, content)
+    }
+
+    // TODO this is hacky as fuck, make it better
+    fn search_data(&mut self, data: &[Value], content: &str) -> Value {
         let length = vc(data.len() as u64);
         let addr = self.memory_alloc(&length);
-        self.memory_write(&addr, &data, &length);
+        self.memory_write(&addr, data, &length);
         let needle = self.pack(&byte_values(content));
         let result = self.memory_search(&addr, &needle, &length, false);
         self.memory_free(&addr);
@@ -1020,6 +1249,11 @@ impl State {
         self.status.clone()
     }
 
+    /// Get the exit code if the state has exited
+    pub fn exit_code(&self) -> Option<Value> {
+        self.exit_code.clone()
+    }
+
     /// Convenience method to mark state inactive
     pub fn set_inactive(&mut self) {
         self.set_status(StateStatus::Inactive);
@@ -1119,6 +1353,18 @@ impl State {
         }
     }
 
+    /// Call the guest function `func` with `args` when the current sim
+    /// returns. Once `func` returns to the sim, `resume` is called with
+    /// `data` and the return value, and may call another function
+    pub fn call_guest(&mut self, func: &Value, args: &[Value], resume: SimMethod, data: Vec<Value>) {
+        self.sim_call = Some(SimCall {
+            func: func.to_owned(),
+            args: args.to_owned(),
+            resume,
+            data,
+        });
+    }
+
     /// Assert the truth of the given bitvector (value != 0)
     pub fn assert_bv(&mut self, bv: &BitVec) {
         self.solver.assert_bv(bv)
@@ -1187,3 +1433,18 @@ impl State {
         self.evaluate_string_bv(value.as_bv().as_ref().unwrap())
     }
 }
+
+// merge the lengths of two versions of a file, only needed if one is symbolic
+fn merge_length(
+    state: &State,
+    asserted: &Value,
+    (length, len): (&Option<Value>, usize),
+    (clength, clen): (&Option<Value>, usize),
+) -> Option<Value> {
+    if length.is_none() && clength.is_none() {
+        return None;
+    }
+    let length = length.to_owned().unwrap_or_else(|| vc(len as u64));
+    let clength = clength.to_owned().unwrap_or_else(|| vc(clen as u64));
+    Some(state.cond(asserted, &length, &clength))
+}
@@ -375,3 +375,1193 @@ fn ioscrackme() {
     println!("FLAG: {}", flag.unwrap());
     radius.r2api.close();
 }
+
+#[test]
+fn dalvik_invoke() {
+    use crate::sims::dalvik::Invoke;
+
+    let invoke =
+        Invoke::parse("invoke-virtual {v1, v2}, Ljava/lang/String;->equals(Ljava/lang/Object;)Z")
+            .unwrap();
+    assert_eq!(invoke.key(), "Ljava/lang/String;->equals(Ljava/lang/Object;)");
+    assert_eq!(invoke.registers, vec!["v1", "v2"]);
+    assert_eq!(invoke.ret, "Z");
+
+    let invoke = Invoke::parse("invoke-static/range {v0 .. v2}, Lcom/a/B;->c(J[I)V").unwrap();
+    assert_eq!(invoke.params, vec!["J", "[I"]);
+    assert_eq!(invoke.registers, vec!["v0", "v1", "v2"]);
+    assert!(invoke.is_static());
+}
+
+#[test]
+fn dalvik_collections() {
+    use crate::radius::Radius;
+    use crate::sims::dalvik;
+    use crate::value::vc;
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    // equal strings at different addresses are the same key
+    let key = state.memory_alloc(&vc(8));
+    let other = state.memory_alloc(&vc(8));
+    let missing = state.memory_alloc(&vc(8));
+    state.memory_write_string(key.as_u64().unwrap(), "key");
+    state.memory_write_string(other.as_u64().unwrap(), "key");
+    state.memory_write_string(missing.as_u64().unwrap(), "nope");
+
+    let map = dalvik::collection_init(&mut state, &[]);
+    dalvik::map_put(&mut state, &[map.clone(), key.clone(), vc(7)]);
+    let value = dalvik::map_get(&mut state, &[map.clone(), other.clone()]);
+    assert_eq!(state.solver.evalcon_to_u64(&value), Some(7));
+    let found = dalvik::map_contains_key(&mut state, &[map.clone(), other.clone()]);
+    assert_eq!(state.solver.evalcon_to_u64(&found), Some(1));
+    let found = dalvik::map_contains_key(&mut state, &[map.clone(), missing.clone()]);
+    assert_eq!(state.solver.evalcon_to_u64(&found), Some(0));
+
+    let previous = dalvik::map_put(&mut state, &[map.clone(), other.clone(), vc(9)]);
+    assert_eq!(state.solver.evalcon_to_u64(&previous), Some(7));
+    assert_eq!(dalvik::map_size(&mut state, std::slice::from_ref(&map)), vc(1));
+
+    // boxed integers are compared by value
+    dalvik::map_put(&mut state, &[map.clone(), vc(5), vc(1)]);
+    let value = dalvik::map_get(&mut state, &[map.clone(), vc(5)]);
+    assert_eq!(state.solver.evalcon_to_u64(&value), Some(1));
+    let value = dalvik::map_get(&mut state, &[map, vc(6)]);
+    assert_eq!(state.solver.evalcon_to_u64(&value), Some(0));
+
+    // other objects are compared by reference
+    let list = dalvik::collection_init(&mut state, &[]);
+    let empty = dalvik::collection_init(&mut state, &[]);
+    let object = dalvik::collection_init(&mut state, &[]);
+    dalvik::list_add(&mut state, &[list.clone(), key]);
+    dalvik::list_add(&mut state, &[list.clone(), empty]);
+    let found = dalvik::list_contains(&mut state, &[list.clone(), other]);
+    assert_eq!(state.solver.evalcon_to_u64(&found), Some(1));
+    let found = dalvik::list_contains(&mut state, &[list.clone(), missing]);
+    assert_eq!(state.solver.evalcon_to_u64(&found), Some(0));
+    let found = dalvik::list_contains(&mut state, &[list, object]);
+    assert_eq!(state.solver.evalcon_to_u64(&found), Some(0));
+}
+
+#[test]
+fn linux_syscall_tables() {
+    use crate::sims::syscall::{get_linux_syscalls, get_syscall_table};
+
+    let name = |arch, bits, num| {
+        get_linux_syscalls(arch, bits)
+            .iter()
+            .find(|(n, _)| *n == num)
+            .map(|(_, name)| *name)
+    };
+
+    assert_eq!(name("x86", 64, 318), Some("getrandom"));
+    assert_eq!(name("x86", 32, 252), Some("exit_group"));
+    assert_eq!(name("arm", 64, 160), Some("uname"));
+    assert_eq!(name("arm", 32, 384), Some("getrandom"));
+    assert_eq!(name("mips", 32, 1), None);
+
+    let darwin = get_syscall_table("darwin", "arm", 64);
+    assert!(darwin.contains(&(4, "write")));
+    assert!(darwin.contains(&(500, "getentropy")));
+}
+
+#[test]
+fn fd_syscalls() {
+    use crate::radius::Radius;
+    use crate::sims::syscall;
+    use crate::value::{byte_values, vc};
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    state.filesystem.add_file("/tmp/fd.txt", &byte_values("contents"));
+    let path = state.memory_alloc(&vc(16));
+    state.memory_write_string(path.as_u64().unwrap(), "/tmp/fd.txt");
+    let fd = syscall::open(&mut state, &[path, vc(0), vc(0)]);
+
+    // dup2 can target any fd, not just the end of the table
+    assert_eq!(syscall::dup2(&mut state, &[fd.clone(), vc(100)]), vc(100));
+    let buf = state.memory_alloc(&vc(16));
+    let len = syscall::read(&mut state, &[vc(100), buf.clone(), vc(16)]);
+    assert_eq!(len, vc(8));
+    assert_eq!(state.memory_read_cstring(buf.as_u64().unwrap()), "contents");
+    let next = vc(fd.as_u64().unwrap() + 1);
+    assert_eq!(syscall::dup(&mut state, std::slice::from_ref(&fd)), next);
+
+    // writes to a duplicated pipe end go to the read end
+    let fds = state.memory_alloc(&vc(8));
+    syscall::pipe(&mut state, std::slice::from_ref(&fds));
+    let read_fd = state.memory_read_value(&fds, 4);
+    let write_fd = state.memory_read_value(&fds.add(&vc(4)), 4);
+    assert_eq!(read_fd, vc(fd.as_u64().unwrap() + 2));
+    assert_eq!(write_fd, vc(fd.as_u64().unwrap() + 3));
+    assert_eq!(syscall::dup2(&mut state, &[write_fd.clone(), vc(50)]), vc(50));
+    let data = state.memory_alloc(&vc(8));
+    state.memory_write_string(data.as_u64().unwrap(), "piped");
+    syscall::write(&mut state, &[vc(50), data.clone(), vc(5)]);
+    let len = syscall::read(&mut state, &[read_fd, buf.clone(), vc(5)]);
+    assert_eq!(len, vc(5));
+
+    // closed fds can not be written
+    syscall::close(&mut state, std::slice::from_ref(&write_fd));
+    let len = syscall::write(&mut state, &[write_fd, data, vc(5)]);
+    assert_eq!(len, vc(-1i64 as u64));
+
+    // errors are -1 like every other syscall sim
+    assert_eq!(syscall::dup2(&mut state, &[vc(99), vc(3)]), vc(-1i64 as u64));
+    assert_eq!(syscall::dup2(&mut state, &[fd.clone(), vc(4096)]), vc(-1i64 as u64));
+    assert_eq!(syscall::ioctl(&mut state, &[fd, vc(0x5401), buf]), vc(-1i64 as u64));
+}
+
+#[test]
+fn simfs_tree() {
+    use crate::sims::fs::{FileMode, MountMode, NodeType, SimFilesytem};
+    use crate::value::byte_values;
+
+    let mut fs = SimFilesytem::new();
+    fs.add_file("/home/user/a.txt", &byte_values("hello"));
+    assert!(fs.mkdir("/home/user/dir"));
+    assert!(!fs.mkdir("/nope/dir"));
+
+    let entries = fs.list("/home/user").unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.contains(&("dir".to_owned(), NodeType::Directory)));
+
+    // writes are visible to later opens
+    let fd = fs.open("/home/user/a.txt", FileMode::Append).unwrap();
+    fs.write(fd, byte_values("!"));
+    fs.close(fd);
+    assert!(fs.chdir("/home/user"));
+    let fd = fs.open("a.txt", FileMode::Read).unwrap();
+    assert_eq!(fs.read(fd, 16).len(), 6);
+
+    assert!(fs.rename("a.txt", "dir/b.txt"));
+    assert!(fs.open("a.txt", FileMode::Read).is_none());
+    assert!(!fs.rmdir("dir"));
+
+    // directories are moved with everything in them
+    assert!(fs.mkdir("dir/sub"));
+    fs.add_file("/home/user/dir/sub/c.txt", &byte_values("c"));
+    assert!(!fs.rename("dir", "dir/sub/dir"));
+    assert!(fs.rename("dir", "moved"));
+    assert!(fs.list("dir").is_none());
+    assert_eq!(fs.list("moved").unwrap().len(), 2);
+    let fd = fs.open("moved/sub/c.txt", FileMode::Read).unwrap();
+    assert_eq!(fs.read(fd, 16).len(), 1);
+    fs.close(fd);
+    assert!(fs.unlink("moved/sub/c.txt"));
+    assert!(fs.rmdir("moved/sub"));
+    assert!(fs.unlink("moved/b.txt"));
+    assert!(fs.rmdir("moved"));
+
+    // host files are only visible through mounts
+    let host = std::env::temp_dir().join("radius_simfs_tree");
+    std::fs::create_dir_all(&host).unwrap();
+    std::fs::write(host.join("c.txt"), "mounted").unwrap();
+    let host_file = host.join("c.txt").to_string_lossy().to_string();
+    assert!(fs.open(&host_file, FileMode::Read).is_none());
+
+    fs.mount(&host.to_string_lossy(), "/mnt", MountMode::ReadOnly);
+    assert!(fs.open("/mnt/c.txt", FileMode::Write).is_none());
+    let fd = fs.open("/mnt/c.txt", FileMode::Read).unwrap();
+    assert_eq!(fs.read(fd, 16).len(), 7);
+    assert!(!fs.unlink("/mnt/c.txt"));
+
+    // only files changed by the program are exported
+    let fd = fs.create("/tmp/out.txt", FileMode::Write).unwrap();
+    fs.write(fd, byte_values("out"));
+    let modified = fs.modified();
+    assert_eq!(modified.len(), 1);
+    assert_eq!(modified[0].0, "/tmp/out.txt");
+    assert_eq!(modified[0].1.content.len(), 3);
+}
+
+#[test]
+fn entry_auxv() {
+    use crate::radius::Radius;
+    use crate::state::State;
+    use crate::value::{byte_values, vc, Value};
+
+    let mut radius = Radius::new("../tests/hello");
+    let mut state = radius.entry_state();
+    let auxv = |state: &mut State| {
+        let mut current = state.context["auxv"][0].clone();
+        let mut entries = std::collections::HashMap::new();
+        loop {
+            let key = state.memory_read_value(&current, 8).as_u64().unwrap();
+            let value = state.memory_read_value(&current.add(&vc(8)), 8);
+            if key == 0 {
+                return entries;
+            }
+            entries.insert(key, value.as_u64().unwrap());
+            current = current.add(&vc(16));
+        }
+    };
+
+    // the program headers are found through e_phoff
+    let entries = auxv(&mut state);
+    let phoff = state.memory_read_value(&vc(0x20), 8).as_u64().unwrap();
+    assert_eq!(entries[&3], phoff);
+    assert_eq!(entries[&4], 56);
+    assert!(entries[&5] > 0);
+    let phdr = state.memory_read_value(&vc(entries[&3]), 4);
+    assert_eq!(phdr, vc(6)); // PT_PHDR comes first
+
+    // setting argv again doesn't make another AT_RANDOM
+    let arg: Value = state.memory.pack(&byte_values("hello"));
+    radius.set_argv_env(&mut state, &[arg.clone(), arg], &[]);
+    let again = auxv(&mut state);
+    assert_eq!(again[&25], entries[&25]);
+
+    // the random bytes are concrete unless they are set to a symbol
+    let random = vc(entries[&25]);
+    assert!(state.memory_read_value(&random, 8).as_u64().is_some());
+    let sym = state.symbolic_value("AT_RANDOM", 128);
+    assert!(radius.set_random(&mut state, &sym));
+    assert!(state.memory_read_value(&random, 8).is_symbolic());
+    let sp = state.registers.get_with_alias("SP");
+    assert_eq!(state.memory_read_value(&sp, 8), vc(2));
+}
+
+#[test]
+fn start_main() {
+    use crate::radius::Radius;
+    use crate::sims::libc;
+    use crate::value::{byte_values, vc};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut radius = Radius::new("../tests/strstuff");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.entry_state();
+
+    // record the order the init_array functions and main are called in
+    let segments = radius.r2api.get_segments().unwrap();
+    let init_array = segments.iter().find(|s| s.name.ends_with(".init_array")).unwrap();
+    let mut expected = vec![];
+    for i in 0..init_array.vsize / 8 {
+        let func = state.memory_read_value(&vc(init_array.vaddr + 8 * i), 8);
+        expected.push(func.as_u64().unwrap());
+    }
+    expected.push(main);
+
+    let name = state.memory.pack(&byte_values("strstuff"));
+    let arg = state.memory.pack(&byte_values("p4s5w0rd"));
+    let env = state.memory.pack(&byte_values("A=B"));
+    radius.set_argv_env(&mut state, &[name, arg], &[env]);
+    let envp = state.context["env"][0].clone();
+
+    // an atexit handler runs after main returns
+    let handler = expected[0];
+    libc::atexit(&mut state, &[vc(handler)]);
+
+    let called = Rc::new(RefCell::new(vec![]));
+    for func in &expected {
+        let called = called.clone();
+        radius.hook(*func, move |state| {
+            called.borrow_mut().push(state.registers.get_pc().as_u64().unwrap());
+            true
+        });
+    }
+
+    // main gets the envp that was set up
+    let main_envp = Rc::new(RefCell::new(None));
+    let envp_arg = main_envp.clone();
+    radius.hook(main, move |state| {
+        *envp_arg.borrow_mut() = Some(state.registers.get("rdx"));
+        true
+    });
+
+    // main's return value is passed to exit
+    radius.break_on_exit(None);
+    let exited = radius.run(state, 1).unwrap();
+    expected.push(handler);
+    assert_eq!(*called.borrow(), expected);
+    assert!(exited.sim_call.is_none() && exited.sim_frames.is_empty());
+    assert_eq!(*main_envp.borrow(), Some(envp));
+    assert_eq!(exited.exit_code().map(|c| c.slice(7, 0)), Some(vc(0)));
+}
+
+#[test]
+fn break_exit_code() {
+    use crate::radius::Radius;
+    use crate::value::byte_values;
+
+    // strstuff returns 0 for the right password and 1 otherwise
+    for code in 0..2 {
+        let mut radius = Radius::new("../tests/strstuff");
+        let mut state = radius.entry_state();
+        let name = state.memory.pack(&byte_values("strstuff"));
+        let arg = state.symbolic_value("arg", 64);
+        radius.set_argv_env(&mut state, &[name, arg.clone()], &[]);
+
+        radius.break_on_exit(Some(code));
+        let mut exited = radius.run(state, 1).unwrap();
+        let password = exited.evaluate_string(&arg).unwrap_or_default();
+        assert_eq!(password == "p4s5w0rd", code == 0);
+    }
+}
+
+#[test]
+fn static_sims() {
+    use crate::radius::Radius;
+
+    // static elfs have no interpreter and no needed libraries
+    let mut radius = Radius::new("../tests/statically-linked");
+    assert!(radius.r2api.is_static());
+
+    // dynamic binaries only get sims on their imports, not local symbols
+    let mut radius = Radius::new("../tests/hello");
+    assert!(!radius.r2api.is_static());
+    let imports: Vec<u64> = radius.r2api.get_imports().unwrap().iter().map(|i| i.plt).collect();
+    let puts = radius.r2api.get_address("sym.imp.puts").unwrap();
+    assert!(radius.processor.sims.contains_key(&puts));
+    assert!(radius.processor.sims.keys().all(|addr| imports.contains(addr)));
+}
+
+#[test]
+fn symbolic_length() {
+    use crate::radius::Radius;
+    use crate::sims::{libc, syscall};
+    use crate::value::{byte_values, vc};
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    let input = state.symbolic_value("input", 64);
+    let bytes = state.unpack(&input, 8);
+    state.fill_file(0, &bytes);
+    let length = state.set_file_length(0, "input_length");
+
+    // a full read followed by EOF means the input is exactly 4 bytes
+    let buf = state.memory_alloc(&vc(16));
+    let count = syscall::read(&mut state, &[vc(0), buf.clone(), vc(4)]);
+    state.assert(&count.eq(&vc(4)));
+    let c = libc::getchar(&mut state, &[]);
+    state.assert(&c.eq(&vc(-1i64 as u64)));
+
+    assert_eq!(state.minimize(&length), 4);
+    assert!(state.solver.is_sat());
+
+    // minimizing doesn't constrain the value
+    let other = state.symbolic_value("other", 8);
+    state.assert(&other.ugt(&vc(3)));
+    assert_eq!(state.minimize(&other), 4);
+    assert!(state.check(&other.eq(&vc(5))));
+
+    // a read with a symbolic length moves the position by the bytes read
+    state.filesystem.add_file("/tmp/length.txt", &byte_values("abcdef"));
+    let path = state.memory_alloc(&vc(16));
+    state.memory_write_string(path.as_u64().unwrap(), "/tmp/length.txt");
+    let fd = syscall::open(&mut state, &[path, vc(0), vc(0)]);
+    let size = state.symbolic_value("size", 64);
+    state.assert(&size.ult(&vc(4)));
+    let first = syscall::read(&mut state, &[fd.clone(), buf.clone(), size]);
+    let second = syscall::read(&mut state, &[fd, buf, vc(16)]);
+    assert_eq!(state.solver.evalcon_to_u64(&first.add(&second)), Some(6));
+
+    // fstat and SEEK_END give the symbolic length
+    state.filesystem.add_file("/tmp/sized.txt", &byte_values("abcdef"));
+    state.set_path_length("/tmp/sized.txt", "sized").unwrap();
+    let path = state.memory_alloc(&vc(16));
+    state.memory_write_string(path.as_u64().unwrap(), "/tmp/sized.txt");
+    let fd = syscall::open(&mut state, &[path, vc(0), vc(0)]);
+    let statbuf = state.memory_alloc(&vc(144));
+    assert_eq!(syscall::fstat(&mut state, &[fd.clone(), statbuf.clone()]), vc(0));
+    assert!(state.memory_read_value(&statbuf.add(&vc(48)), 8).is_symbolic());
+    let end = syscall::lseek(&mut state, &[fd.clone(), vc(0), vc(2)]);
+    assert!(end.is_symbolic());
+    assert!(state.check(&end.eq(&vc(2))));
+
+    // negative offsets move back but not before the start
+    let back = syscall::lseek(&mut state, &[fd.clone(), vc(-2i64 as u64), vc(1)]);
+    assert_eq!(back, vc(4));
+    let before = syscall::lseek(&mut state, &[fd, vc(-8i64 as u64), vc(1)]);
+    assert_eq!(before, vc(-1i64 as u64));
+}
+
+#[test]
+fn stdio_streams() {
+    use crate::radius::Radius;
+    use crate::sims::fs::FileMode;
+    use crate::sims::{libc, syscall};
+    use crate::value::{byte_values, vc};
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    state.filesystem.add_file("/tmp/in.txt", &byte_values("ab\ncd"));
+    let fd = syscall::open_path(&mut state, "/tmp/in.txt", FileMode::Read, false, false);
+    let stream = libc::new_file(&mut state, &fd, FileMode::Read);
+    let file = [stream.clone()];
+    assert_eq!(libc::fileno(&mut state, &file), fd);
+
+    // fgets stops after the newline
+    let buf = state.memory_alloc(&vc(16));
+    libc::fgets(&mut state, &[buf.clone(), vc(16), stream.clone()]);
+    assert_eq!(state.memory_read_cstring(buf.as_u64().unwrap()), "ab\n");
+
+    let c = libc::fgetc(&mut state, &file);
+    assert_eq!(c, vc('c' as u64));
+    libc::ungetc(&mut state, &[c, stream.clone()]);
+    assert_eq!(libc::fgetc(&mut state, &file), vc('c' as u64));
+
+    // getline allocates the line and hits the end of the file
+    let ptr = state.memory_alloc(&vc(16));
+    state.memory_write_value(&ptr, &vc(0), 8);
+    state.memory_write_value(&ptr.add(&vc(8)), &vc(0), 8);
+    let len = libc::getline(&mut state, &[ptr.clone(), ptr.add(&vc(8)), stream.clone()]);
+    assert_eq!(len, vc(1));
+    let line = state.memory_read_value(&ptr, 8).as_u64().unwrap();
+    assert_eq!(state.memory_read_cstring(line), "d");
+
+    assert_eq!(libc::feof(&mut state, &file), vc(1));
+    libc::clearerr(&mut state, &file);
+    assert_eq!(libc::feof(&mut state, &file), vc(0));
+
+    // fwrite returns the items written, none for a bad fd
+    let out = syscall::open_path(&mut state, "/tmp/out.txt", FileMode::Write, true, true);
+    let out = libc::new_file(&mut state, &out, FileMode::Write);
+    let items = libc::fwrite(&mut state, &[buf.clone(), vc(3), vc(2), out]);
+    assert_eq!(state.solver.evalcon_to_u64(&items), Some(2));
+    let bad = libc::new_file(&mut state, &vc(77), FileMode::Write);
+    let items = libc::fwrite(&mut state, &[buf, vc(3), vc(2), bad.clone()]);
+    assert_eq!(state.solver.evalcon_to_u64(&items), Some(0));
+    assert_ne!(libc::ferror(&mut state, &[bad]), vc(0));
+}
+
+#[test]
+fn stream_scanf() {
+    use crate::radius::Radius;
+    use crate::sims::fs::FileMode;
+    use crate::sims::{libc, syscall};
+    use crate::value::{byte_values, vc};
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    state.filesystem.add_file("/tmp/scan.txt", &byte_values("12 34\nabcdef\n"));
+    let fd = syscall::open_path(&mut state, "/tmp/scan.txt", FileMode::Read, false, false);
+    let stream = libc::new_file(&mut state, &fd, FileMode::Read);
+    let fmt = state.memory_alloc(&vc(16));
+    let num = state.memory_alloc(&vc(8));
+    let buf = state.memory_alloc(&vc(16));
+
+    // each fscanf only uses the bytes its format matched
+    state.memory_write_string(fmt.as_u64().unwrap(), "%d");
+    for expected in [12, 34] {
+        let items = libc::fscanf(&mut state, &[stream.clone(), fmt.clone(), num.clone()]);
+        assert_eq!(items, vc(1));
+        assert_eq!(state.memory_read_value(&num, 4), vc(expected));
+    }
+
+    // the width limits the string which is still terminated
+    state.memory_write_string(fmt.as_u64().unwrap(), "%3s");
+    let items = libc::fscanf(&mut state, &[stream.clone(), fmt, buf.clone()]);
+    assert_eq!(items, vc(1));
+    assert_eq!(state.memory_read_cstring(buf.as_u64().unwrap()), "abc");
+
+    assert_eq!(libc::fgetc(&mut state, std::slice::from_ref(&stream)), vc('d' as u64));
+    libc::fgets(&mut state, &[buf.clone(), vc(16), stream]);
+    assert_eq!(state.memory_read_cstring(buf.as_u64().unwrap()), "ef\n");
+}
+
+#[test]
+fn va_list_format() {
+    use crate::radius::Radius;
+    use crate::sims::libc;
+    use crate::value::vc;
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    let fmt = state.memory_alloc(&vc(16)).as_u64().unwrap();
+    let string = state.memory_alloc(&vc(16)).as_u64().unwrap();
+    state.memory_write_string(fmt, "%d %s %x");
+    state.memory_write_string(string, "hi");
+
+    // sysv va_list with two args left in the save area and one on the stack
+    let save = state.memory_alloc(&vc(64));
+    let overflow = state.memory_alloc(&vc(16));
+    state.memory_write_value(&save.add(&vc(32)), &vc(42), 8);
+    state.memory_write_value(&save.add(&vc(40)), &vc(string), 8);
+    state.memory_write_value(&overflow, &vc(0x7f), 8);
+
+    // each call gets its own copy like after a va_copy
+    let mut aps = vec![];
+    for _ in 0..2 {
+        let ap = state.memory_alloc(&vc(24));
+        state.memory_write_value(&ap, &vc(32), 4);
+        state.memory_write_value(&ap.add(&vc(4)), &vc(48), 4);
+        state.memory_write_value(&ap.add(&vc(8)), &overflow, 8);
+        state.memory_write_value(&ap.add(&vc(16)), &save, 8);
+        aps.push(ap);
+    }
+
+    // vsnprintf returns the full length and truncates the output
+    let buf = state.memory_alloc(&vc(16));
+    let len = libc::vsnprintf(&mut state, &[buf.clone(), vc(6), vc(fmt), aps.remove(0)]);
+    assert_eq!(len, vc(8));
+    assert_eq!(state.memory_read_cstring(buf.as_u64().unwrap()), "42 hi");
+
+    let len = libc::vsprintf(&mut state, &[buf.clone(), vc(fmt), aps.remove(0)]);
+    assert_eq!(len, vc(8));
+    assert_eq!(state.memory_read_cstring(buf.as_u64().unwrap()), "42 hi 7f");
+
+    // direct floats come from xmm0, the ints from the arg registers
+    state.memory_write_string(fmt, "%d %f %d");
+    state.registers.set("xmm0", vc(1.5f64.to_bits()));
+    libc::sprintf(&mut state, &[buf.clone(), vc(fmt), vc(7), vc(9)]);
+    assert_eq!(state.memory_read_cstring(buf.as_u64().unwrap()), "7 1.5e0 9");
+}
+
+#[test]
+fn libc_strings_time() {
+    use crate::radius::Radius;
+    use crate::sims::libc;
+    use crate::value::vc;
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    let string = state.memory_alloc(&vc(32));
+    let delim = state.memory_alloc(&vc(8));
+    state.memory_write_string(string.as_u64().unwrap(), ",,ab,c");
+    state.memory_write_string(delim.as_u64().unwrap(), ",");
+
+    assert_eq!(libc::strspn(&mut state, &[string.clone(), delim.clone()]), vc(2));
+    assert_eq!(libc::strcspn(&mut state, &[string.add(&vc(2)), delim.clone()]), vc(2));
+
+    let token = libc::strtok(&mut state, &[string.clone(), delim.clone()]);
+    assert_eq!(token, string.add(&vc(2)));
+    assert_eq!(state.memory_read_cstring(token.as_u64().unwrap()), "ab");
+    let token = libc::strtok(&mut state, &[vc(0), delim.clone()]);
+    assert_eq!(state.memory_read_cstring(token.as_u64().unwrap()), "c");
+    assert_eq!(libc::strtok(&mut state, &[vc(0), delim]), vc(0));
+
+    let other = state.memory_alloc(&vc(8));
+    state.memory_write_string(other.as_u64().unwrap(), "AB");
+    let args = [string.add(&vc(2)), other.clone()];
+    assert_eq!(libc::strcasecmp(&mut state, &args), vc(0));
+    state.memory_write_string(other.as_u64().unwrap(), "AC");
+    assert_ne!(libc::strcasecmp(&mut state, &args), vc(0));
+    let args = [string.add(&vc(2)), other, vc(1)];
+    assert_eq!(libc::strncasecmp(&mut state, &args), vc(0));
+
+    // 2021-03-04 05:06:07 was a thursday
+    let timep = state.memory_alloc(&vc(8));
+    state.memory_write_value(&timep, &vc(1614834367), 8);
+    let tm = libc::gmtime(&mut state, &[timep]);
+    let fields: Vec<u64> = (0..8)
+        .map(|i| state.memory_read_value(&tm.add(&vc(4 * i)), 4).as_u64().unwrap())
+        .collect();
+    assert_eq!(fields, vec![7, 6, 5, 4, 2, 121, 4, 62]);
+}
+
+#[test]
+fn setjmp_longjmp() {
+    use crate::radius::Radius;
+    use crate::sims::libc;
+    use crate::state::StateStatus;
+    use crate::value::vc;
+
+    let mut radius = Radius::new("../tests/hello");
+    let main = radius.r2api.get_address("main").unwrap();
+    let mut state = radius.call_state(main);
+
+    let sp = state.registers.get_with_alias("SP");
+    let ret_addr = state.memory_read_value(&sp, 8);
+    state.registers.set("rbx", vc(0x1337));
+    state.registers.set("r15", vc(0x4242));
+
+    let env = state.memory_alloc(&vc(200));
+    assert_eq!(libc::setjmp(&mut state, std::slice::from_ref(&env)), vc(0));
+    assert_eq!(state.memory_read_value(&env, 8), ret_addr);
+    assert_eq!(state.memory_read_value(&env.add(&vc(8)), 8), sp.add(&vc(8)));
+    assert_eq!(state.memory_read_value(&env.add(&vc(16)), 8), vc(0x1337));
+
+    // only the callee saved registers come back
+    state.registers.set("rbx", vc(0));
+    state.registers.set("r15", vc(0));
+    state.registers.set("rcx", vc(0x99));
+    assert_eq!(libc::longjmp(&mut state, &[env.clone(), vc(0)]), vc(1));
+    assert_eq!(state.registers.get_pc(), ret_addr);
+    assert_eq!(state.registers.get_with_alias("SP"), sp.add(&vc(8)));
+    assert_eq!(state.registers.get("rbx"), vc(0x1337));
+    assert_eq!(state.registers.get("r15"), vc(0x4242));
+    assert_eq!(state.registers.get("rcx"), vc(0x99));
+    assert_eq!(libc::longjmp(&mut state, &[env, vc(5)]), vc(5));
+
+    let unset = state.memory_alloc(&vc(200));
+    state.memory_write_value(&unset, &vc(0), 8);
+    libc::longjmp(&mut state, &[unset, vc(1)]);
+    assert_eq!(state.status, StateStatus::Inactive);
+}
+
+#[test]
+fn closure_hooks() {
+    use crate::radius::Radius;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut radius = Radius::new("../tests/hello");
+    let hits = Rc::new(Cell::new(0));
+    let counter = hits.clone();
+    radius.hook(0x00001149, move |_state| {
+        counter.set(counter.get() + 1);
+        true
+    });
+
+    let state = radius.call_state(0x00001149);
+    radius.run_until(state, 0x1163, &[]).unwrap();
+    assert_eq!(hits.get(), 1);
+}
+
+#[test]
+fn signature_symbols() {
+    use crate::sims::signature_symbol;
+
+    assert_eq!(signature_symbol("sign.bytes.sym.strlen_0"), "strlen");
+    assert_eq!(signature_symbol("sign.graph.memcpy_12"), "memcpy");
+    assert_eq!(signature_symbol("flirt.__libc_malloc"), "__libc_malloc");
+    assert_eq!(signature_symbol("flirt.atoi_l"), "atoi_l");
+}
+
+#[test]
+fn event_hooks() {
+    use crate::radius::Radius;
+    use crate::state::{Event, EventContext, EventTrigger};
+    use crate::value::vc;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut radius = Radius::new("../tests/hello");
+    let writes = Rc::new(RefCell::new(vec![]));
+    let collector = writes.clone();
+    radius.hook_event(Event::Write(EventTrigger::After), move |_state, context| {
+        if let EventContext::WriteContext(addr, _len) = context {
+            collector.borrow_mut().push(addr.clone());
+        }
+    });
+
+    let registers = Rc::new(RefCell::new(0));
+    let counter = registers.clone();
+    radius.hook_event(Event::All(EventTrigger::Before), move |_state, context| {
+        if let EventContext::RegisterContext(_name, _value) = context {
+            *counter.borrow_mut() += 1;
+        }
+    });
+
+    let mut state = radius.call_state(0x00001149);
+    let addr = state.memory_alloc(&vc(8));
+    state.memory_write_value(&addr, &vc(0x41), 1);
+    assert_eq!(writes.borrow()[0], addr);
+
+    radius.run_until(state, 0x1163, &[]).unwrap();
+    assert!(*registers.borrow() > 0);
+}
+
+#[test]
+fn watchpoints() {
+    use crate::radius::Radius;
+    use crate::state::{Event, EventTrigger};
+    use crate::value::vc;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut radius = Radius::new("../tests/hello");
+    let accesses = Rc::new(RefCell::new(vec![]));
+    let collector = accesses.clone();
+    radius.watch(0x100000, 4, "rw", move |_state, access| {
+        collector.borrow_mut().push(access.clone());
+    });
+
+    let writes = Rc::new(RefCell::new(0));
+    let counter = writes.clone();
+    radius.watch_register("rax", move |_state, access| {
+        if access.perm == 'w' {
+            *counter.borrow_mut() += 1;
+        }
+    });
+
+    let mut state = radius.call_state(0x00001149);
+    // only the memory and register events are hooked
+    assert!(!state.hooks.contains_key(&Event::All(EventTrigger::After)));
+    assert!(!state.hooks.contains_key(&Event::Syscall(EventTrigger::After)));
+    state.memory_write_value(&vc(0x100002), &vc(0x41), 1);
+    state.memory_read_value(&vc(0x100004), 4); // outside the range
+    state.memory_read_value(&vc(0x100000), 4);
+
+    let accesses = accesses.borrow();
+    assert_eq!(accesses.len(), 2);
+    assert_eq!((accesses[0].perm, accesses[1].perm), ('w', 'r'));
+    assert_eq!(accesses[0].new, accesses[1].old);
+
+    radius.run_until(state, 0x1163, &[]).unwrap();
+    assert!(*writes.borrow() > 0);
+}
+
+#[test]
+fn trace_replay() {
+    use crate::radius::{Radius, RadiusOption};
+    use crate::trace::Trace;
+    use crate::value::Value;
+
+    let options = [RadiusOption::Trace(true)];
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    let mut state = radius.call_state(0x5fa);
+    let bv = state.bv("num", 32);
+    state.registers.set("edi", Value::Symbolic(bv, 0));
+
+    let end = radius.run_until(state, 0x60b, &[0x612]).unwrap();
+    let trace = end.trace.unwrap();
+    assert!(!trace.branches().is_empty());
+    // sequential instructions are recorded as one block
+    assert!((trace.entries().len() as u64) < trace.steps());
+
+    let file = format!("radius_trace_replay_{}.jsonl", std::process::id());
+    let path = std::env::temp_dir().join(file);
+    let path = path.to_str().unwrap();
+    trace.write(path).unwrap();
+    let read = Trace::read(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(read, trace);
+
+    let mut state = radius.call_state(0x5fa);
+    let bv = state.bv("num", 32);
+    state.registers.set("edi", Value::Symbolic(bv.clone(), 0));
+    let mut replayed = radius.replay(state, &read).unwrap();
+
+    assert_eq!(replayed.registers.get_pc().as_u64(), Some(0x60b));
+    assert_eq!(replayed.trace.as_ref().unwrap().divergence(&trace), None);
+    assert_eq!(replayed.evaluate(&bv).unwrap().as_u64(), Some(2));
+}
+
+#[test]
+fn replay_loop() {
+    use crate::radius::{Radius, RadiusOption};
+    use crate::value::vc;
+
+    // loop back to the compare at 0x605 from the jne target, the compared
+    // value becomes symbolic on the third time through
+    let options = [RadiusOption::Trace(true)];
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    radius.hook(0x612, |state| {
+        let count = state.registers.get("r8").as_u64().unwrap() + 1;
+        state.registers.set("r8", vc(count));
+        if count == 2 {
+            let num = state.symbolic_value("num", 32);
+            let addr = state.registers.get("rbp").sub(&vc(4));
+            state.memory_write_value(&addr, &num, 4);
+        }
+        if count < 3 {
+            state.registers.set_pc(vc(0x605));
+        }
+        count >= 3
+    });
+
+    let mut state = radius.call_state(0x5fa);
+    state.registers.set("edi", vc(5));
+    state.registers.set("r8", vc(0));
+    let end = radius.run_until(state, 0x60b, &[]).unwrap();
+    let trace = end.trace.unwrap();
+    assert_eq!(trace.branches(), vec![(0x609, 0x60b)]);
+
+    // the concrete jne before the symbolic one doesn't take the branch
+    let mut state = radius.call_state(0x5fa);
+    state.registers.set("edi", vc(5));
+    state.registers.set("r8", vc(0));
+    let replayed = radius.replay(state, &trace).unwrap();
+    assert_eq!(replayed.registers.get_pc().as_u64(), Some(0x60b));
+    assert_eq!(replayed.trace.as_ref().unwrap().divergence(&trace), None);
+}
+
+#[test]
+fn coverage() {
+    use crate::radius::{Radius, RadiusOption};
+    use crate::value::Value;
+
+    let options = [RadiusOption::Coverage(true)];
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    let mut state = radius.call_state(0x5fa);
+    let bv = state.bv("num", 32);
+    state.registers.set("edi", Value::Symbolic(bv, 0));
+    radius.run_until(state, 0x60b, &[0x612]).unwrap();
+
+    let coverage = radius.get_coverage();
+    assert!(coverage.instructions.contains_key(&0x5fa));
+    assert!(coverage.blocks.contains_key(&0x5fa));
+    assert!(coverage.addresses().starts_with("0x5fa\n"));
+
+    let drcov = coverage.drcov();
+    assert!(drcov.starts_with(b"DRCOV VERSION: 2\n"));
+    assert!(!coverage.modules.is_empty());
+
+    // simple is a PIE at base 0, its blocks are in the first module
+    assert_eq!(coverage.modules[0].base, 0);
+    assert_eq!(coverage.module_index(0x5fa), Some(0));
+    let table = String::from_utf8_lossy(&drcov).to_string();
+    assert!(table.contains("BB Table: ") && !table.contains("BB Table: 0 bbs"));
+}
+
+#[test]
+fn testgen() {
+    use crate::radius::Radius;
+    use crate::sims::syscall;
+    use crate::testgen::{InputKind, TestGenerator};
+    use crate::value::vc;
+    use std::fs;
+
+    let mut radius = Radius::new("../tests/simple");
+    radius.breakpoint(0x60b);
+    radius.breakpoint(0x612);
+    let mut state = radius.call_state(0x5fa);
+
+    // the argument is read from stdin
+    let input = state.symbolic_value("stdin", 32);
+    let bytes = state.unpack(&input, 4);
+    state.fill_file(0, &bytes);
+    let buf = state.memory_alloc(&vc(4));
+    syscall::read(&mut state, &[vc(0), buf.clone(), vc(4)]);
+    let num = state.memory_read_value(&buf, 4);
+    state.registers.set("edi", num);
+
+    let dir = std::env::temp_dir().join(format!("radius_testgen_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let mut generator = TestGenerator::new(dir.to_str().unwrap(), vec![]);
+    generator.add_input(InputKind::Fd(0), input, 4);
+    generator.max_states = 1;
+    let count = radius.generate(state, &mut generator).unwrap();
+
+    // both sides of the branch are written even though one isn't explored
+    assert!(count >= 2);
+    assert_eq!(generator.dropped, 1);
+    assert_eq!(generator.crash_cases, 0);
+    assert!(dir.join("cases/id_000001/stdin").exists());
+
+    let seeds: Vec<Vec<u8>> = fs::read_dir(dir.join("seeds"))
+        .unwrap()
+        .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+        .collect();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(seeds.contains(&vec![2, 0, 0, 0]));
+}
+
+#[test]
+fn concolic() {
+    use crate::concolic::Concolic;
+    use crate::radius::Radius;
+    use crate::value::{vc, Value};
+
+    let mut radius = Radius::new("../tests/simple");
+    radius.breakpoint(0x60b);
+    radius.breakpoint(0x612);
+    let mut state = radius.call_state(0x5fa);
+    let num = Value::Symbolic(state.bv("num", 32), 0);
+    state.registers.set("edi", num.clone());
+
+    let mut concolic = Concolic::new(vec![num]);
+    concolic.add_seed(&[vec![0, 0, 0, 0]]);
+    let count = radius.concolic(state, &mut concolic);
+
+    assert!(count >= 1);
+    assert!(concolic.runs >= 2);
+    assert!(concolic.generated.contains(&vec![vec![2, 0, 0, 0]]));
+    assert!(concolic.edges.len() >= 2);
+
+    // assertions made during a run are kept when its branches are negated
+    let mut radius = Radius::new("../tests/simple");
+    radius.breakpoint(0x60b);
+    radius.breakpoint(0x612);
+    let mut state = radius.call_state(0x5fa);
+    let num = Value::Symbolic(state.bv("num", 32), 0);
+    state.registers.set("edi", num.clone());
+    let not_two = !num.eq(&vc(2));
+    radius.hook(0x5fa, move |state| {
+        state.assert(&not_two);
+        true
+    });
+
+    let mut concolic = Concolic::new(vec![num.clone()]);
+    concolic.add_seed(&[vec![0, 0, 0, 0]]);
+    radius.concolic(state.clone(), &mut concolic);
+    assert!(!concolic.generated.contains(&vec![vec![2, 0, 0, 0]]));
+
+    // no branch is reached in a single step
+    let mut concolic = Concolic::new(vec![num]);
+    concolic.add_seed(&[vec![0, 0, 0, 0]]);
+    concolic.max_steps = 1;
+    assert_eq!(radius.concolic(state, &mut concolic), 0);
+}
+
+#[test]
+fn fast_concrete() {
+    use crate::radius::{Radius, RadiusOption};
+    use crate::value::Value;
+
+    let options = [RadiusOption::FastConcrete(true)];
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    let mut state = radius.call_state(0x5fa);
+    let bv = state.bv("num", 32);
+    state.registers.set("edi", Value::Symbolic(bv.clone(), 0));
+
+    let mut end = radius.run_until(state, 0x60b, &[0x612]).unwrap();
+    assert_eq!(end.evaluate(&bv).unwrap().as_u64(), Some(2));
+    assert!(radius.processor.instructions[&0x5fa].fast.is_some());
+}
+
+#[test]
+fn fast_concrete_fallback() {
+    use crate::fast;
+    use crate::radius::Radius;
+    use crate::value::{vc, Value};
+
+    let mut radius = Radius::new("../tests/simple");
+    let mut state = radius.call_state(0x5fa);
+    let sym = Value::Symbolic(state.bv("sym", 64), 0);
+    state.memory_write_value(&vc(0x100000), &vc(0x1111), 8);
+    state.memory_write_value(&vc(0x100008), &sym, 8);
+    state.registers.set("rax", vc(0x2222));
+    state.registers.set("rbx", vc(0x3333));
+
+    // rax and memory are written before the symbolic read
+    let esil = "0x41,rax,=,0x42,0x100000,=[8],0x100008,[8],rbx,=";
+    let words = radius.processor.tokenize(&mut state, esil);
+    let fast_words = fast::compile(&words).unwrap();
+    assert!(!fast::execute(&mut state, &fast_words));
+    assert_eq!(state.registers.get("rax"), vc(0x2222));
+    assert_eq!(state.memory_read_value(&vc(0x100000), 8), vc(0x1111));
+    assert_eq!(state.registers.get("rbx"), vc(0x3333));
+
+    // parse then runs it from the restored state
+    radius.processor.parse(&mut state, &words);
+    assert_eq!(state.registers.get("rax"), vc(0x41));
+    assert_eq!(state.memory_read_value(&vc(0x100000), 8), vc(0x42));
+    assert!(state.registers.get("rbx").is_symbolic());
+}
+
+#[test]
+fn lift_ir() {
+    use crate::radius::{Radius, RadiusOption};
+    use crate::value::Value;
+
+    let options = [RadiusOption::Lift(true)];
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    let mut state = radius.call_state(0x5fa);
+    let bv = state.bv("num", 32);
+    state.registers.set("edi", Value::Symbolic(bv.clone(), 0));
+
+    let mut end = radius.run_until(state, 0x60b, &[0x612]).unwrap();
+    assert_eq!(end.evaluate(&bv).unwrap().as_u64(), Some(2));
+    assert!(radius.processor.instructions[&0x5fa].ir.is_some());
+}
+
+#[test]
+fn ir_folding() {
+    use crate::ir::{self, Inst, Operand};
+    use crate::radius::Radius;
+
+    let mut radius = Radius::new("../tests/simple");
+    let mut state = radius.call_state(0x5fa);
+    let rax = state.registers.get_register("rax").unwrap().index;
+    let rbx = state.registers.get_register("rbx").unwrap().index;
+    let mut lift = |esil: &str| {
+        let words = radius.processor.tokenize(&mut state, esil);
+        ir::lift(&words, 0x1000, 64, &state.registers)
+    };
+
+    // constants are folded, also through registers written with one
+    let write = |reg, val| Inst::Write { reg, value: Operand::Const(val), esil: true };
+    assert_eq!(lift("1,2,+,rax,=").unwrap().insts, vec![write(rax, 3)]);
+    let insts = lift("5,rax,=,rax,1,+,rbx,=").unwrap().insts;
+    assert_eq!(insts, vec![write(rax, 5), write(rbx, 6)]);
+
+    // operations whose results are never used are removed
+    assert!(lift("rcx,rcx,+,rcx,+").unwrap().insts.is_empty());
+    assert_eq!(lift("$$,rax,=").unwrap().insts, vec![write(rax, 0x1000)]);
+}
+
+#[test]
+fn ir_conditionals() {
+    use crate::ir::{self, Inst, Operand};
+    use crate::radius::Radius;
+
+    let mut radius = Radius::new("../tests/simple");
+    let mut state = radius.call_state(0x5fa);
+    let mut lift = |esil: &str| {
+        let words = radius.processor.tokenize(&mut state, esil);
+        ir::lift(&words, 0x1000, 64, &state.registers)
+    };
+
+    // a register condition is lifted to a skip over the clause
+    let mut lifted = lift("zf,?{,1,rax,=,}").unwrap();
+    lifted.allocate();
+    let insts = lifted.insts.as_slice();
+    assert!(matches!(insts, [Inst::Read { .. }, _, Inst::Write { .. }, Inst::EndIf]));
+    assert_eq!(insts[1], Inst::If { cond: Operand::Temp(0), next: 3 });
+
+    // constant conditions keep only the clause that runs
+    assert!(lift("0,?{,1,rax,=,}").unwrap().insts.is_empty());
+    let lifted = lift("1,?{,1,rax,=,}").unwrap();
+    assert!(matches!(lifted.insts.as_slice(), [Inst::Write { .. }]));
+    let lifted = lift("zf,?{,1,rax,=,}{,2,rax,=,}").unwrap();
+    assert!(lifted.insts.iter().any(|inst| matches!(inst, Inst::Else { .. })));
+
+    // nested conditionals are parsed instead
+    assert!(lift("zf,?{,cf,?{,1,rax,=,},}").is_none());
+}
+
+#[test]
+fn ir_eliminate() {
+    use crate::ir;
+    use crate::radius::Radius;
+
+    let mut radius = Radius::new("../tests/simple");
+    let mut state = radius.call_state(0x5fa);
+    let registers = state.registers.clone();
+    let mut lift = |esil: &str| {
+        let words = radius.processor.tokenize(&mut state, esil);
+        ir::lift(&words, 0x1000, 64, &state.registers)
+    };
+
+    // a flag overwritten by the next instruction is removed
+    let mut lifted = vec![lift("1,zf,:="), lift("0,zf,:=")];
+    ir::eliminate(&mut lifted, &[true], &registers);
+    assert!(lifted[0].as_ref().unwrap().insts.is_empty());
+
+    // unless the next instruction reads it before writing it
+    let mut lifted = vec![lift("1,zf,:="), lift("zf,rax,=,0,zf,:=")];
+    ir::eliminate(&mut lifted, &[true], &registers);
+    assert_eq!(lifted[0].as_ref().unwrap().insts.len(), 1);
+
+    // or it does not always run right after
+    let mut lifted = vec![lift("1,zf,:="), lift("0,zf,:=")];
+    ir::eliminate(&mut lifted, &[false], &registers);
+    assert_eq!(lifted[0].as_ref().unwrap().insts.len(), 1);
+}
+
+#[test]
+fn ir_differential() {
+    use crate::ir;
+    use crate::radius::{Radius, RadiusOption};
+    use crate::value::vc;
+
+    let options = [RadiusOption::Lift(true)];
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    for num in [2, 3] {
+        let mut state = radius.call_state(0x5fa);
+        state.registers.set("edi", vc(num));
+        let mut lifted = state.clone();
+
+        // the block up to the jne, parsed and executed as IR
+        for pc in [0x5fa, 0x5fb, 0x5fe, 0x601, 0x605, 0x609] {
+            radius.processor.fetch_instruction(&mut state, pc);
+            let entry = &radius.processor.instructions[&pc];
+            let next = vc(pc + entry.instruction.size);
+            state.registers.set_pc(next.clone());
+            lifted.registers.set_pc(next);
+
+            radius.processor.parse(&mut state, &entry.tokens);
+            assert!(ir::execute(&mut lifted, entry.ir.as_ref().unwrap()));
+        }
+
+        assert_eq!(lifted.registers.values, state.registers.values);
+        let local = state.registers.get("rbp").sub(&vc(4));
+        assert_eq!(lifted.memory_read_value(&local, 4), vc(num));
+        assert_eq!(state.memory_read_value(&local, 4), vc(num));
+    }
+}
+
+#[test]
+fn disk_cache() {
+    use crate::cache::DiskCache;
+    use crate::radius::{Radius, RadiusOption};
+    use crate::value::Value;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("radius_cache_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let run = |radius: &mut Radius| {
+        let mut state = radius.call_state(0x5fa);
+        let bv = state.bv("num", 32);
+        state.registers.set("edi", Value::Symbolic(bv.clone(), 0));
+
+        let mut end = radius.run_until(state, 0x60b, &[0x612]).unwrap();
+        assert_eq!(end.evaluate(&bv).unwrap().as_u64(), Some(2));
+    };
+
+    // the cache is only used by library users that ask for it
+    let cache_dir = RadiusOption::CacheDir(dir.to_str().unwrap().to_owned());
+    let options = [cache_dir.clone()];
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    run(&mut radius);
+    radius.close();
+    assert!(!dir.exists());
+
+    let options = [RadiusOption::Cache(true), cache_dir];
+    for _ in 0..2 {
+        let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+        run(&mut radius);
+        radius.close();
+    }
+
+    // one file for the binary, with the disassembly and tokens of both runs
+    let files: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let path = files[0].as_ref().unwrap().path();
+    let disk: DiskCache = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+    assert!(disk.commands.keys().any(|cmd| cmd.starts_with("pdj")));
+    assert!(!disk.tokens.is_empty());
+
+    // changing an r2 option starts another cache
+    let mut radius = Radius::new_with_options(Some("../tests/simple"), &options);
+    radius.set_option("asm.pseudo", "true");
+    run(&mut radius);
+    radius.close();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn switch_jump() {
+    use crate::radius::Radius;
+    use crate::value::{vc, Value};
+
+    // jmp rax at 0x544, rax loaded from a table with repeated targets
+    let mut radius = Radius::new("../tests/simple");
+    let mut state = radius.call_state(0x544);
+    let table = [0x5fa, 0x60b, 0x5fa, 0x612, 0x60b, 0x620];
+    for (i, target) in table.iter().enumerate() {
+        state.memory_write_value(&vc(0x100000 + 8 * i as u64), &vc(*target), 8);
+    }
+    let index = Value::Symbolic(state.bv("index", 64), 0);
+    state.assert(&index.ult(&vc(table.len() as u64)));
+    let rax = state.memory_read_value(&(vc(0x100000) + (index << vc(3))), 8);
+    state.registers.set("rax", rax);
+
+    // 0x620 is not a case so it is still found by evaluating the target
+    radius.processor.switches.insert(0x544, vec![0x5fa, 0x60b, 0x612]);
+    let mut forks = radius.processor.step(&mut state);
+    forks.push(state);
+    let mut pcs: Vec<u64> = forks
+        .iter()
+        .map(|s| s.registers.get_pc().as_u64().unwrap())
+        .collect();
+    pcs.sort_unstable();
+    assert_eq!(pcs, vec![0x5fa, 0x60b, 0x612, 0x620]);
+
+    // conditional jumps are not looked up as switches
+    let mut state = radius.call_state(0x5fa);
+    let num = state.symbolic_value("num", 32);
+    state.registers.set("edi", num);
+    radius.run_until(state, 0x60b, &[0x612]).unwrap();
+    assert!(!radius.processor.switches.contains_key(&0x609));
+}
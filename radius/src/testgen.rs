@@ -0,0 +1,178 @@
+use crate::state::State;
+use crate::value::Value;
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where an input is passed to the program
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputKind {
+    /// The argument at this index, including argv\[0\]
+    Argv(usize),
+    /// The environment variable at this index
+    Env(usize),
+    /// Data read from this file descriptor, 0 is stdin
+    Fd(usize),
+    /// Contents of the file at this path
+    File(String),
+}
+
+/// A program input whose solution is written to each test case
+#[derive(Debug, Clone)]
+pub struct Input {
+    pub kind: InputKind,
+    pub value: Value,
+    /// Length in bytes
+    pub length: usize,
+}
+
+/// Writes a test case for every new branch edge found by `Processor::generate`
+///
+/// Each case is a directory `cases/id_NNNNNN` containing `argv` and `env`
+/// (NUL separated), `stdin`, `fdN` for other descriptors and `files/<path>`.
+/// Cases for states that crash go in `crashes/` instead. The raw bytes of the
+/// main input (stdin, else the first file, else the first symbolic input) are
+/// also written to `seeds/id_NNNNNN` (or `input` for crashes) so the seeds
+/// directory can be used as an AFL++ or libFuzzer corpus
+#[derive(Debug, Clone)]
+pub struct TestGenerator {
+    pub dir: PathBuf,
+    pub inputs: Vec<Input>,
+    pub edges: HashSet<(u64, u64)>, // (pc, target) edges already covered
+    pub crashes: HashSet<u64>,      // crash addresses already written
+    pub solutions: HashSet<Vec<Vec<u8>>>,
+    pub cases: usize,
+    pub crash_cases: usize,
+    pub max_states: usize, // maximum number of states to keep at a time
+    pub dropped: usize,    // states not explored because of max_states
+}
+
+impl TestGenerator {
+    pub fn new(dir: &str, inputs: Vec<Input>) -> Self {
+        TestGenerator {
+            dir: Path::new(dir).to_owned(),
+            inputs,
+            edges: HashSet::new(),
+            crashes: HashSet::new(),
+            solutions: HashSet::new(),
+            cases: 0,
+            crash_cases: 0,
+            max_states: 256,
+            dropped: 0,
+        }
+    }
+
+    /// Add an input to include in every test case
+    pub fn add_input(&mut self, kind: InputKind, value: Value, length: usize) {
+        self.inputs.push(Input {
+            kind,
+            value,
+            length,
+        });
+    }
+
+    /// Total number of test cases written
+    pub fn count(&self) -> usize {
+        self.cases + self.crash_cases
+    }
+
+    /// Solve the inputs for `state` and write them as a test case, returns
+    /// false if the state is unsat or the solution was already written
+    pub fn write_case(&mut self, state: &mut State, crash: bool) -> io::Result<bool> {
+        let mut solution = vec![];
+        for input in &self.inputs {
+            if let Some(bytes) = input_bytes(state, input) {
+                solution.push(bytes);
+            } else {
+                return Ok(false);
+            }
+        }
+
+        if !crash && self.solutions.contains(&solution) {
+            return Ok(false);
+        }
+
+        let (kind, id) = if crash {
+            self.crash_cases += 1;
+            ("crashes", self.crash_cases)
+        } else {
+            self.cases += 1;
+            ("cases", self.cases)
+        };
+
+        let name = format!("id_{:06}", id);
+        let case = self.dir.join(kind).join(&name);
+        fs::create_dir_all(&case)?;
+
+        let mut argv = vec![];
+        let mut env = vec![];
+        for (input, bytes) in self.inputs.iter().zip(&solution) {
+            match &input.kind {
+                InputKind::Argv(_) => {
+                    argv.extend(bytes);
+                    argv.push(0);
+                }
+                InputKind::Env(_) => {
+                    env.extend(bytes);
+                    env.push(0);
+                }
+                InputKind::Fd(0) => fs::write(case.join("stdin"), bytes)?,
+                InputKind::Fd(fd) => fs::write(case.join(format!("fd{}", fd)), bytes)?,
+                InputKind::File(path) => {
+                    let file = case.join("files").join(path.trim_start_matches('/'));
+                    if let Some(parent) = file.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(file, bytes)?;
+                }
+            }
+        }
+
+        if !argv.is_empty() {
+            fs::write(case.join("argv"), argv)?;
+        }
+        if !env.is_empty() {
+            fs::write(case.join("env"), env)?;
+        }
+
+        // crashing seeds stay with their case so they dont end up in the corpus
+        if let Some(index) = self.seed_index() {
+            if crash {
+                fs::write(case.join("input"), &solution[index])?;
+            } else {
+                let seeds = self.dir.join("seeds");
+                fs::create_dir_all(&seeds)?;
+                fs::write(seeds.join(&name), &solution[index])?;
+            }
+        }
+
+        self.solutions.insert(solution);
+        Ok(true)
+    }
+
+    // the input fuzzers should mutate, stdin then files then anything symbolic
+    fn seed_index(&self) -> Option<usize> {
+        let inputs = &self.inputs;
+        inputs
+            .iter()
+            .position(|i| i.kind == InputKind::Fd(0))
+            .or_else(|| {
+                inputs
+                    .iter()
+                    .position(|i| matches!(i.kind, InputKind::File(_)) && i.value.is_symbolic())
+            })
+            .or_else(|| inputs.iter().position(|i| i.value.is_symbolic()))
+    }
+}
+
+// evaluate the bytes of an input in the solver of `state`
+fn input_bytes(state: &mut State, input: &Input) -> Option<Vec<u8>> {
+    if input.length == 0 {
+        return Some(vec![]);
+    }
+    let value = state.translate_value(&input.value);
+    let bv = state.solver.to_bv(&value, 8 * input.length as u32);
+    state.evaluate_bytes_bv(&bv)
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::rc::Rc;
+
+/// A single recorded event in the execution of a state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TraceEntry {
+    /// `count` instructions were executed in sequence starting at `pc`
+    Block { pc: u64, count: u64 },
+    /// The state took `target` out of several possible next PCs
+    Branch {
+        pc: u64,
+        target: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        condition: Option<String>,
+    },
+    /// A sim was called at `pc`, the return value if it is concrete
+    Sim {
+        pc: u64,
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ret: Option<u64>,
+    },
+    /// A syscall or trap was executed at `pc`
+    Syscall {
+        pc: u64,
+        num: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ret: Option<u64>,
+    },
+}
+
+/// The recorded execution of a state, stored as JSON lines. The entries
+/// recorded before a fork are kept in a parent shared by every state from it
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    parent: Option<Rc<Trace>>,
+    entries: Vec<TraceEntry>,
+    // the pc following the last block, where the block would continue
+    next: u64,
+}
+
+impl PartialEq for Trace {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries() == other.entries()
+    }
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace::default()
+    }
+
+    #[inline]
+    pub fn push(&mut self, entry: TraceEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Record the instruction of `size` at `pc`, extending the last
+    /// block if the instruction directly follows it
+    #[inline]
+    pub fn step(&mut self, pc: u64, size: u64) {
+        match self.entries.last_mut() {
+            Some(TraceEntry::Block { count, .. }) if self.next == pc => *count += 1,
+            _ => self.entries.push(TraceEntry::Block { pc, count: 1 }),
+        }
+        self.next = pc + size;
+    }
+
+    /// Move the entries into a shared parent before the state is forked,
+    /// so cloning the trace does not copy them
+    pub fn split(&mut self) {
+        if !self.entries.is_empty() {
+            let parent = Trace {
+                parent: self.parent.take(),
+                entries: std::mem::take(&mut self.entries),
+                next: self.next,
+            };
+            self.parent = Some(Rc::new(parent));
+        }
+    }
+
+    /// Every recorded entry, including the ones shared with other states
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        let mut traces = vec![self];
+        while let Some(parent) = &traces[traces.len() - 1].parent {
+            traces.push(parent);
+        }
+        traces.iter().rev().flat_map(|t| t.entries.iter().cloned()).collect()
+    }
+
+    /// The number of executed instructions
+    pub fn steps(&self) -> u64 {
+        self.entries()
+            .iter()
+            .map(|e| match e {
+                TraceEntry::Block { count, .. } => *count,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// The branch decisions as (pc, target) pairs in order
+    pub fn branches(&self) -> Vec<(u64, u64)> {
+        self.entries()
+            .iter()
+            .filter_map(|e| match e {
+                TraceEntry::Branch { pc, target, .. } => Some((*pc, *target)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Index of the first entry that differs between two traces,
+    /// branch conditions are not compared as they are solver specific
+    pub fn divergence(&self, other: &Trace) -> Option<usize> {
+        let (entries, other_entries) = (self.entries(), other.entries());
+        let index = entries
+            .iter()
+            .zip(&other_entries)
+            .position(|(a, b)| match (a, b) {
+                (
+                    TraceEntry::Branch { pc, target, .. },
+                    TraceEntry::Branch {
+                        pc: other_pc,
+                        target: other_target,
+                        ..
+                    },
+                ) => pc != other_pc || target != other_target,
+                _ => a != b,
+            });
+
+        if index.is_none() && entries.len() != other_entries.len() {
+            Some(entries.len().min(other_entries.len()))
+        } else {
+            index
+        }
+    }
+
+    /// Write the trace to `path` with one JSON entry per line
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        for entry in &self.entries() {
+            serde_json::to_writer(&mut file, entry)?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()
+    }
+
+    /// Read a trace written by `Trace::write`
+    pub fn read(path: &str) -> io::Result<Trace> {
+        let file = BufReader::new(fs::File::open(path)?);
+        let mut trace = Trace::new();
+        for line in file.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                trace.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(trace)
+    }
+}